@@ -0,0 +1,127 @@
+//! Where uploaded files are kept. Their metadata is in `storage`.
+
+use async_std::fs;
+use async_trait::async_trait;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[async_trait]
+pub trait BlobStore: fmt::Debug + Send + Sync + 'static {
+    /// Replaces the blob if `key` is already taken.
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()>;
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Does nothing if there's no blob for `key`.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// One file per blob, in `directory`. Keys are used as file names, so they have to be safe ones.
+#[derive(Debug)]
+pub struct LocalBlobStore {
+    directory: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        debug_assert!(
+            key.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'),
+            "unsafe blob key {:?}",
+            key
+        );
+        self.directory.join(key)
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()> {
+        fs::create_dir_all(&self.directory).await?;
+
+        // Written to a temporary file first so readers never see half a blob
+        let path = self.path(key);
+        let tmp_path = self.path(&format!("{}.tmp", key));
+        fs::write(&tmp_path, bytes).await?;
+        fs::rename(&tmp_path, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct InMemoryBlobStore {
+    blobs: std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl BlobStore for InMemoryBlobStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()> {
+        self.blobs.lock().unwrap().insert(key.to_string(), bytes);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.blobs.lock().unwrap().get(key).cloned())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.blobs.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    async fn put_and_get(store: &dyn BlobStore) {
+        assert_eq!(store.get("a").await.unwrap(), None);
+
+        store.put("a", b"one".to_vec()).await.unwrap();
+        store.put("b", b"two".to_vec()).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), Some(b"one".to_vec()));
+        assert_eq!(store.get("b").await.unwrap(), Some(b"two".to_vec()));
+
+        store.put("a", b"three".to_vec()).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), Some(b"three".to_vec()));
+
+        store.delete("a").await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), None);
+        assert_eq!(store.get("b").await.unwrap(), Some(b"two".to_vec()));
+        store.delete("a").await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn in_memory() {
+        put_and_get(&InMemoryBlobStore::default()).await;
+    }
+
+    #[async_std::test]
+    async fn local() {
+        let directory = std::env::temp_dir().join(format!("witter-blobs-{}", uuid::Uuid::new_v4()));
+        put_and_get(&LocalBlobStore::new(directory.clone())).await;
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
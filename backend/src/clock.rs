@@ -1,37 +1,66 @@
-use async_std::sync::Mutex;
 use chrono::prelude::*;
-use lazy_static::lazy_static;
+use std::fmt;
 
 #[cfg(test)]
-use std::future::Future;
+use chrono::Duration;
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+/// Where handlers get the current time from. Stored in `State` so tests can control it.
+pub trait Clock: fmt::Debug + Send + Sync + 'static {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always returns the same time.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
 
 #[cfg(test)]
-pub async fn freeze_time<T, F, Fut>(time: DateTime<Utc>, mut f: F) -> Fut::Output
-where
-    F: FnMut() -> Fut,
-    Fut: Future,
-{
-    *FROZEN_TIME.lock().await = Some(time);
-    let result = f().await;
-    *FROZEN_TIME.lock().await = None;
-    result
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
 }
 
-lazy_static! {
-    static ref FROZEN_TIME: Mutex<Option<DateTime<Utc>>> = Mutex::new(None);
+/// Only moves when told to. Clones share the same time, so a test can keep one and hand another
+/// to the server.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    time: Arc<Mutex<DateTime<Utc>>>,
 }
 
-#[cfg(not(test))]
-pub async fn current_time() -> DateTime<Utc> {
-    Utc::now()
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(time: DateTime<Utc>) -> Self {
+        Self {
+            time: Arc::new(Mutex::new(time)),
+        }
+    }
+
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.time.lock().unwrap() += duration;
+    }
 }
 
 #[cfg(test)]
-pub async fn current_time() -> DateTime<Utc> {
-    if let Some(time) = *FROZEN_TIME.lock().await {
-        time
-    } else {
-        Utc::now()
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.time.lock().unwrap()
     }
 }
 
@@ -40,15 +69,31 @@ mod test {
     #[allow(unused_imports)]
     use super::*;
 
-    #[async_std::test]
-    async fn freezing_time() {
+    #[test]
+    fn fixed_clock() {
+        let time = Utc.ymd(1970, 1, 1).and_hms(0, 1, 1);
+        let clock = FixedClock(time);
+        assert_eq!(clock.now(), time);
+        assert_eq!(clock.now(), time);
+    }
+
+    #[test]
+    fn manual_clock() {
         let time = Utc.ymd(1970, 1, 1).and_hms(0, 1, 1);
+        let clock = ManualClock::new(time);
+        let shared = clock.clone();
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(shared.now(), time + Duration::minutes(5));
 
-        freeze_time::<(), _, _>(time, || async {
-            assert_eq!(current_time().await, time);
-        })
-        .await;
+        shared.set(time);
+        assert_eq!(clock.now(), time);
+    }
 
-        assert_ne!(current_time().await, time);
+    #[test]
+    fn system_clock() {
+        let before = Utc::now();
+        let now = SystemClock.now();
+        assert!(now >= before && now <= Utc::now());
     }
 }
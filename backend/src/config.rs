@@ -0,0 +1,616 @@
+//! Settings for the backend, loaded once at startup.
+//!
+//! Later layers override earlier ones:
+//!
+//! 1. The defaults below.
+//! 2. A TOML file given with `--config` or `CONFIG_FILE`.
+//! 3. Environment variables, see `Config::apply_env`.
+//! 4. Command line flags, see `Args`.
+
+use crate::env::Env;
+use crate::rate_limit::RateLimitPolicy;
+use crate::{BackendApiEndpoint, Endpoints};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+use thiserror::Error;
+
+#[derive(Debug, Default, StructOpt)]
+#[structopt(name = "backend")]
+pub struct Args {
+    /// TOML file with settings. Can also be set with CONFIG_FILE
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, e.g. 0.0.0.0:8080
+    #[structopt(long = "bind")]
+    pub bind_address: Option<SocketAddr>,
+
+    /// production, development or test
+    #[structopt(long)]
+    pub env: Option<Env>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Write the OpenAPI document to a file, or stdout if there is no path
+    Openapi {
+        #[structopt(parse(from_os_str))]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub env: Env,
+    pub bind_address: SocketAddr,
+    pub database_url: Secret,
+    /// Mixed into password hashes.
+    pub secret_key: Secret,
+    pub db_pool: DbPoolConfig,
+    pub cors: CorsConfig,
+    pub argon2: Argon2Config,
+    pub rate_limits: RateLimitsConfig,
+    pub scheduler: SchedulerConfig,
+    pub tweets: TweetsConfig,
+    pub media: MediaConfig,
+    pub features: Features,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            env: Env::Development,
+            bind_address: ([127, 0, 0, 1], 8080).into(),
+            database_url: Secret::default(),
+            secret_key: Secret::default(),
+            db_pool: DbPoolConfig::default(),
+            cors: CorsConfig::default(),
+            argon2: Argon2Config::default(),
+            rate_limits: RateLimitsConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            tweets: TweetsConfig::default(),
+            media: MediaConfig::default(),
+            features: Features::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbPoolConfig {
+    pub max_size: u32,
+    pub min_size: u32,
+}
+
+impl Default for DbPoolConfig {
+    fn default() -> Self {
+        DbPoolConfig {
+            max_size: 10,
+            min_size: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// `"*"` allows any origin.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Argon2Config {
+    pub iterations: u32,
+    /// In kibibytes.
+    pub memory_size: u32,
+    /// Defaults to the number of CPUs.
+    pub lanes: Option<u32>,
+}
+
+impl Default for Argon2Config {
+    fn default() -> Self {
+        Argon2Config {
+            iterations: 192,
+            memory_size: 4096,
+            lanes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// How often to check for scheduled tweets that are due.
+    pub interval_seconds: u64,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            interval_seconds: 10,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.interval_seconds)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TweetsConfig {
+    /// How long after posting a tweet its author can edit it. 0 turns editing off.
+    pub edit_window_minutes: u32,
+}
+
+impl Default for TweetsConfig {
+    fn default() -> Self {
+        TweetsConfig {
+            edit_window_minutes: 30,
+        }
+    }
+}
+
+impl TweetsConfig {
+    pub fn edit_window(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.edit_window_minutes.into())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    /// Where uploaded images are kept.
+    pub directory: PathBuf,
+    /// The largest upload `POST /media` accepts, in bytes.
+    pub max_upload_size: u64,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig {
+            directory: PathBuf::from("media"),
+            max_upload_size: 5 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitsConfig {
+    pub enabled: bool,
+    /// Replaces the policies endpoints declare, keyed by endpoint name like `CreateUser`.
+    pub endpoints: HashMap<String, RateLimitPolicy>,
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        RateLimitsConfig {
+            enabled: true,
+            endpoints: HashMap::new(),
+        }
+    }
+}
+
+impl RateLimitsConfig {
+    pub fn policy<E: BackendApiEndpoint>(&self) -> Option<RateLimitPolicy> {
+        match E::SHARES_RATE_LIMIT_WITH {
+            Some(shared) => self.policy_named((shared.name)(), shared.policy),
+            None => self.policy_named(crate::endpoint_name::<E>(), E::RATE_LIMIT),
+        }
+    }
+
+    /// For routes that aren't `ApiEndpoint`s, which are named in the config like endpoints are.
+    pub fn policy_named(
+        &self,
+        name: &str,
+        default: Option<RateLimitPolicy>,
+    ) -> Option<RateLimitPolicy> {
+        if !self.enabled {
+            return None;
+        }
+        self.endpoints.get(name).copied().or(default)
+    }
+}
+
+/// Parts of the API that can be turned off. Their endpoints aren't routed when disabled.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    pub signups: bool,
+    /// Only turns off enrolling. Users who already have two-factor auth can still log in and
+    /// disable it.
+    pub two_factor: bool,
+    pub api_tokens: bool,
+    pub oauth: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            signups: true,
+            two_factor: true,
+            api_tokens: true,
+            oauth: true,
+        }
+    }
+}
+
+/// A string that shouldn't end up in logs.
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    #[cfg(test)]
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("couldn't read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("couldn't parse {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+    #[error("invalid value for {name}: {message}")]
+    InvalidVar { name: String, message: String },
+    #[error("invalid configuration:\n{}", .0.join("\n"))]
+    Invalid(Vec<String>),
+}
+
+impl Config {
+    /// `vars` looks up environment variables. It's a function so tests don't have to touch the
+    /// real environment.
+    pub fn load(args: &Args, vars: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let file = args
+            .config
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .or_else(|| vars("CONFIG_FILE"));
+        let mut config = match file {
+            Some(path) => Config::from_file(&path)?,
+            None => Config::default(),
+        };
+
+        config.apply_env(vars)?;
+
+        if let Some(env) = args.env {
+            config.env = env;
+        }
+        if let Some(bind_address) = args.bind_address {
+            config.bind_address = bind_address;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Small Argon2 parameters keep the tests fast.
+    #[cfg(test)]
+    pub fn for_tests() -> Config {
+        Config {
+            env: Env::Test,
+            secret_key: Secret::new("test"),
+            argon2: Argon2Config {
+                iterations: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn from_file(path: &str) -> Result<Config, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_string(),
+            source,
+        })?;
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    /// The variables we've always used keep their names, e.g. `DATABASE_URL`.
+    fn apply_env(&mut self, vars: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(env) = parse_var(&vars, "APP_ENV")? {
+            self.env = env;
+        }
+        if let Some(bind_address) = parse_var(&vars, "BIND_ADDRESS")? {
+            self.bind_address = bind_address;
+        }
+        if let Some(database_url) = vars("DATABASE_URL") {
+            self.database_url = Secret(database_url);
+        }
+        if let Some(secret_key) = vars("SECRET_KEY") {
+            self.secret_key = Secret(secret_key);
+        }
+        if let Some(max_size) = parse_var(&vars, "DB_POOL_MAX_SIZE")? {
+            self.db_pool.max_size = max_size;
+        }
+        if let Some(min_size) = parse_var(&vars, "DB_POOL_MIN_SIZE")? {
+            self.db_pool.min_size = min_size;
+        }
+        if let Some(origins) = vars("CORS_ALLOWED_ORIGINS") {
+            self.cors.allowed_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// Collects every problem so they can be fixed in one go.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.database_url.expose().is_empty() {
+            errors.push("database_url is required".to_string());
+        }
+        if self.secret_key.expose().is_empty() {
+            errors.push("secret_key is required".to_string());
+        }
+
+        if self.db_pool.max_size == 0 {
+            errors.push("db_pool.max_size must be at least 1".to_string());
+        }
+        if self.db_pool.min_size > self.db_pool.max_size {
+            errors.push("db_pool.min_size can't be larger than db_pool.max_size".to_string());
+        }
+
+        if self.cors.allowed_origins.is_empty() {
+            errors.push("cors.allowed_origins can't be empty".to_string());
+        }
+        let origins = &self.cors.allowed_origins;
+        if origins.len() > 1 && origins.iter().any(|origin| origin == "*") {
+            errors.push("cors.allowed_origins can't mix `*` with other origins".to_string());
+        }
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" && tide::http::Url::parse(origin).is_err() {
+                errors.push(format!("cors.allowed_origins: `{}` isn't a URL", origin));
+            }
+        }
+
+        let lanes = self.argon2.lanes.unwrap_or(1);
+        if self.argon2.iterations == 0 {
+            errors.push("argon2.iterations must be at least 1".to_string());
+        }
+        if lanes == 0 {
+            errors.push("argon2.lanes must be at least 1".to_string());
+        }
+        if self.argon2.memory_size < 8 * lanes {
+            errors.push("argon2.memory_size must be at least 8 times argon2.lanes".to_string());
+        }
+
+        if self.scheduler.interval_seconds == 0 {
+            errors.push("scheduler.interval_seconds must be at least 1".to_string());
+        }
+
+        if self.media.max_upload_size == 0 {
+            errors.push("media.max_upload_size must be at least 1".to_string());
+        }
+
+        let known_endpoints = endpoint_names();
+        for (name, policy) in &self.rate_limits.endpoints {
+            if !known_endpoints.contains(name.as_str()) {
+                errors.push(format!(
+                    "rate_limits.endpoints: unknown endpoint `{}`",
+                    name
+                ));
+            }
+            if policy.limit == 0 || policy.period_seconds <= 0 {
+                errors.push(format!(
+                    "rate_limits.endpoints.{}: limit and period_seconds must be positive",
+                    name
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            errors.sort();
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+fn parse_var<T>(vars: impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    vars(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|err: T::Err| ConfigError::InvalidVar {
+                    name: name.to_string(),
+                    message: err.to_string(),
+                })
+        })
+        .transpose()
+}
+
+fn endpoint_names() -> HashSet<&'static str> {
+    #[derive(Default)]
+    struct Names(HashSet<&'static str>);
+
+    impl Endpoints for Names {
+        fn add<E>(&mut self)
+        where
+            E: 'static + BackendApiEndpoint,
+            E::Payload: Send,
+            E::Query: Send,
+        {
+            // Shared limits are configured under the endpoint that owns them
+            if E::SHARES_RATE_LIMIT_WITH.is_none() {
+                self.0.insert(crate::endpoint_name::<E>());
+            }
+        }
+    }
+
+    let mut names = Names::default();
+    crate::add_endpoints(&mut names, &Features::default());
+    names.0
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn vars<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    const REQUIRED: &[(&str, &str)] = &[
+        ("DATABASE_URL", "postgres://localhost/witter"),
+        ("SECRET_KEY", "secret"),
+    ];
+
+    #[test]
+    fn defaults_with_required_vars() {
+        let config = Config::load(&Args::default(), vars(REQUIRED)).unwrap();
+        assert_eq!(config.env, Env::Development);
+        assert_eq!(config.bind_address.to_string(), "127.0.0.1:8080");
+        assert_eq!(config.secret_key.expose(), "secret");
+        assert!(config.features.signups);
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let file = std::env::temp_dir().join(format!("witter-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &file,
+            r#"
+            bind_address = "0.0.0.0:80"
+            secret_key = "from file"
+
+            [db_pool]
+            max_size = 20
+
+            [rate_limits.endpoints.CreateUser]
+            limit = 100
+            period_seconds = 60
+
+            [features]
+            oauth = false
+            "#,
+        )
+        .unwrap();
+
+        let mut env = REQUIRED.to_vec();
+        env.push(("BIND_ADDRESS", "0.0.0.0:8000"));
+        let args = Args::from_iter(&[
+            "backend",
+            "--config",
+            file.to_str().unwrap(),
+            "--env",
+            "production",
+        ]);
+        let config = Config::load(&args, vars(&env)).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(config.env, Env::Production);
+        assert_eq!(config.bind_address.to_string(), "0.0.0.0:8000");
+        assert_eq!(config.secret_key.expose(), "secret");
+        assert_eq!(config.db_pool.max_size, 20);
+        assert_eq!(
+            config.rate_limits.endpoints["CreateUser"],
+            RateLimitPolicy {
+                limit: 100,
+                period_seconds: 60
+            }
+        );
+        assert!(!config.features.oauth);
+        assert!(config.features.signups);
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let mut config = Config::default();
+        config.db_pool.max_size = 0;
+        config.db_pool.min_size = 1;
+        config.scheduler.interval_seconds = 0;
+        config.media.max_upload_size = 0;
+        config.cors.allowed_origins = vec!["*".to_string(), "https://witter.example".to_string()];
+        config
+            .rate_limits
+            .endpoints
+            .insert("Nope".to_string(), RateLimitPolicy::per_hours(1, 1));
+
+        match config.validate() {
+            Err(ConfigError::Invalid(errors)) => assert_eq!(
+                errors,
+                vec![
+                    "cors.allowed_origins can't mix `*` with other origins",
+                    "database_url is required",
+                    "db_pool.max_size must be at least 1",
+                    "db_pool.min_size can't be larger than db_pool.max_size",
+                    "media.max_upload_size must be at least 1",
+                    "rate_limits.endpoints: unknown endpoint `Nope`",
+                    "scheduler.interval_seconds must be at least 1",
+                    "secret_key is required",
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_env_vars() {
+        let mut env = REQUIRED.to_vec();
+        env.push(("APP_ENV", "staging"));
+        let err = Config::load(&Args::default(), vars(&env)).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidVar { .. }));
+    }
+
+    #[test]
+    fn secrets_arent_logged() {
+        let config = Config::for_tests();
+        assert!(!format!("{:?}", config).contains("test\""));
+    }
+}
@@ -0,0 +1,110 @@
+use super::{authenticate, generate_token, Authenticated, GrantedScopes};
+use crate::storage::Storage;
+use crate::{BackendApiEndpoint, State};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use sha2::{Digest, Sha256};
+use shared::payloads::CreateApiTokenPayload;
+use shared::responses::CreatedApiTokenResponse;
+use shared::*;
+use tide::{Error, Request, StatusCode};
+use uuid::Uuid;
+
+/// Makes API tokens easy to tell apart from session tokens, both for us and for secret scanners.
+pub const TOKEN_PREFIX: &str = "wit_";
+
+#[async_trait]
+impl BackendApiEndpoint for CreateApiToken {
+    const ISSUES_CREDENTIALS: bool = true;
+
+    async fn handler(
+        req: Request<State>,
+        payload: CreateApiTokenPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &req.state().storage;
+        let user = authenticate(&req).await?.user;
+
+        let name = payload.name.trim();
+        if name.is_empty() {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "API tokens must have a name",
+            ));
+        }
+
+        let mut scopes = payload.scopes;
+        scopes.sort_by_key(|scope| scope.as_str());
+        scopes.dedup();
+        if scopes.is_empty() {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "API tokens must have at least one scope",
+            ));
+        }
+
+        let token = format!("{}{}", TOKEN_PREFIX, generate_token());
+        let now = req.state().clock.now();
+        let api_token = storage
+            .create_api_token(user.id, name, &hash_token(&token), &scopes, now)
+            .await?;
+
+        Ok((
+            CreatedApiTokenResponse { token, api_token },
+            StatusCode::Created,
+        ))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for ListApiTokens {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let tokens = req.state().storage.api_tokens(user.id).await?;
+
+        Ok((tokens, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for RevokeApiToken {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = req.param::<Uuid>("id").map_err(|_| api_token_not_found())?;
+
+        if !req.state().storage.delete_api_token(user.id, id).await? {
+            return Err(api_token_not_found());
+        }
+
+        Ok(((), StatusCode::Ok))
+    }
+}
+
+pub async fn find_by_token(
+    token: &str,
+    now: DateTime<Utc>,
+    storage: &dyn Storage,
+) -> tide::Result<Option<Authenticated>> {
+    let grant = storage.use_api_token(&hash_token(token), now).await?;
+
+    Ok(grant.map(|grant| Authenticated {
+        user: grant.user,
+        scopes: GrantedScopes::Only(grant.scopes),
+    }))
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn api_token_not_found() -> Error {
+    Error::from_str(StatusCode::NotFound, "API token not found")
+}
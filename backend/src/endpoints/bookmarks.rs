@@ -0,0 +1,163 @@
+use crate::endpoints::{authenticate, polls, tweets};
+use crate::responses::{api_error, IntoError};
+use crate::storage::{BookmarkCursor, StorageError};
+use crate::{BackendApiEndpoint, State};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use shared::queries::CursorPagination;
+use shared::responses::{ApiError, CursorPage, ErrorCode};
+use shared::{
+    ApiEndpoint, BookmarkTweet, ListBookmarks, NoPayload, NoQuery, RemoveBookmark, Scope,
+};
+use tide::{Error, Request, StatusCode};
+
+#[async_trait]
+impl BackendApiEndpoint for BookmarkTweet {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::BookmarksWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = tweets::tweet_id(&req)?;
+        let storage = &req.state().storage;
+        let now = req.state().clock.now();
+
+        storage
+            .create_bookmark(user.id, id, now)
+            .await
+            .map_err(|err| match err {
+                StorageError::UniqueViolation { .. } => {
+                    api_error(ErrorCode::Conflict, "You've already bookmarked this tweet")
+                }
+                StorageError::ForeignKeyViolation => tweets::tweet_not_found(),
+                err => err.into(),
+            })?;
+
+        Ok(((), StatusCode::Created))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for RemoveBookmark {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::BookmarksWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = tweets::tweet_id(&req)?;
+
+        if !req.state().storage.delete_bookmark(user.id, id).await? {
+            return Err(api_error(
+                ErrorCode::NotFound,
+                "You haven't bookmarked this tweet",
+            ));
+        }
+
+        Ok(((), StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for ListBookmarks {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::BookmarksRead);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        pagination: CursorPagination,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let page_size = pagination.page_size.unwrap_or(20).clamp(1, 20);
+        let after = pagination
+            .cursor
+            .as_deref()
+            .map(decode_cursor)
+            .transpose()?;
+
+        let user = authenticate(&req).await?.user;
+        let now = req.state().clock.now();
+
+        // One more than fits on the page, to tell whether there's another one
+        let mut bookmarks = req
+            .state()
+            .storage
+            .bookmarks(user.id, after, page_size as i64 + 1)
+            .await?;
+        let next_cursor = if bookmarks.len() > page_size {
+            bookmarks.truncate(page_size);
+            bookmarks
+                .last()
+                .map(|bookmark| encode_cursor(bookmark.cursor))
+        } else {
+            None
+        };
+
+        let items = bookmarks
+            .into_iter()
+            .map(|bookmark| polls::for_viewer(bookmark.tweet, now))
+            .collect();
+
+        Ok((CursorPage { items, next_cursor }, StatusCode::Ok))
+    }
+}
+
+/// Cursors are opaque to clients, so the format can change.
+fn encode_cursor(cursor: BookmarkCursor) -> String {
+    base64::encode_config(
+        format!(
+            "{}/{}",
+            cursor
+                .created_at
+                .to_rfc3339_opts(SecondsFormat::Nanos, true),
+            cursor.id
+        ),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+fn decode_cursor(encoded: &str) -> Result<BookmarkCursor, Error> {
+    let decode = || {
+        let decoded = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (created_at, id) = decoded.split_at(decoded.find('/')?);
+        Some(BookmarkCursor {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .ok()?
+                .with_timezone(&Utc),
+            id: id[1..].parse().ok()?,
+        })
+    };
+
+    decode().ok_or_else(|| {
+        ApiError::new(ErrorCode::BadRequest, "Invalid cursor")
+            .with_field_error("cursor", "must be the `next_cursor` of a page")
+            .into_error()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = BookmarkCursor {
+            created_at: Utc.ymd(2020, 1, 1).and_hms_nano(12, 0, 0, 123_456_789),
+            id: uuid::Uuid::new_v4(),
+        };
+        assert_eq!(decode_cursor(&encode_cursor(cursor)).unwrap(), cursor);
+    }
+
+    #[test]
+    fn rejects_made_up_cursors() {
+        for cursor in &["", "nope", "bm9wZQ", "MjAyMC0wMS0wMVQxMjowMDowMFovbm9wZQ"] {
+            assert!(decode_cursor(cursor).is_err(), "{:?}", cursor);
+        }
+    }
+}
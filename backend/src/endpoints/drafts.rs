@@ -0,0 +1,199 @@
+use super::tweets::validate_text;
+use super::{authenticate, with_transaction};
+use crate::responses::{api_error, IntoError};
+use crate::{BackendApiEndpoint, SharedRateLimit, State};
+use async_trait::async_trait;
+use futures::FutureExt;
+use shared::payloads::DraftPayload;
+use shared::responses::{ApiError, DraftResponse, ErrorCode, PostTweetResponse};
+use shared::*;
+use tide::{Error, Request, StatusCode};
+use uuid::Uuid;
+
+#[async_trait]
+impl BackendApiEndpoint for CreateDraft {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        payload: DraftPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let now = req.state().clock.now();
+        validate_draft_text(&payload.text)?;
+
+        let text = &payload.text;
+        let id = with_transaction(&*req.state().storage, |tx| {
+            async move {
+                if tx.count_drafts(user.id).await? >= MAX_DRAFTS_PER_USER {
+                    return Err(api_error(
+                        ErrorCode::ValidationFailed,
+                        format!("You can have at most {} drafts", MAX_DRAFTS_PER_USER),
+                    ));
+                }
+                Ok(tx.create_draft(user.id, text, now).await?)
+            }
+            .boxed()
+        })
+        .await?;
+
+        Ok((
+            DraftResponse {
+                id,
+                text: payload.text,
+                created_at: now,
+                updated_at: now,
+            },
+            StatusCode::Created,
+        ))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for ListDrafts {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+
+        let drafts = req.state().storage.drafts(user.id).await?;
+
+        Ok((drafts, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for GetDraft {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = draft_id(&req)?;
+
+        let draft = req
+            .state()
+            .storage
+            .find_draft(user.id, id)
+            .await?
+            .ok_or_else(draft_not_found)?;
+
+        Ok((draft, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for UpdateDraft {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        payload: DraftPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = draft_id(&req)?;
+        let now = req.state().clock.now();
+        validate_draft_text(&payload.text)?;
+
+        let draft = req
+            .state()
+            .storage
+            .update_draft(user.id, id, &payload.text, now)
+            .await?
+            .ok_or_else(draft_not_found)?;
+
+        Ok((draft, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for DeleteDraft {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = draft_id(&req)?;
+
+        req.state()
+            .storage
+            .delete_draft(user.id, id)
+            .await?
+            .ok_or_else(draft_not_found)?;
+
+        Ok(((), StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for PublishDraft {
+    const SHARES_RATE_LIMIT_WITH: Option<SharedRateLimit> =
+        Some(SharedRateLimit::of::<PostTweet>());
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = draft_id(&req)?;
+        let now = req.state().clock.now();
+
+        let (tweet_id, text) = with_transaction(&*req.state().storage, |tx| {
+            async move {
+                // Deleting first means a draft published twice at once only becomes one tweet
+                let draft = tx
+                    .delete_draft(user.id, id)
+                    .await?
+                    .ok_or_else(draft_not_found)?;
+                let text = validate_text(&draft.text)?;
+                let tweet_id = tx.create_tweet(user.id, &text, now).await?;
+                Ok((tweet_id, text))
+            }
+            .boxed()
+        })
+        .await?;
+
+        Ok((
+            PostTweetResponse {
+                id: tweet_id,
+                text,
+                publish_at: None,
+            },
+            StatusCode::Created,
+        ))
+    }
+}
+
+/// Drafts are checked against `MAX_TWEET_LENGTH` when they're published, not while they're saved.
+fn validate_draft_text(text: &str) -> tide::Result<()> {
+    if text.len() > MAX_DRAFT_SIZE {
+        return Err(
+            ApiError::new(ErrorCode::ValidationFailed, "Draft is too long")
+                .with_field_error("text", format!("must be at most {} bytes", MAX_DRAFT_SIZE))
+                .into_error(),
+        );
+    }
+    Ok(())
+}
+
+fn draft_id(req: &Request<State>) -> Result<Uuid, Error> {
+    req.param::<Uuid>("id").map_err(|_| draft_not_found())
+}
+
+fn draft_not_found() -> Error {
+    api_error(ErrorCode::NotFound, "Draft not found")
+}
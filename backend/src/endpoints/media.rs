@@ -0,0 +1,236 @@
+//! Image uploads. These are raw routes since their requests and responses aren't JSON.
+
+use super::{authenticate, RequiredScope};
+use crate::media::{self, InvalidImage};
+use crate::rate_limit::RateLimitPolicy;
+use crate::responses::{api_error, IntoError};
+use crate::storage::Repositories;
+use crate::State;
+use futures::io::AsyncReadExt;
+use shared::responses::{ApiError, ErrorCode, MediaResponse};
+use shared::Scope;
+use tide::http::headers;
+use tide::{Body, Error, Request, Response, StatusCode};
+use uuid::Uuid;
+
+/// The form field the image is sent in.
+pub const FILE_FIELD: &str = "file";
+
+/// What the upload route is called in the `rate_limits` config.
+pub const UPLOAD_NAME: &str = "UploadMedia";
+pub const UPLOAD_RATE_LIMIT: RateLimitPolicy = RateLimitPolicy::per_hours(100, 1);
+
+/// Files never change once they're uploaded, so clients can keep them for as long as they like.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// `POST /media` with a `multipart/form-data` body. Responds with a `MediaResponse`, whose id
+/// can be attached to a tweet.
+pub async fn upload(mut req: Request<State>) -> tide::Result {
+    req.set_ext(RequiredScope(Some(Scope::TweetsWrite)));
+    let user = authenticate(&req).await?.user;
+
+    let boundary = req
+        .content_type()
+        .filter(|mime| mime.essence() == "multipart/form-data")
+        .and_then(|mime| mime.param("boundary").map(|boundary| boundary.to_string()))
+        .ok_or_else(|| {
+            api_error(
+                ErrorCode::UnsupportedMediaType,
+                "Images must be uploaded as multipart/form-data",
+            )
+        })?;
+
+    let max_size = req.state().config.media.max_upload_size;
+    let mut body = Vec::new();
+    req.take_body()
+        .take(max_size + 1)
+        .read_to_end(&mut body)
+        .await?;
+    if body.len() as u64 > max_size {
+        return Err(api_error(
+            ErrorCode::MediaTooLarge,
+            format!("Uploads can be at most {} bytes", max_size),
+        ));
+    }
+
+    let file = file_field(body, boundary).await?;
+    let image = async_std::task::spawn_blocking(move || media::process(&file))
+        .await
+        .map_err(invalid_image)?;
+
+    let state = req.state();
+    let now = state.clock.now();
+    let (content_type, width, height) = (image.content_type, image.width, image.height);
+    let id = Uuid::new_v4();
+    // The files are stored before the row is created so every media row has its files
+    let stored = async {
+        state
+            .blob_store
+            .put(&original_key(id), image.original)
+            .await?;
+        state
+            .blob_store
+            .put(&thumbnail_key(id), image.thumbnail)
+            .await?;
+        state
+            .storage
+            .create_media(id, user.id, content_type, width, height, now)
+            .await?;
+        Ok::<_, Error>(())
+    }
+    .await;
+    if let Err(err) = stored {
+        // Files without a row would never be served or cleaned up
+        for key in &[original_key(id), thumbnail_key(id)] {
+            if let Err(err) = state.blob_store.delete(key).await {
+                log::warn!("Failed to delete blob {} of failed upload: {}", key, err);
+            }
+        }
+        return Err(err);
+    }
+
+    let media = MediaResponse::new(id, content_type, width, height);
+    let mut resp = Response::new(StatusCode::Created);
+    resp.set_body(Body::from_json(&serde_json::json!({ "data": media }))?);
+    Ok(resp)
+}
+
+/// `GET /media/:id`
+pub async fn serve(req: Request<State>) -> tide::Result {
+    serve_file(req, original_key).await
+}
+
+/// `GET /media/:id/thumbnail`
+pub async fn serve_thumbnail(req: Request<State>) -> tide::Result {
+    serve_file(req, thumbnail_key).await
+}
+
+async fn serve_file(req: Request<State>, key: fn(Uuid) -> String) -> tide::Result {
+    let id = req.param::<Uuid>("id").map_err(|_| media_not_found())?;
+    let media = req
+        .state()
+        .storage
+        .find_media(id)
+        .await?
+        .ok_or_else(media_not_found)?;
+
+    let key = key(id);
+    let etag = format!("\"{}\"", key);
+    let mut resp = Response::new(StatusCode::Ok);
+    resp.insert_header(headers::CACHE_CONTROL, CACHE_CONTROL);
+    resp.insert_header(headers::ETAG, &etag);
+
+    let not_modified = req
+        .header(headers::IF_NONE_MATCH)
+        .map(|values| {
+            values
+                .iter()
+                .flat_map(|value| value.as_str().split(','))
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        })
+        .unwrap_or(false);
+    if not_modified {
+        resp.set_status(StatusCode::NotModified);
+        return Ok(resp);
+    }
+
+    let bytes = req
+        .state()
+        .blob_store
+        .get(&key)
+        .await?
+        .ok_or_else(media_not_found)?;
+    resp.set_body(bytes);
+    resp.set_content_type(media.content_type.parse::<tide::http::Mime>()?);
+    // Browsers mustn't guess some other type, e.g. HTML
+    resp.insert_header("X-Content-Type-Options", "nosniff");
+    Ok(resp)
+}
+
+/// Attaches uploaded media to a new tweet, in the given order.
+pub async fn attach(
+    repositories: &dyn Repositories,
+    user_id: Uuid,
+    tweet_id: Uuid,
+    media_ids: &[Uuid],
+) -> tide::Result<()> {
+    for (position, media_id) in media_ids.iter().enumerate() {
+        let attached = repositories
+            .attach_media(user_id, *media_id, tweet_id, position as u32)
+            .await?;
+        if !attached {
+            return Err(ApiError::new(
+                ErrorCode::ValidationFailed,
+                format!("Media {} can't be attached", media_id),
+            )
+            .with_field_error(
+                "media_ids",
+                "must be your own uploads that aren't attached to another tweet",
+            )
+            .into_error());
+        }
+    }
+    Ok(())
+}
+
+async fn file_field(body: Vec<u8>, boundary: String) -> tide::Result<Vec<u8>> {
+    let stream = futures::stream::once(async move {
+        Ok::<_, std::convert::Infallible>(bytes::Bytes::from(body))
+    });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    while let Some(field) = multipart.next_field().await.map_err(invalid_multipart)? {
+        if field.name() == Some(FILE_FIELD) {
+            let bytes = field.bytes().await.map_err(invalid_multipart)?;
+            return Ok(bytes.to_vec());
+        }
+    }
+
+    Err(
+        ApiError::new(ErrorCode::ValidationFailed, "No image was uploaded")
+            .with_field_error(FILE_FIELD, "is required")
+            .into_error(),
+    )
+}
+
+fn invalid_multipart(err: multer::Error) -> Error {
+    api_error(
+        ErrorCode::BadRequest,
+        format!("Unable to parse multipart body: {}", err),
+    )
+}
+
+fn invalid_image(err: InvalidImage) -> Error {
+    let (code, field_error) = match err {
+        InvalidImage::UnsupportedFormat => (
+            ErrorCode::UnsupportedMediaType,
+            "must be a PNG or JPEG image".to_string(),
+        ),
+        InvalidImage::TooLarge => (
+            ErrorCode::ValidationFailed,
+            format!(
+                "can be at most {max}x{max} pixels",
+                max = media::MAX_DIMENSION
+            ),
+        ),
+        InvalidImage::Corrupt => (
+            ErrorCode::ValidationFailed,
+            "isn't a valid image".to_string(),
+        ),
+    };
+    ApiError::new(code, err.to_string())
+        .with_field_error(FILE_FIELD, field_error)
+        .into_error()
+}
+
+fn media_not_found() -> Error {
+    api_error(ErrorCode::NotFound, "Media not found")
+}
+
+fn original_key(id: Uuid) -> String {
+    id.to_string()
+}
+
+fn thumbnail_key(id: Uuid) -> String {
+    format!("{}.thumbnail", id)
+}
@@ -1,41 +1,125 @@
-use crate::{responses::BuildApiResponse, State};
+use crate::responses::api_error;
+use crate::storage::{Repositories, Storage};
+use crate::State;
+use futures::future::BoxFuture;
 use lazy_static::lazy_static;
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
 use regex::Regex;
-use serde_json::Value;
-use shared::responses::UserResponse;
-use sqlx::query_as;
+use shared::responses::{ErrorCode, UserResponse};
+use shared::Scope;
 use tide::http::headers::HeaderName;
 use tide::http::Error;
 use tide::http::StatusCode;
-use tide::{Request, Response};
+use tide::Request;
 
+pub mod api_tokens;
+pub mod bookmarks;
+pub mod drafts;
 pub mod me;
+pub mod media;
+pub mod oauth;
+pub mod polls;
+pub mod scheduled_tweets;
 pub mod tweets;
+pub mod two_factor;
 pub mod users;
 
 lazy_static! {
     static ref BEARER_TOKEN_REGEX: Regex = Regex::new("^Bearer (.*)$").unwrap();
 }
 
-pub async fn authenticate(req: &Request<State>) -> Result<UserResponse, Error> {
+/// The scope required by the endpoint being called. Set by `add_endpoint` and checked by
+/// `authenticate`.
+#[derive(Debug, Clone, Copy)]
+pub struct RequiredScope(pub Option<Scope>);
+
+#[derive(Debug)]
+pub struct Authenticated {
+    pub user: UserResponse,
+    pub scopes: GrantedScopes,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum GrantedScopes {
+    /// Tokens from logging in with a password can do everything.
+    Session,
+    Only(Vec<Scope>),
+}
+
+impl GrantedScopes {
+    pub fn allows(&self, required: Option<Scope>) -> bool {
+        match (self, required) {
+            (GrantedScopes::Session, _) => true,
+            (GrantedScopes::Only(granted), Some(required)) => granted.contains(&required),
+            // Endpoints that don't require a specific scope can only be used by sessions
+            (GrantedScopes::Only(_), None) => false,
+        }
+    }
+}
+
+pub async fn authenticate(req: &Request<State>) -> Result<Authenticated, Error> {
+    let required_scope = req.ext::<RequiredScope>().and_then(|required| required.0);
+    authenticate_with_scope(req, required_scope).await
+}
+
+pub async fn authenticate_with_scope(
+    req: &Request<State>,
+    required_scope: Option<Scope>,
+) -> Result<Authenticated, Error> {
     let auth_token = get_auth_token(req)?;
+    let authenticated = find_authenticated(req, auth_token)
+        .await?
+        .ok_or_else(|| api_error(ErrorCode::InvalidToken, "Invalid auth token"))?;
+
+    if !authenticated.scopes.allows(required_scope) {
+        return Err(match required_scope {
+            Some(scope) => api_error(
+                ErrorCode::MissingScope,
+                format!("Token is missing the `{}` scope", scope),
+            ),
+            None => api_error(
+                ErrorCode::Forbidden,
+                "API tokens cannot be used for this endpoint",
+            ),
+        });
+    }
 
-    let db_pool = &req.state().db_pool;
-    let user = query_as!(
-        UserResponse,
-        r#"
-            select users.id, users.username
-            from users
-            inner join auth_tokens
-                on auth_tokens.user_id = users.id
-                and auth_tokens.token = $1
-            "#,
-        auth_token
-    )
-    .fetch_optional(db_pool)
-    .await?;
-
-    user.ok_or_else(|| Error::from_str(StatusCode::Unauthorized, "Invalid auth token"))
+    Ok(authenticated)
+}
+
+/// Looks up who `auth_token` belongs to, whichever kind of token it is.
+pub async fn find_authenticated(
+    req: &Request<State>,
+    auth_token: &str,
+) -> Result<Option<Authenticated>, Error> {
+    let storage = &*req.state().storage;
+    let now = req.state().clock.now();
+
+    if auth_token.starts_with(api_tokens::TOKEN_PREFIX) {
+        api_tokens::find_by_token(auth_token, now, storage).await
+    } else if auth_token.starts_with(oauth::ACCESS_TOKEN_PREFIX) {
+        oauth::find_by_access_token(auth_token, now, storage).await
+    } else {
+        let user = storage.find_user_by_auth_token(auth_token).await?;
+
+        Ok(user.map(|user| Authenticated {
+            user,
+            scopes: GrantedScopes::Session,
+        }))
+    }
+}
+
+/// Runs `f` in a transaction that's committed if `f` succeeds and rolled back otherwise.
+pub async fn with_transaction<'s, T, F>(storage: &'s dyn Storage, f: F) -> tide::Result<T>
+where
+    F: for<'t> FnOnce(&'t (dyn Repositories + 's)) -> BoxFuture<'t, tide::Result<T>>,
+{
+    let tx = storage.begin().await?;
+    let value = f(&*tx).await?;
+    tx.commit().await?;
+    Ok(value)
 }
 
 pub fn get_auth_token(req: &Request<State>) -> Result<&str, Error> {
@@ -54,6 +138,15 @@ pub fn get_auth_token(req: &Request<State>) -> Result<&str, Error> {
     Ok(caps.get(1).expect("missing capture group").as_str())
 }
 
+/// The IP address of the client, without the port.
+pub fn client_ip(req: &Request<State>) -> Option<&str> {
+    let peer_addr = req.peer_addr()?;
+    match peer_addr.rfind(':') {
+        Some(idx) => Some(&peer_addr[..idx]),
+        None => Some(peer_addr),
+    }
+}
+
 fn get_header<'a>(header_key: &str, req: &'a Request<State>) -> Result<&'a str, Error> {
     let auth_header_key: HeaderName = header_key.parse()?;
 
@@ -73,10 +166,6 @@ fn get_header<'a>(header_key: &str, req: &'a Request<State>) -> Result<&'a str,
     }
 }
 
-pub fn empty_response() -> Result<Response, Error> {
-    Value::Null.to_response()
-}
-
-pub fn something_went_wrong(status_code: StatusCode) -> tide::Error {
-    tide::Error::from_str(status_code, "Something went wrong")
+pub fn generate_token() -> String {
+    OsRng.sample_iter(&Alphanumeric).take(32).collect()
 }
@@ -0,0 +1,539 @@
+//! An OAuth 2.0 authorization server so third party apps can act on behalf of users.
+//!
+//! Only the authorization code grant is supported and PKCE ([RFC 7636]) with `S256` is required
+//! for all clients. The token and revocation endpoints follow [RFC 6749] and [RFC 7009] rather
+//! than the rest of our API since that is what OAuth client libraries expect.
+//!
+//! [RFC 6749]: https://tools.ietf.org/html/rfc6749
+//! [RFC 7009]: https://tools.ietf.org/html/rfc7009
+//! [RFC 7636]: https://tools.ietf.org/html/rfc7636
+
+use super::{authenticate, generate_token, get_header, Authenticated, GrantedScopes};
+use crate::storage::{AuthorizationGrant, OAuthClient, Storage};
+use crate::{BackendApiEndpoint, State};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use shared::payloads::{AuthorizeOAuthClientPayload, RegisterOAuthClientPayload};
+use shared::responses::{
+    OAuthAuthorizationResponse, OAuthClientResponse, RegisteredOAuthClientResponse,
+};
+use shared::*;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tide::http::url::Host;
+use tide::http::{headers, Url};
+use tide::{Error, Request, Response, StatusCode};
+use uuid::Uuid;
+
+/// Access tokens get their own prefix so `authenticate` can tell them apart from API tokens.
+pub const ACCESS_TOKEN_PREFIX: &str = "wito_";
+
+const AUTHORIZATION_CODE_LIFETIME_MINUTES: i64 = 10;
+const ACCESS_TOKEN_LIFETIME_DAYS: i64 = 30;
+
+#[async_trait]
+impl BackendApiEndpoint for RegisterOAuthClient {
+    const ISSUES_CREDENTIALS: bool = true;
+
+    async fn handler(
+        req: Request<State>,
+        payload: RegisterOAuthClientPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &req.state().storage;
+        let user = authenticate(&req).await?.user;
+
+        let name = payload.name.trim();
+        if name.is_empty() {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "OAuth clients must have a name",
+            ));
+        }
+
+        if payload.redirect_uris.is_empty() {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "OAuth clients must have at least one redirect URI",
+            ));
+        }
+        for redirect_uri in &payload.redirect_uris {
+            validate_redirect_uri(redirect_uri)?;
+        }
+
+        let client_secret = if payload.confidential {
+            Some(generate_token())
+        } else {
+            None
+        };
+
+        let now = req.state().clock.now();
+        let hashed_client_secret = client_secret.as_deref().map(hash);
+        let client_id = storage
+            .create_oauth_client(
+                user.id,
+                name,
+                hashed_client_secret.as_deref(),
+                &payload.redirect_uris,
+                now,
+            )
+            .await?;
+
+        Ok((
+            RegisteredOAuthClientResponse {
+                client_id,
+                name: name.to_string(),
+                redirect_uris: payload.redirect_uris,
+                client_secret,
+            },
+            StatusCode::Created,
+        ))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for GetOAuthClient {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let client_id = req
+            .param::<Uuid>("client_id")
+            .map_err(|_| client_not_found())?;
+
+        let client = req
+            .state()
+            .storage
+            .find_oauth_client(client_id)
+            .await?
+            .ok_or_else(client_not_found)?;
+
+        Ok((
+            OAuthClientResponse {
+                client_id: client.id,
+                name: client.name,
+            },
+            StatusCode::Ok,
+        ))
+    }
+}
+
+/// Called by our frontend when the user approves or denies an authorization request.
+#[async_trait]
+impl BackendApiEndpoint for AuthorizeOAuthClient {
+    const ISSUES_CREDENTIALS: bool = true;
+
+    async fn handler(
+        req: Request<State>,
+        payload: AuthorizeOAuthClientPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &req.state().storage;
+        let user = authenticate(&req).await?.user;
+
+        let client = storage
+            .find_oauth_client(payload.client_id)
+            .await?
+            .ok_or_else(client_not_found)?;
+
+        // Never redirect to a URI the client hasn't registered, otherwise anyone could get codes
+        // sent to them
+        if !client.redirect_uris.contains(&payload.redirect_uri) {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Redirect URI is not registered for this client",
+            ));
+        }
+        // Clients registered before the schemes were restricted might still have others
+        validate_redirect_uri(&payload.redirect_uri)?;
+
+        if !payload.approved {
+            let redirect_to = redirect_with_params(
+                &payload.redirect_uri,
+                &[("error", "access_denied")],
+                payload.state.as_deref(),
+            )?;
+            return Ok((OAuthAuthorizationResponse { redirect_to }, StatusCode::Ok));
+        }
+
+        let scopes = Scope::parse_list(&payload.scope)
+            .map_err(|err| Error::from_str(StatusCode::UnprocessableEntity, err.to_string()))?;
+        if scopes.is_empty() {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "At least one scope must be requested",
+            ));
+        }
+
+        if payload.code_challenge_method != "S256" {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Only the `S256` code challenge method is supported",
+            ));
+        }
+        if !(43..=128).contains(&payload.code_challenge.len()) {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Invalid code challenge",
+            ));
+        }
+
+        let code = generate_token();
+        let now = req.state().clock.now();
+        let grant = AuthorizationGrant {
+            client_id: client.id,
+            user_id: user.id,
+            redirect_uri: payload.redirect_uri.clone(),
+            scopes,
+            code_challenge: payload.code_challenge,
+            expires_at: now + Duration::minutes(AUTHORIZATION_CODE_LIFETIME_MINUTES),
+        };
+        storage
+            .create_authorization_code(&hash(&code), &grant, now)
+            .await?;
+
+        let redirect_to = redirect_with_params(
+            &payload.redirect_uri,
+            &[("code", code.as_str())],
+            payload.state.as_deref(),
+        )?;
+
+        Ok((
+            OAuthAuthorizationResponse { redirect_to },
+            StatusCode::Created,
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    code_verifier: Option<String>,
+}
+
+pub async fn token(mut req: Request<State>) -> tide::Result {
+    let params = match req.body_form::<TokenRequest>().await {
+        Ok(params) => params,
+        Err(_) => {
+            return oauth_error(
+                StatusCode::BadRequest,
+                "invalid_request",
+                "Unable to parse request body",
+            )
+        }
+    };
+    let storage = &*req.state().storage;
+
+    if params.grant_type != "authorization_code" {
+        return oauth_error(
+            StatusCode::BadRequest,
+            "unsupported_grant_type",
+            "Only the `authorization_code` grant type is supported",
+        );
+    }
+
+    let client = match authenticate_client(&req, &params, storage).await? {
+        Some(client) => client,
+        None => {
+            return oauth_error(
+                StatusCode::Unauthorized,
+                "invalid_client",
+                "Client authentication failed",
+            )
+        }
+    };
+
+    let (code, redirect_uri, code_verifier) =
+        match (&params.code, &params.redirect_uri, &params.code_verifier) {
+            (Some(code), Some(redirect_uri), Some(code_verifier)) => {
+                (code, redirect_uri, code_verifier)
+            }
+            _ => {
+                return oauth_error(
+                    StatusCode::BadRequest,
+                    "invalid_request",
+                    "`code`, `redirect_uri` and `code_verifier` are required",
+                )
+            }
+        };
+
+    // Codes are deleted as soon as they're presented so they can only be used once, even if
+    // the rest of the request turns out to be invalid
+    let grant = storage.take_authorization_code(&hash(code)).await?;
+
+    let now = req.state().clock.now();
+    let grant = match grant {
+        Some(grant)
+            if grant.client_id == client.id
+                && grant.redirect_uri == *redirect_uri
+                && grant.expires_at > now =>
+        {
+            grant
+        }
+        _ => {
+            return oauth_error(
+                StatusCode::BadRequest,
+                "invalid_grant",
+                "Invalid or expired authorization code",
+            )
+        }
+    };
+
+    if pkce_challenge(code_verifier) != grant.code_challenge {
+        return oauth_error(
+            StatusCode::BadRequest,
+            "invalid_grant",
+            "Code verifier doesn't match the code challenge",
+        );
+    }
+
+    let access_token = format!("{}{}", ACCESS_TOKEN_PREFIX, generate_token());
+    let expires_in = Duration::days(ACCESS_TOKEN_LIFETIME_DAYS);
+    storage
+        .create_access_token(
+            client.id,
+            grant.user_id,
+            &hash(&access_token),
+            &grant.scopes,
+            now + expires_in,
+            now,
+        )
+        .await?;
+
+    oauth_response(
+        StatusCode::Ok,
+        json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": expires_in.num_seconds(),
+            "scope": Scope::format_list(&grant.scopes),
+        }),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct RevocationRequest {
+    token: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+pub async fn revoke(mut req: Request<State>) -> tide::Result {
+    let params = match req.body_form::<RevocationRequest>().await {
+        Ok(params) => params,
+        Err(_) => {
+            return oauth_error(
+                StatusCode::BadRequest,
+                "invalid_request",
+                "Unable to parse request body",
+            )
+        }
+    };
+    let storage = &*req.state().storage;
+
+    let credentials = ClientCredentials::from_request(
+        &req,
+        params.client_id.as_deref(),
+        params.client_secret.as_deref(),
+    );
+    let client = match credentials {
+        Some(credentials) => credentials.verify(storage).await?,
+        None => None,
+    };
+    let client = match client {
+        Some(client) => client,
+        None => {
+            return oauth_error(
+                StatusCode::Unauthorized,
+                "invalid_client",
+                "Client authentication failed",
+            )
+        }
+    };
+
+    // Unknown tokens and tokens belonging to other clients are ignored, as required by RFC 7009
+    storage
+        .delete_access_token(client.id, &hash(&params.token))
+        .await?;
+
+    oauth_response(StatusCode::Ok, json!({}))
+}
+
+pub async fn find_by_access_token(
+    token: &str,
+    now: DateTime<Utc>,
+    storage: &dyn Storage,
+) -> tide::Result<Option<Authenticated>> {
+    let grant = storage.find_access_token(&hash(token), now).await?;
+
+    Ok(grant.map(|grant| Authenticated {
+        user: grant.user,
+        scopes: GrantedScopes::Only(grant.scopes),
+    }))
+}
+
+/// Clients can authenticate with HTTP Basic auth or by including their credentials in the
+/// request body. Public clients only send their client id.
+struct ClientCredentials {
+    client_id: String,
+    client_secret: Option<String>,
+}
+
+impl ClientCredentials {
+    fn from_request(
+        req: &Request<State>,
+        client_id: Option<&str>,
+        client_secret: Option<&str>,
+    ) -> Option<Self> {
+        if let Some(credentials) = Self::from_basic_auth(req) {
+            return Some(credentials);
+        }
+
+        Some(ClientCredentials {
+            client_id: client_id?.to_string(),
+            client_secret: client_secret.map(|secret| secret.to_string()),
+        })
+    }
+
+    fn from_basic_auth(req: &Request<State>) -> Option<Self> {
+        let header_value = get_header("Authorization", req).ok()?;
+        let encoded = header_value.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(base64::decode(encoded).ok()?).ok()?;
+        let mut parts = decoded.splitn(2, ':');
+
+        Some(ClientCredentials {
+            client_id: parts.next()?.to_string(),
+            client_secret: parts.next().map(|secret| secret.to_string()),
+        })
+    }
+
+    async fn verify(self, storage: &dyn Storage) -> tide::Result<Option<OAuthClient>> {
+        let client_id = match self.client_id.parse::<Uuid>() {
+            Ok(client_id) => client_id,
+            Err(_) => return Ok(None),
+        };
+
+        let client = match storage.find_oauth_client(client_id).await? {
+            Some(client) => client,
+            None => return Ok(None),
+        };
+
+        let authenticated = match (&client.hashed_client_secret, &self.client_secret) {
+            (None, _) => true,
+            (Some(hashed_secret), Some(secret)) => *hashed_secret == hash(secret),
+            (Some(_), None) => false,
+        };
+
+        if authenticated {
+            Ok(Some(client))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+async fn authenticate_client(
+    req: &Request<State>,
+    params: &TokenRequest,
+    storage: &dyn Storage,
+) -> tide::Result<Option<OAuthClient>> {
+    let credentials = ClientCredentials::from_request(
+        req,
+        params.client_id.as_deref(),
+        params.client_secret.as_deref(),
+    );
+
+    match credentials {
+        Some(credentials) => credentials.verify(storage).await,
+        None => Ok(None),
+    }
+}
+
+/// Redirect URIs must use `https`, or `http` on the user's own machine for native apps. The
+/// frontend navigates to them, so something like `javascript:` would run in our origin.
+fn validate_redirect_uri(redirect_uri: &str) -> tide::Result<()> {
+    let invalid = || {
+        Error::from_str(
+            StatusCode::UnprocessableEntity,
+            format!("Invalid redirect URI `{}`", redirect_uri),
+        )
+    };
+
+    let url = Url::parse(redirect_uri).map_err(|_| invalid())?;
+    let allowed_scheme = match url.scheme() {
+        "https" => true,
+        "http" => is_loopback(&url),
+        _ => false,
+    };
+    if !allowed_scheme || url.fragment().is_some() || redirect_uri.contains(' ') {
+        return Err(invalid());
+    }
+
+    Ok(())
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(ip)) => ip == Ipv4Addr::LOCALHOST,
+        Some(Host::Ipv6(ip)) => ip == Ipv6Addr::LOCALHOST,
+        None => false,
+    }
+}
+
+fn redirect_with_params(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+    state: Option<&str>,
+) -> tide::Result<String> {
+    let mut url = Url::parse(redirect_uri)?;
+    {
+        let mut query_pairs = url.query_pairs_mut();
+        query_pairs.extend_pairs(params);
+        if let Some(state) = state {
+            query_pairs.append_pair("state", state);
+        }
+    }
+    Ok(url.to_string())
+}
+
+fn pkce_challenge(code_verifier: &str) -> String {
+    base64::encode_config(
+        Sha256::digest(code_verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+fn hash(value: &str) -> String {
+    format!("{:x}", Sha256::digest(value.as_bytes()))
+}
+
+fn oauth_response(status: StatusCode, body: serde_json::Value) -> tide::Result {
+    let mut resp = Response::new(status);
+    resp.insert_header(headers::CACHE_CONTROL, "no-store");
+    resp.insert_header("Pragma", "no-cache");
+    resp.set_body(body);
+    Ok(resp)
+}
+
+fn oauth_error(status: StatusCode, error: &str, description: &str) -> tide::Result {
+    oauth_response(
+        status,
+        json!({
+            "error": error,
+            "error_description": description,
+        }),
+    )
+}
+
+fn client_not_found() -> Error {
+    Error::from_str(StatusCode::NotFound, "OAuth client not found")
+}
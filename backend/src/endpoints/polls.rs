@@ -0,0 +1,156 @@
+//! Polls are created along with their tweet, in `tweets`. This is voting in them, and showing them
+//! to each viewer.
+
+use crate::endpoints::{authenticate, tweets};
+use crate::responses::{api_error, IntoError};
+use crate::storage::StorageError;
+use crate::{BackendApiEndpoint, State};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use shared::payloads::{CreatePollPayload, VotePayload};
+use shared::responses::{ApiError, ErrorCode, TweetResponse};
+use shared::tweet_text;
+use shared::{
+    ApiEndpoint, NoQuery, Scope, VoteInPoll, MAX_POLL_DURATION_DAYS, MAX_POLL_OPTIONS,
+    MAX_POLL_OPTION_LENGTH, MIN_POLL_OPTIONS,
+};
+use tide::{Error, Request, StatusCode};
+
+#[async_trait]
+impl BackendApiEndpoint for VoteInPoll {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        vote: VotePayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = tweets::tweet_id(&req)?;
+        let storage = &req.state().storage;
+        let now = req.state().clock.now();
+
+        let tweet = storage
+            .find_tweet(id, user.id)
+            .await?
+            .ok_or_else(tweets::tweet_not_found)?;
+        let poll = tweet.poll.ok_or_else(poll_not_found)?;
+        if poll.closes_at <= now {
+            return Err(api_error(ErrorCode::Forbidden, "This poll has closed"));
+        }
+        if poll.voted_for.is_some() {
+            return Err(already_voted());
+        }
+        if vote.option as usize >= poll.options.len() {
+            return Err(
+                ApiError::new(ErrorCode::ValidationFailed, "There's no such option")
+                    .with_field_error("option", "must be one of the poll's options")
+                    .into_error(),
+            );
+        }
+
+        // Checked again by the storage, in case the same user votes twice at once
+        let voted = storage
+            .vote_in_poll(id, user.id, vote.option, now)
+            .await
+            .map_err(|err| match err {
+                StorageError::UniqueViolation { .. } => already_voted(),
+                err => err.into(),
+            })?;
+        if !voted {
+            return Err(poll_not_found());
+        }
+
+        let tweet = storage
+            .find_tweet(id, user.id)
+            .await?
+            .ok_or_else(tweets::tweet_not_found)?;
+
+        Ok((for_viewer(tweet, now), StatusCode::Created))
+    }
+}
+
+/// Sets whether the tweet's poll has closed, and hides its vote counts unless the viewer has voted
+/// or it has closed. Tweets have to go through this before they're returned.
+pub fn for_viewer(mut tweet: TweetResponse, now: DateTime<Utc>) -> TweetResponse {
+    if let Some(poll) = &mut tweet.poll {
+        poll.closed = poll.closes_at <= now;
+        if !poll.closed && poll.voted_for.is_none() {
+            for option in &mut poll.options {
+                option.votes = None;
+            }
+        }
+    }
+    tweet
+}
+
+/// Returns the options normalized the way they should be stored.
+pub fn validate(
+    poll: &CreatePollPayload,
+    publish_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> tide::Result<Vec<String>> {
+    let options = poll
+        .options
+        .iter()
+        .map(|option| tweet_text::normalize(option.trim()))
+        .collect::<Vec<_>>();
+    let mut error = ApiError::new(ErrorCode::ValidationFailed, "Poll isn't valid");
+
+    if publish_at.is_some() {
+        error = error.with_field_error("poll", "can't be used with scheduled tweets");
+    }
+
+    if !(MIN_POLL_OPTIONS..=MAX_POLL_OPTIONS).contains(&options.len()) {
+        error = error.with_field_error(
+            "poll.options",
+            format!(
+                "must have between {} and {} options",
+                MIN_POLL_OPTIONS, MAX_POLL_OPTIONS
+            ),
+        );
+    }
+    if options.iter().any(|option| option.is_empty()) {
+        error = error.with_field_error("poll.options", "can't be blank");
+    }
+    if options
+        .iter()
+        .any(|option| tweet_text::length(option) > MAX_POLL_OPTION_LENGTH)
+    {
+        error = error.with_field_error(
+            "poll.options",
+            format!("must be at most {} characters", MAX_POLL_OPTION_LENGTH),
+        );
+    }
+    if options
+        .iter()
+        .enumerate()
+        .any(|(i, option)| options[..i].contains(option))
+    {
+        error = error.with_field_error("poll.options", "must be different from each other");
+    }
+
+    if poll.closes_at <= now {
+        error = error.with_field_error("poll.closes_at", "must be in the future");
+    } else if poll.closes_at > now + Duration::days(MAX_POLL_DURATION_DAYS) {
+        error = error.with_field_error(
+            "poll.closes_at",
+            format!("can be at most {} days away", MAX_POLL_DURATION_DAYS),
+        );
+    }
+
+    if error.field_errors.is_empty() {
+        Ok(options)
+    } else {
+        Err(error.into_error())
+    }
+}
+
+fn poll_not_found() -> Error {
+    api_error(ErrorCode::NotFound, "This tweet doesn't have a poll")
+}
+
+fn already_voted() -> Error {
+    api_error(ErrorCode::Conflict, "You've already voted in this poll")
+}
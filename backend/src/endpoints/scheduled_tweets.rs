@@ -0,0 +1,90 @@
+use super::authenticate;
+use super::tweets::{validate_publish_at, validate_text};
+use crate::responses::api_error;
+use crate::{BackendApiEndpoint, State};
+use async_trait::async_trait;
+use shared::payloads::UpdateScheduledTweetPayload;
+use shared::responses::ErrorCode;
+use shared::*;
+use tide::{Error, Request, StatusCode};
+use uuid::Uuid;
+
+#[async_trait]
+impl BackendApiEndpoint for ListScheduledTweets {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+
+        let tweets = req.state().storage.scheduled_tweets(user.id).await?;
+
+        Ok((tweets, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for UpdateScheduledTweet {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        payload: UpdateScheduledTweetPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = req
+            .param::<Uuid>("id")
+            .map_err(|_| scheduled_tweet_not_found())?;
+        let now = req.state().clock.now();
+
+        let text = payload.text.as_deref().map(validate_text).transpose()?;
+        if let Some(publish_at) = payload.publish_at {
+            validate_publish_at(publish_at, now)?;
+        }
+
+        let tweet = req
+            .state()
+            .storage
+            .update_scheduled_tweet(user.id, id, text.as_deref(), payload.publish_at, now)
+            .await?
+            .ok_or_else(scheduled_tweet_not_found)?;
+
+        Ok((tweet, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for CancelScheduledTweet {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let user = authenticate(&req).await?.user;
+        let id = req
+            .param::<Uuid>("id")
+            .map_err(|_| scheduled_tweet_not_found())?;
+
+        if !req
+            .state()
+            .storage
+            .delete_scheduled_tweet(user.id, id)
+            .await?
+        {
+            return Err(scheduled_tweet_not_found());
+        }
+
+        Ok(((), StatusCode::Ok))
+    }
+}
+
+/// Also used once the tweet has been published, since it can't be changed anymore.
+fn scheduled_tweet_not_found() -> Error {
+    api_error(ErrorCode::NotFound, "Scheduled tweet not found")
+}
@@ -1,55 +1,196 @@
-use crate::endpoints::authenticate;
+use crate::endpoints::{authenticate, media, polls, with_transaction};
+use crate::rate_limit::RateLimitPolicy;
+use crate::responses::{api_error, IntoError};
 use crate::{BackendApiEndpoint, State};
 use async_trait::async_trait;
-use chrono::Utc;
-use shared::MAX_TWEET_LENGTH;
+use chrono::prelude::*;
+use futures::FutureExt;
+use shared::tweet_text::{self, InvalidTweetText};
 use shared::{
-    payloads::CreateTweetPayload,
-    responses::{ApiResponse, PostTweetResponse, TweetResponse},
-    ApiEndpoint, PostTweet,
+    payloads::{CreateTweetPayload, EditTweetPayload},
+    responses::{ApiError, ErrorCode, PostTweetResponse},
+    ApiEndpoint, EditTweet, NoPayload, NoQuery, PostTweet, Scope, TweetHistory,
 };
-use sqlx::query;
+use shared::{MAX_MEDIA_PER_TWEET, MAX_TWEET_LENGTH};
 use tide::{Error, Request, StatusCode};
 use uuid::Uuid;
 
 #[async_trait]
 impl BackendApiEndpoint for PostTweet {
+    const RATE_LIMIT: Option<RateLimitPolicy> = Some(RateLimitPolicy::per_hours(300, 3));
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
     async fn handler(
         req: Request<State>,
         create_tweet: CreateTweetPayload,
+        _: NoQuery,
     ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
-        let db_pool = req.state().db_pool.clone();
+        let text = validate_text(&create_tweet.text)?;
 
-        if create_tweet.text.len() > MAX_TWEET_LENGTH {
-            return Err(Error::from_str(
-                StatusCode::UnprocessableEntity,
-                format!("Tweet is too long. Max then is {}", MAX_TWEET_LENGTH),
-            ));
-        }
+        let user = authenticate(&req).await?.user;
+        let storage = &req.state().storage;
+        let now = req.state().clock.now();
 
-        let user = authenticate(&req).await?;
-
-        let now = crate::clock::current_time().await;
-        let row = query!(
-            r#"
-            insert into tweets (id, user_id, text, created_at, updated_at)
-            values ($1, $2, $3, $4, $5) returning id, text
-        "#,
-            Uuid::new_v4(),
-            user.id,
-            create_tweet.text,
-            now,
-            now,
-        )
-        .fetch_one(&db_pool)
-        .await?;
+        validate_media_ids(&create_tweet)?;
+        let poll_options = create_tweet
+            .poll
+            .as_ref()
+            .map(|poll| polls::validate(poll, create_tweet.publish_at, now))
+            .transpose()?;
+
+        let id = match create_tweet.publish_at {
+            Some(publish_at) => {
+                validate_publish_at(publish_at, now)?;
+                storage
+                    .create_scheduled_tweet(user.id, &text, publish_at, now)
+                    .await?
+            }
+            None => {
+                let (text, create_tweet, poll_options) = (&text, &create_tweet, &poll_options);
+                with_transaction(&**storage, |tx| {
+                    async move {
+                        let id = tx.create_tweet(user.id, text, now).await?;
+                        media::attach(tx, user.id, id, &create_tweet.media_ids).await?;
+                        if let (Some(poll), Some(options)) = (&create_tweet.poll, poll_options) {
+                            tx.create_poll(id, options, poll.closes_at, now).await?;
+                        }
+                        Ok(id)
+                    }
+                    .boxed()
+                })
+                .await?
+            }
+        };
 
         Ok((
             PostTweetResponse {
-                id: row.id,
-                text: row.text,
+                id,
+                text,
+                publish_at: create_tweet.publish_at,
             },
             StatusCode::Created,
         ))
     }
 }
+
+#[async_trait]
+impl BackendApiEndpoint for EditTweet {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::TweetsWrite);
+
+    async fn handler(
+        req: Request<State>,
+        payload: EditTweetPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let text = validate_text(&payload.text)?;
+
+        let user = authenticate(&req).await?.user;
+        let id = tweet_id(&req)?;
+        let storage = &req.state().storage;
+        let now = req.state().clock.now();
+
+        let tweet = storage
+            .find_tweet(id, user.id)
+            .await?
+            .ok_or_else(tweet_not_found)?;
+        if tweet.user.id != user.id {
+            return Err(api_error(
+                ErrorCode::Forbidden,
+                "You can only edit your own tweets",
+            ));
+        }
+        let edit_window = req.state().config.tweets.edit_window();
+        if now - tweet.created_at > edit_window {
+            return Err(api_error(
+                ErrorCode::Forbidden,
+                format!(
+                    "Tweets can only be edited for {} minutes after they're posted",
+                    edit_window.num_minutes()
+                ),
+            ));
+        }
+
+        // Nothing is parsed out of the text yet. Once mentions or hashtags are, they need to be
+        // parsed again here
+        let tweet = storage
+            .edit_tweet(id, &text, now)
+            .await?
+            .ok_or_else(tweet_not_found)?;
+
+        Ok((polls::for_viewer(tweet, now), StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for TweetHistory {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let id = tweet_id(&req)?;
+
+        let history = req.state().storage.tweet_history(id).await?;
+        if history.is_empty() {
+            return Err(tweet_not_found());
+        }
+
+        Ok((history, StatusCode::Ok))
+    }
+}
+
+fn validate_media_ids(create_tweet: &CreateTweetPayload) -> tide::Result<()> {
+    let field_error = if create_tweet.media_ids.len() > MAX_MEDIA_PER_TWEET {
+        format!("can have at most {} items", MAX_MEDIA_PER_TWEET)
+    } else if !create_tweet.media_ids.is_empty() && create_tweet.publish_at.is_some() {
+        "can't be used with scheduled tweets".to_string()
+    } else {
+        return Ok(());
+    };
+
+    Err(
+        ApiError::new(ErrorCode::ValidationFailed, "Media can't be attached")
+            .with_field_error("media_ids", field_error)
+            .into_error(),
+    )
+}
+
+pub fn tweet_id(req: &Request<State>) -> Result<Uuid, Error> {
+    req.param::<Uuid>("id").map_err(|_| tweet_not_found())
+}
+
+pub fn tweet_not_found() -> Error {
+    api_error(ErrorCode::NotFound, "Tweet not found")
+}
+
+/// Returns the text normalized the way it should be stored.
+pub fn validate_text(text: &str) -> tide::Result<String> {
+    tweet_text::validate(text).map_err(|err| match err {
+        InvalidTweetText::Blank => {
+            ApiError::new(ErrorCode::ValidationFailed, "Tweet can't be blank")
+                .with_field_error("text", "can't be blank")
+                .into_error()
+        }
+        InvalidTweetText::TooLong { .. } => ApiError::new(
+            ErrorCode::TweetTooLong,
+            format!("Tweet is too long. Max then is {}", MAX_TWEET_LENGTH),
+        )
+        .with_field_error(
+            "text",
+            format!("must be at most {} characters", MAX_TWEET_LENGTH),
+        )
+        .into_error(),
+    })
+}
+
+pub fn validate_publish_at(publish_at: DateTime<Utc>, now: DateTime<Utc>) -> tide::Result<()> {
+    if publish_at <= now {
+        return Err(ApiError::new(
+            ErrorCode::ValidationFailed,
+            "Tweets can't be scheduled in the past",
+        )
+        .with_field_error("publish_at", "must be in the future")
+        .into_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use super::{authenticate, generate_token, with_transaction};
+use crate::endpoints::users::{auth_token_for_user, login_throttle_keys};
+use crate::login_throttle::ThrottleKey;
+use crate::responses::api_error;
+use crate::storage::{Repositories, Storage};
+use crate::totp;
+use crate::{BackendApiEndpoint, State};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use futures::FutureExt;
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use shared::payloads::{SecondFactorPayload, TwoFactorCodePayload};
+use shared::responses::{
+    ErrorCode, RecoveryCodesResponse, TokenResponse, TwoFactorEnrollmentResponse,
+};
+use shared::*;
+use tide::{Error, Request, StatusCode};
+use uuid::Uuid;
+
+const CHALLENGE_LIFETIME_MINUTES: i64 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[async_trait]
+impl BackendApiEndpoint for EnrollTwoFactor {
+    const ISSUES_CREDENTIALS: bool = true;
+
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let user = authenticate(&req).await?.user;
+
+        if confirmed_secret(user.id, storage).await?.is_some() {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Two-factor authentication is already enabled",
+            ));
+        }
+
+        // Enrolling again before confirming replaces the pending secret
+        let secret = totp::generate_secret();
+        let now = req.state().clock.now();
+        storage
+            .set_pending_totp_secret(user.id, &secret, now)
+            .await?;
+
+        Ok((
+            TwoFactorEnrollmentResponse {
+                provisioning_uri: totp::provisioning_uri(&secret, &user.username),
+                secret,
+            },
+            StatusCode::Created,
+        ))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for ConfirmTwoFactor {
+    const ISSUES_CREDENTIALS: bool = true;
+
+    async fn handler(
+        req: Request<State>,
+        payload: TwoFactorCodePayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let user = authenticate(&req).await?.user;
+
+        let secret = storage.find_totp_secret(user.id).await?.ok_or_else(|| {
+            Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Two-factor authentication has not been enrolled",
+            )
+        })?;
+
+        if secret.confirmed {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Two-factor authentication is already enabled",
+            ));
+        }
+
+        let now = req.state().clock.now();
+        // Codes can be guessed by anyone with the session, so they're throttled like logins
+        let throttle = &req.state().login_throttle;
+        let throttle_keys = login_throttle_keys(&req, &user.username);
+        throttle
+            .check(&throttle_keys, now)
+            .map_err(|err| Error::new(StatusCode::TooManyRequests, err))?;
+
+        let invalid_code =
+            || Error::from_str(StatusCode::UnprocessableEntity, "Invalid two-factor code");
+        let step = match totp::verify(&secret.secret, &payload.code, now) {
+            Some(step) => step,
+            None => {
+                throttle.record_failure(&throttle_keys, now);
+                return Err(invalid_code());
+            }
+        };
+
+        let user_id = user.id;
+        let recovery_codes = with_transaction(storage, |tx| {
+            async move {
+                // So the code can't be used again to log in
+                if !tx.use_totp_step(user_id, step, now).await? {
+                    return Err(invalid_code());
+                }
+                tx.confirm_totp_secret(user_id, now).await?;
+                create_recovery_codes(user_id, now, tx).await
+            }
+            .boxed()
+        })
+        .await?;
+        throttle.record_success(&ThrottleKey::username(&user.username));
+
+        Ok((
+            RecoveryCodesResponse { recovery_codes },
+            StatusCode::Created,
+        ))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for DisableTwoFactor {
+    async fn handler(
+        req: Request<State>,
+        payload: TwoFactorCodePayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let user = authenticate(&req).await?.user;
+
+        let secret = confirmed_secret(user.id, storage).await?.ok_or_else(|| {
+            Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "Two-factor authentication is not enabled",
+            )
+        })?;
+
+        let now = req.state().clock.now();
+        let throttle = &req.state().login_throttle;
+        let throttle_keys = login_throttle_keys(&req, &user.username);
+        throttle
+            .check(&throttle_keys, now)
+            .map_err(|err| Error::new(StatusCode::TooManyRequests, err))?;
+
+        if !verify_second_factor(user.id, &secret, &payload.code, now, storage).await? {
+            throttle.record_failure(&throttle_keys, now);
+            return Err(invalid_two_factor_code());
+        }
+        throttle.record_success(&ThrottleKey::username(&user.username));
+
+        storage.delete_two_factor(user.id).await?;
+
+        Ok(((), StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for CompleteTwoFactorLogin {
+    const ISSUES_CREDENTIALS: bool = true;
+
+    async fn handler(
+        req: Request<State>,
+        payload: SecondFactorPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let username = req.param::<String>("username")?;
+        let throttle = &req.state().login_throttle;
+        let throttle_keys = login_throttle_keys(&req, &username);
+        let now = req.state().clock.now();
+
+        throttle
+            .check(&throttle_keys, now)
+            .map_err(|err| Error::new(StatusCode::TooManyRequests, err))?;
+
+        let invalid_challenge = || {
+            api_error(
+                ErrorCode::InvalidToken,
+                "Invalid or expired challenge token",
+            )
+        };
+        let user_id = storage
+            .find_second_factor_challenge(&payload.challenge_token, &username, now)
+            .await?
+            .ok_or_else(invalid_challenge)?;
+        let secret = confirmed_secret(user_id, storage)
+            .await?
+            .ok_or_else(invalid_challenge)?;
+
+        if !verify_second_factor(user_id, &secret, &payload.code, now, storage).await? {
+            throttle.record_failure(&throttle_keys, now);
+            return Err(invalid_two_factor_code());
+        }
+        throttle.record_success(&ThrottleKey::username(&username));
+
+        storage
+            .delete_second_factor_challenge(&payload.challenge_token)
+            .await?;
+
+        let token = auth_token_for_user(user_id, now, storage).await?;
+
+        Ok((TokenResponse::new(&token), StatusCode::Created))
+    }
+}
+
+pub async fn confirmed_secret(
+    user_id: Uuid,
+    storage: &dyn Storage,
+) -> tide::Result<Option<String>> {
+    let secret = storage.find_totp_secret(user_id).await?;
+    Ok(secret
+        .filter(|secret| secret.confirmed)
+        .map(|secret| secret.secret))
+}
+
+pub async fn create_challenge(
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    storage: &dyn Storage,
+) -> tide::Result<String> {
+    let token = generate_token();
+    storage
+        .create_second_factor_challenge(
+            user_id,
+            &token,
+            now + Duration::minutes(CHALLENGE_LIFETIME_MINUTES),
+            now,
+        )
+        .await?;
+
+    Ok(token)
+}
+
+/// Checks `code` against the user's authenticator app, falling back to their unused recovery
+/// codes. Either kind of code can only be used once.
+async fn verify_second_factor(
+    user_id: Uuid,
+    secret: &str,
+    code: &str,
+    now: DateTime<Utc>,
+    storage: &dyn Storage,
+) -> tide::Result<bool> {
+    if let Some(step) = totp::verify(secret, code, now) {
+        if storage.use_totp_step(user_id, step, now).await? {
+            return Ok(true);
+        }
+    }
+
+    let used = storage
+        .use_recovery_code(user_id, &hash_recovery_code(code), now)
+        .await?;
+    Ok(used)
+}
+
+async fn create_recovery_codes(
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    storage: &dyn Repositories,
+) -> tide::Result<Vec<String>> {
+    let codes = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect::<Vec<_>>();
+    let hashed_codes = codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect::<Vec<_>>();
+    storage
+        .replace_recovery_codes(user_id, &hashed_codes, now)
+        .await?;
+
+    Ok(codes)
+}
+
+fn generate_recovery_code() -> String {
+    let chars: String = OsRng
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .collect::<String>()
+        .to_lowercase();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn invalid_two_factor_code() -> Error {
+    api_error(ErrorCode::InvalidTwoFactorCode, "Invalid two-factor code")
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalized = code.trim().to_lowercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
@@ -1,282 +1,352 @@
-use super::{authenticate, empty_response, get_auth_token, something_went_wrong};
-use crate::env;
-use crate::responses::BuildApiResponse;
+use super::{
+    authenticate, client_ip, generate_token, get_auth_token, two_factor, with_transaction,
+};
+use crate::config::Config;
+use crate::login_throttle::ThrottleKey;
+use crate::rate_limit::RateLimitPolicy;
+use crate::responses::{api_error, IntoError};
+use crate::storage::{Repositories, Storage, StorageError};
 use crate::{BackendApiEndpoint, State};
 use argonautica::{Hasher, Verifier};
 use async_trait::async_trait;
 use chrono::prelude::*;
 use failure::Fail;
 use futures::compat::Compat01As03;
-use rand::distributions::Alphanumeric;
-use rand::rngs::OsRng;
-use rand::Rng;
-use serde_json::Value;
+use futures::FutureExt;
 use shared::payloads::CreateUserPayload;
 use shared::payloads::LoginPayload;
 use shared::{
-    responses::{TokenResponse, UserResponse},
+    responses::{
+        ApiError, ErrorCode, LoginResponse, SecondFactorChallengeResponse, TokenResponse,
+        UserResponse,
+    },
     *,
 };
-use sqlx::{query, query_as, PgPool};
+
 use tide::Request;
 use tide::{Error, StatusCode};
 use uuid::Uuid;
 
 #[async_trait]
 impl BackendApiEndpoint for CreateUser {
+    const RATE_LIMIT: Option<RateLimitPolicy> = Some(RateLimitPolicy::per_hours(5, 1));
+    const ISSUES_CREDENTIALS: bool = true;
+
     async fn handler(
         req: Request<State>,
         create_user: CreateUserPayload,
+        _: NoQuery,
     ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
-        let db_pool = &req.state().db_pool;
-
-        if username_already_claimed(&create_user.username, &db_pool).await? {
-            return Err(Error::from_str(
-                StatusCode::UnprocessableEntity,
-                "Username is already claimed",
-            ));
-        }
+        let storage = &*req.state().storage;
 
-        let secret_key = std::env::var("SECRET_KEY")?;
-        let clear_text_password = create_user.password.clone();
-        let mut hasher = Hasher::default();
+        validate_credentials(&create_user)?;
 
-        if env::current().is_test() {
-            hasher.configure_iterations(1);
+        if username_already_claimed(&create_user.username, storage).await? {
+            return Err(username_taken());
         }
 
-        let hashed_password = Compat01As03::new(
-            hasher
-                .with_password(clear_text_password)
-                .with_secret_key(secret_key)
-                .hash_non_blocking(),
-        )
-        .await
-        .map_err(|err| err.compat())?;
-
-        let now = crate::clock::current_time().await;
-        let row = query!(
-            r#"
-            insert into users (id, username, hashed_password, created_at, updated_at)
-            values ($1, $2, $3, $4, $5) returning id
-        "#,
-            Uuid::new_v4(),
-            create_user.username,
-            hashed_password,
-            now,
-            now,
-        )
-        .fetch_one(db_pool)
-        .await?;
-        let user_id = row.id;
-
-        let raw_token: String = OsRng.sample_iter(&Alphanumeric).take(32).collect();
-        let token = query!(
-            r#"
-                    insert into auth_tokens (
-                        id,
-                        user_id,
-                        token,
-                        created_at,
-                        updated_at
-                    )
-                    values ($1, $2, $3, $4, $5) returning token
-                "#,
-            Uuid::new_v4(),
-            user_id,
-            raw_token,
-            now,
-            now,
-        )
-        .fetch_one(db_pool)
+        let hashed_password = hash_password(&create_user.password, &req.state().config).await?;
+
+        let now = req.state().clock.now();
+        let username = &create_user.username;
+        let hashed_password = &hashed_password;
+        let token = with_transaction(storage, |tx| {
+            async move {
+                // Someone else can claim the username after the check above
+                let user_id = tx
+                    .create_user(username, hashed_password, now)
+                    .await
+                    .map_err(|err| match err {
+                        StorageError::UniqueViolation { .. } => username_taken(),
+                        err => err.into(),
+                    })?;
+
+                create_auth_token(user_id, now, tx).await
+            }
+            .boxed()
+        })
         .await?;
 
-        Ok((TokenResponse::new(&token.token), StatusCode::Created))
+        Ok((TokenResponse::new(&token), StatusCode::Created))
     }
 }
 
-async fn username_already_claimed(username: &str, db_pool: &PgPool) -> tide::Result<bool> {
-    let row = query!("select 1 as one from users where username = $1", username)
-        .fetch_optional(db_pool)
-        .await?;
+fn validate_credentials(create_user: &CreateUserPayload) -> tide::Result<()> {
+    let username_errors = credentials::username_errors(&create_user.username);
+    let password_errors =
+        credentials::password_errors(&create_user.password, &create_user.username);
+    if username_errors.is_empty() && password_errors.is_empty() {
+        return Ok(());
+    }
 
-    Ok(row.is_some())
+    let mut error = ApiError::new(
+        ErrorCode::ValidationFailed,
+        "Username or password isn't allowed",
+    );
+    for message in username_errors {
+        error = error.with_field_error("username", message);
+    }
+    for message in password_errors {
+        error = error.with_field_error("password", message);
+    }
+    Err(error.into_error())
+}
+
+fn username_taken() -> Error {
+    ApiError::new(ErrorCode::UsernameTaken, "Username is already claimed")
+        .with_field_error("username", "is already claimed")
+        .into_error()
+}
+
+async fn username_already_claimed(username: &str, storage: &dyn Storage) -> tide::Result<bool> {
+    let user = storage.find_user_by_username(username).await?;
+    Ok(user.is_some())
 }
 
 #[async_trait]
 impl BackendApiEndpoint for Login {
+    const ISSUES_CREDENTIALS: bool = true;
+
     async fn handler(
         req: Request<State>,
         payload: LoginPayload,
+        _: NoQuery,
     ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
         let username = req.param::<String>("username")?;
         let password = payload.password;
 
-        let db_pool = req.state().db_pool.clone();
-
-        let user = query!(
-            r#"
-            select id, hashed_password
-            from users
-            where username = $1
-        "#,
-            username
-        )
-        .fetch_optional(&db_pool)
-        .await?;
+        // No account can have a longer password, so don't spend time hashing it
+        if password.chars().count() > credentials::MAX_PASSWORD_LENGTH {
+            return Err(invalid_credentials());
+        }
+
+        let storage = &*req.state().storage;
+        let config = &req.state().config;
+        let throttle = &req.state().login_throttle;
+        let throttle_keys = login_throttle_keys(&req, &username);
+        let now = req.state().clock.now();
+
+        throttle
+            .check(&throttle_keys, now)
+            .map_err(|err| Error::new(StatusCode::TooManyRequests, err))?;
+
+        let user = storage.find_user_by_username(&username).await?;
+
+        let is_valid = match &user {
+            Some(user) => verify_password(&user.hashed_password, &password, config).await?,
+            None => {
+                // Hash the password anyway so unknown usernames take as long as wrong passwords
+                hash_password(&password, config).await?;
+                false
+            }
+        };
+
         let user = match user {
-            Some(user) => user,
-            None => return Err(Error::from_str(StatusCode::NotFound, "User not found")),
+            Some(user) if is_valid => user,
+            _ => {
+                throttle.record_failure(&throttle_keys, now);
+                return Err(invalid_credentials());
+            }
         };
-        let user_password = user.hashed_password;
-
-        let secret_key = std::env::var("SECRET_KEY")?;
-        let mut verifier = Verifier::default();
-        let is_valid = Compat01As03::new(
-            verifier
-                .with_hash(user_password)
-                .with_password(password)
-                .with_secret_key(secret_key)
-                .verify_non_blocking(),
-        )
-        .await
-        .map_err(|err| err.compat())?;
-
-        if is_valid {
-            let token_row = query!(
-                r#"
-                select token
-                from auth_tokens
-                where user_id = $1
-            "#,
-                user.id
-            )
-            .fetch_one(&db_pool)
-            .await?;
 
-            Ok((TokenResponse::new(&token_row.token), StatusCode::Created))
-        } else {
-            Err(something_went_wrong(StatusCode::Forbidden))
+        // Failures are only forgotten once the second factor has been verified as well, otherwise
+        // someone who knows the password could guess codes indefinitely
+        if two_factor::confirmed_secret(user.id, storage)
+            .await?
+            .is_some()
+        {
+            let challenge_token = two_factor::create_challenge(user.id, now, storage).await?;
+            return Ok((
+                LoginResponse::SecondFactorRequired(SecondFactorChallengeResponse {
+                    challenge_token,
+                }),
+                StatusCode::Ok,
+            ));
         }
+
+        throttle.record_success(&ThrottleKey::username(&username));
+
+        let token = auth_token_for_user(user.id, now, storage).await?;
+        Ok((
+            LoginResponse::Token(TokenResponse::new(&token)),
+            StatusCode::Created,
+        ))
     }
 }
 
-pub async fn follow(req: Request<State>) -> tide::Result {
-    let db_pool = req.state().db_pool.clone();
-    let current_user = authenticate(&req).await?;
-    let username = req.param::<String>("username")?;
+pub fn login_throttle_keys(req: &Request<State>, username: &str) -> Vec<ThrottleKey> {
+    let mut keys = vec![ThrottleKey::username(username)];
+    if let Some(ip) = client_ip(req) {
+        keys.push(ThrottleKey::ip(ip));
+    }
+    keys
+}
 
-    let row = query!("select id from users where username = $1", username)
-        .fetch_optional(&db_pool)
-        .await?;
-    let followee_id: Uuid = if let Some(row) = row { row.id } else { todo!() };
+fn invalid_credentials() -> Error {
+    api_error(
+        ErrorCode::InvalidCredentials,
+        "Invalid username or password",
+    )
+}
 
-    if current_user.id == followee_id {
-        return Err(Error::from_str(
-            StatusCode::UnprocessableEntity,
-            "You cannot follow yourself",
-        ));
+async fn hash_password(password: &str, config: &Config) -> tide::Result<String> {
+    let mut hasher = Hasher::default();
+    hasher
+        .configure_iterations(config.argon2.iterations)
+        .configure_memory_size(config.argon2.memory_size);
+    if let Some(lanes) = config.argon2.lanes {
+        hasher.configure_lanes(lanes);
     }
 
-    if user_following(current_user.id, followee_id, &db_pool).await? {
-        return Err(Error::from_str(
-            StatusCode::UnprocessableEntity,
-            "You cannot follow the same user twice",
-        ));
-    }
+    let hashed_password = Compat01As03::new(
+        hasher
+            .with_password(password.to_string())
+            .with_secret_key(config.secret_key.expose())
+            .hash_non_blocking(),
+    )
+    .await
+    .map_err(|err| err.compat())?;
+
+    Ok(hashed_password)
+}
 
-    let now = crate::clock::current_time().await;
-    let rows_inserted = query!(
-        r#"
-            insert into follows (id, follower_id, followee_id, created_at, updated_at)
-            values ($1, $2, $3, $4, $5)
-        "#,
-        Uuid::new_v4(),
-        current_user.id,
-        followee_id,
-        now,
-        now,
+/// The parameters are stored in the hash, so only the secret key is needed.
+async fn verify_password(
+    hashed_password: &str,
+    password: &str,
+    config: &Config,
+) -> tide::Result<bool> {
+    let mut verifier = Verifier::default();
+    let is_valid = Compat01As03::new(
+        verifier
+            .with_hash(hashed_password)
+            .with_password(password.to_string())
+            .with_secret_key(config.secret_key.expose())
+            .verify_non_blocking(),
     )
-    .execute(&db_pool)
-    .await?;
+    .await
+    .map_err(|err| err.compat())?;
+
+    Ok(is_valid)
+}
 
-    if rows_inserted == 1 {
-        Value::Null.to_response_with_status(StatusCode::Created)
-    } else {
-        todo!()
+pub async fn auth_token_for_user(
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    storage: &(impl Repositories + ?Sized),
+) -> tide::Result<String> {
+    match storage.find_auth_token(user_id).await? {
+        Some(token) => Ok(token),
+        None => create_auth_token(user_id, now, storage).await,
     }
 }
 
-pub async fn following(req: Request<State>) -> tide::Result {
-    let db_pool = req.state().db_pool.clone();
-    let username = req.param::<String>("username")?;
+async fn create_auth_token(
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    storage: &(impl Repositories + ?Sized),
+) -> tide::Result<String> {
+    let token = generate_token();
+    storage.create_auth_token(user_id, &token, now).await?;
+    Ok(token)
+}
 
-    // TODO: extract this into function
-    let row = query!("select id from users where username = $1", username)
-        .fetch_optional(&db_pool)
-        .await?;
-    let user_id: Uuid = if let Some(row) = row { row.id } else { todo!() };
+#[async_trait]
+impl BackendApiEndpoint for Follow {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::FollowsWrite);
 
-    let rows = query_as!(
-        UserResponse,
-        r#"
-            select users.id, users.username
-            from users
-            inner join follows on
-                follows.follower_id = $1
-                and follows.followee_id = users.id
-        "#,
-        user_id,
-    )
-    .fetch_all(&db_pool)
-    .await?;
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let current_user = authenticate(&req).await?.user;
+        let followee_id = find_user_id(&req.param::<String>("username")?, storage).await?;
 
-    rows.to_response()
+        if current_user.id == followee_id {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "You cannot follow yourself",
+            ));
+        }
+
+        if storage.is_following(current_user.id, followee_id).await? {
+            return Err(Error::from_str(
+                StatusCode::UnprocessableEntity,
+                "You cannot follow the same user twice",
+            ));
+        }
+
+        let now = req.state().clock.now();
+        storage
+            .create_follow(current_user.id, followee_id, now)
+            .await?;
+
+        Ok(((), StatusCode::Created))
+    }
 }
 
-pub async fn followers(req: Request<State>) -> tide::Result {
-    let db_pool = req.state().db_pool.clone();
-    let username = req.param::<String>("username")?;
+#[async_trait]
+impl BackendApiEndpoint for Unfollow {
+    const REQUIRED_SCOPE: Option<Scope> = Some(Scope::FollowsWrite);
 
-    let row = query!("select id from users where username = $1", username)
-        .fetch_optional(&db_pool)
-        .await?;
-    let user_id: Uuid = if let Some(row) = row { row.id } else { todo!() };
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let current_user = authenticate(&req).await?.user;
+        let followee_id = find_user_id(&req.param::<String>("username")?, storage).await?;
 
-    let rows = query_as!(
-        UserResponse,
-        r#"
-            select users.id, users.username
-            from users
-            inner join follows on
-                follows.followee_id = $1
-                and follows.follower_id = users.id
-        "#,
-        user_id,
-    )
-    .fetch_all(&db_pool)
-    .await?;
+        if !storage.delete_follow(current_user.id, followee_id).await? {
+            return Err(Error::from_str(
+                StatusCode::NotFound,
+                "You are not following that user",
+            ));
+        }
 
-    rows.to_response()
+        Ok(((), StatusCode::Ok))
+    }
 }
 
-async fn user_following(
-    current_user_id: Uuid,
-    followee_id: Uuid,
-    db_pool: &PgPool,
-) -> tide::Result<bool> {
-    let row = query!(
-        r#"
-        select 1 as one from follows
-        where follower_id = $1 and followee_id = $2
-    "#,
-        current_user_id,
-        followee_id
-    )
-    .fetch_optional(db_pool)
-    .await?;
+async fn find_user_id(username: &str, storage: &dyn Storage) -> tide::Result<Uuid> {
+    let user = storage.find_user_by_username(username).await?;
+    user.map(|user| user.id)
+        .ok_or_else(|| Error::from_str(StatusCode::NotFound, "User not found"))
+}
+
+#[async_trait]
+impl BackendApiEndpoint for Following {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let user_id = find_user_id(&req.param::<String>("username")?, storage).await?;
+
+        let users = storage.following(user_id).await?;
+
+        Ok((users, StatusCode::Ok))
+    }
+}
+
+#[async_trait]
+impl BackendApiEndpoint for Followers {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let storage = &*req.state().storage;
+        let user_id = find_user_id(&req.param::<String>("username")?, storage).await?;
+
+        let users = storage.followers(user_id).await?;
 
-    Ok(row.is_some())
+        Ok((users, StatusCode::Ok))
+    }
 }
 
 #[async_trait]
@@ -284,35 +354,33 @@ impl BackendApiEndpoint for GetUser {
     async fn handler(
         req: Request<State>,
         _: NoPayload,
+        _: NoQuery,
     ) -> tide::Result<(UserResponse, StatusCode)> {
-        let db_pool = &req.state().db_pool;
         let username = req.param::<String>("username")?;
-
-        let user = query_as!(
-            UserResponse,
-            r#"
-            select id, username
-            from users
-            where username = $1
-        "#,
-            username
-        )
-        .fetch_optional(db_pool)
-        .await?;
-
-        let resp = user.ok_or_else(|| Error::from_str(StatusCode::NotFound, "User not found"))?;
+        let user = req.state().storage.find_user_by_username(&username).await?;
+
+        let resp = user
+            .map(|user| UserResponse {
+                id: user.id,
+                username: user.username,
+            })
+            .ok_or_else(|| Error::from_str(StatusCode::NotFound, "User not found"))?;
         Ok((resp, StatusCode::Ok))
     }
 }
 
-pub async fn logout(req: Request<State>) -> tide::Result {
-    let _ = authenticate(&req).await?;
-    let auth_token = get_auth_token(&req)?;
+#[async_trait]
+impl BackendApiEndpoint for Logout {
+    async fn handler(
+        req: Request<State>,
+        _: NoPayload,
+        _: NoQuery,
+    ) -> tide::Result<(<Self as ApiEndpoint>::Response, StatusCode)> {
+        let _ = authenticate(&req).await?;
+        let auth_token = get_auth_token(&req)?;
 
-    let db_pool = &req.state().db_pool;
-    query!("delete from auth_tokens where token = $1", auth_token)
-        .execute(db_pool)
-        .await?;
+        req.state().storage.delete_auth_token(auth_token).await?;
 
-    empty_response()
+        Ok(((), StatusCode::Ok))
+    }
 }
@@ -1,25 +1,27 @@
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Env {
     Production,
     Development,
     Test,
 }
 
-impl Env {
-    pub fn is_test(&self) -> bool {
-        match self {
-            Env::Test => true,
-            _ => false,
-        }
-    }
-}
+impl FromStr for Env {
+    type Err = String;
 
-pub fn current() -> Env {
-    match std::env::var("APP_ENV").unwrap().as_str() {
-        "production" => Env::Production,
-        "development" => Env::Development,
-        "test" => Env::Test,
-        _ => panic!(),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "production" => Ok(Env::Production),
+            "development" => Ok(Env::Development),
+            "test" => Ok(Env::Test),
+            other => Err(format!(
+                "`{}` isn't one of production, development or test",
+                other
+            )),
+        }
     }
 }
 
@@ -27,14 +29,17 @@ pub fn current() -> Env {
 mod test {
     #[allow(unused_imports)]
     use super::*;
-    use crate::tests::test_helpers::*;
+    use crate::config::Config;
 
-    #[async_std::test]
-    async fn in_test_env_during_tests() {
-        test_setup().await;
+    #[test]
+    fn parsing() {
+        assert_eq!("test".parse(), Ok(Env::Test));
+        assert_eq!("production".parse(), Ok(Env::Production));
+        assert!("Test".parse::<Env>().is_err());
+    }
 
-        let env = current();
-        assert_eq!(env, Env::Test);
-        assert!(env.is_test());
+    #[test]
+    fn in_test_env_during_tests() {
+        assert_eq!(Config::for_tests().env, Env::Test);
     }
 }
@@ -0,0 +1,236 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Tracks failed login attempts per username and per IP address and locks them out temporarily
+/// once they fail too many times in a row. Each further failure doubles the lockout.
+#[derive(Debug, Clone, Default)]
+pub struct LoginThrottle {
+    attempts: Arc<Mutex<HashMap<ThrottleKey, FailedAttempts>>>,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum ThrottleKey {
+    Username(String),
+    Ip(String),
+}
+
+impl ThrottleKey {
+    pub fn username(username: &str) -> Self {
+        ThrottleKey::Username(username.to_lowercase())
+    }
+
+    pub fn ip(ip: &str) -> Self {
+        ThrottleKey::Ip(ip.to_string())
+    }
+
+    fn policy(&self) -> Policy {
+        match self {
+            ThrottleKey::Username(_) => USERNAME_POLICY,
+            // Many users might share an IP so we allow more attempts
+            ThrottleKey::Ip(_) => IP_POLICY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Policy {
+    max_attempts: u32,
+    base_lockout_seconds: i64,
+    max_lockout_seconds: i64,
+    forget_after_seconds: i64,
+}
+
+const USERNAME_POLICY: Policy = Policy {
+    max_attempts: 5,
+    base_lockout_seconds: 60,
+    max_lockout_seconds: 60 * 60,
+    forget_after_seconds: 60 * 60,
+};
+
+const IP_POLICY: Policy = Policy {
+    max_attempts: 20,
+    base_lockout_seconds: 60,
+    max_lockout_seconds: 60 * 60,
+    forget_after_seconds: 60 * 60,
+};
+
+/// Once we're tracking this many keys we start throwing away the ones that have been forgotten.
+const MAX_KEYS_BEFORE_PRUNING: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct FailedAttempts {
+    count: u32,
+    last_failure_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl FailedAttempts {
+    fn is_forgotten(&self, policy: Policy, now: DateTime<Utc>) -> bool {
+        now - self.last_failure_at > Duration::seconds(policy.forget_after_seconds)
+            && !matches!(self.locked_until, Some(locked_until) if locked_until > now)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Too many failed login attempts. Try again in {} seconds", .retry_after.num_seconds())]
+pub struct LockedOut {
+    pub retry_after: Duration,
+}
+
+impl LoginThrottle {
+    pub fn check(&self, keys: &[ThrottleKey], now: DateTime<Utc>) -> Result<(), LockedOut> {
+        let attempts = self.attempts.lock().unwrap();
+
+        let retry_after = keys
+            .iter()
+            .filter_map(|key| attempts.get(key)?.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+            .max();
+
+        match retry_after {
+            Some(retry_after) => Err(LockedOut { retry_after }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, keys: &[ThrottleKey], now: DateTime<Utc>) {
+        let mut attempts = self.attempts.lock().unwrap();
+
+        if attempts.len() >= MAX_KEYS_BEFORE_PRUNING {
+            attempts.retain(|key, entry| !entry.is_forgotten(key.policy(), now));
+        }
+
+        for key in keys {
+            let policy = key.policy();
+            let entry = attempts.entry(key.clone()).or_insert(FailedAttempts {
+                count: 0,
+                last_failure_at: now,
+                locked_until: None,
+            });
+
+            if entry.is_forgotten(policy, now) {
+                entry.count = 0;
+                entry.locked_until = None;
+            }
+
+            entry.count += 1;
+            entry.last_failure_at = now;
+
+            if entry.count >= policy.max_attempts {
+                let doublings = (entry.count - policy.max_attempts).min(16);
+                let lockout_seconds =
+                    (policy.base_lockout_seconds << doublings).min(policy.max_lockout_seconds);
+                entry.locked_until = Some(now + Duration::seconds(lockout_seconds));
+
+                log::warn!(
+                    "Locking out logins for {:?} for {} seconds after {} failed attempts",
+                    key,
+                    lockout_seconds,
+                    entry.count,
+                );
+            }
+        }
+    }
+
+    pub fn record_success(&self, key: &ThrottleKey) {
+        self.attempts.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn locks_out_after_too_many_failures() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::username("bob")];
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for _ in 0..4 {
+            throttle.record_failure(&keys, now);
+            assert!(throttle.check(&keys, now).is_ok());
+        }
+
+        throttle.record_failure(&keys, now);
+        let err = throttle.check(&keys, now).unwrap_err();
+        assert_eq!(err.retry_after, Duration::seconds(60));
+
+        let later = now + Duration::seconds(61);
+        assert!(throttle.check(&keys, later).is_ok());
+
+        throttle.record_failure(&keys, later);
+        let err = throttle.check(&keys, later).unwrap_err();
+        assert_eq!(err.retry_after, Duration::seconds(120));
+    }
+
+    #[test]
+    fn usernames_are_case_insensitive() {
+        let throttle = LoginThrottle::default();
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for _ in 0..5 {
+            throttle.record_failure(&[ThrottleKey::username("Bob")], now);
+        }
+
+        assert!(throttle
+            .check(&[ThrottleKey::username("bob")], now)
+            .is_err());
+    }
+
+    #[test]
+    fn failures_are_forgotten_after_a_while() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::username("bob")];
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for _ in 0..4 {
+            throttle.record_failure(&keys, now);
+        }
+
+        let later = now + Duration::hours(2);
+        throttle.record_failure(&keys, later);
+        assert!(throttle.check(&keys, later).is_ok());
+    }
+
+    #[test]
+    fn forgotten_failures_are_pruned() {
+        let throttle = LoginThrottle::default();
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for i in 0..MAX_KEYS_BEFORE_PRUNING {
+            throttle.record_failure(&[ThrottleKey::ip(&i.to_string())], now);
+        }
+        // Nothing has been forgotten yet
+        let soon = now + Duration::minutes(30);
+        throttle.record_failure(&[ThrottleKey::username("bob")], soon);
+        assert_eq!(
+            throttle.attempts.lock().unwrap().len(),
+            MAX_KEYS_BEFORE_PRUNING + 1
+        );
+
+        let later = now + Duration::hours(2);
+        throttle.record_failure(&[ThrottleKey::username("alice")], later);
+        assert_eq!(throttle.attempts.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::username("bob")];
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for _ in 0..4 {
+            throttle.record_failure(&keys, now);
+        }
+        throttle.record_success(&keys[0]);
+        throttle.record_failure(&keys, now);
+
+        assert!(throttle.check(&keys, now).is_ok());
+    }
+}
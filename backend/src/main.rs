@@ -1,11 +1,18 @@
 use dotenv;
 
 use async_trait::async_trait;
-use payloads::*;
+use blob_store::{BlobStore, LocalBlobStore};
+use clock::{Clock, SystemClock};
+use config::{Args, Command, Config, Features, RateLimitsConfig};
+use rate_limit::{InMemoryRateLimitStore, RateLimitPolicy, RateLimitStore};
 use shared::*;
 use sqlx::PgPool;
-use sqlx::Pool;
-use tide::http::{headers::HeaderValue, Method};
+use std::path::PathBuf;
+use std::sync::Arc;
+use storage::{PgStorage, Storage};
+use structopt::StructOpt;
+use tide::http::headers::HeaderValue;
+use tide::http::Method;
 use tide::security::CorsMiddleware;
 use tide::security::Origin;
 use tide::{Body, Request, Response, Server, StatusCode};
@@ -13,30 +20,93 @@ use tide::{Body, Request, Response, Server, StatusCode};
 #[cfg(test)]
 mod tests;
 
+mod blob_store;
 mod clock;
+mod config;
 mod endpoints;
 mod env;
+mod login_throttle;
+mod media;
 mod middlewares;
+mod openapi;
+mod rate_limit;
 mod responses;
+mod scheduler;
+mod storage;
+mod totp;
 
 #[async_std::main]
 async fn main() {
+    let args = Args::from_args();
+    if let Some(Command::Openapi { path }) = args.command {
+        write_api_docs(path);
+        return;
+    }
+
     dotenv::dotenv().ok();
     pretty_env_logger::init();
 
-    let db_pool = make_db_pool().await;
-    let app = server(db_pool).await;
+    let config = match Config::load(&args, |name| std::env::var(name).ok()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let db_pool = make_db_pool(&config).await;
+    let storage: Arc<dyn Storage> = Arc::new(PgStorage::new(db_pool));
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let blob_store: Arc<dyn BlobStore> =
+        Arc::new(LocalBlobStore::new(config.media.directory.clone()));
+    let bind_address = config.bind_address;
+
+    async_std::task::spawn(scheduler::run(
+        storage.clone(),
+        clock.clone(),
+        config.scheduler.interval(),
+    ));
+
+    let app = server(config, storage, clock, blob_store).await;
+
+    app.listen(bind_address).await.unwrap();
+}
 
-    app.listen("127.0.0.1:8080").await.unwrap();
+/// Writes the OpenAPI document to `path`, or stdout if there is no path. Documents every
+/// endpoint, including the ones behind disabled features.
+fn write_api_docs(path: Option<PathBuf>) {
+    let api_docs = openapi::document(&Features::default());
+    let api_docs = serde_json::to_string_pretty(&api_docs).unwrap();
+    match path {
+        Some(path) => std::fs::write(path, api_docs).unwrap(),
+        None => println!("{}", api_docs),
+    }
 }
 
-pub async fn make_db_pool() -> PgPool {
-    let db_url = std::env::var("DATABASE_URL").unwrap();
-    Pool::new(&db_url).await.unwrap()
+async fn make_db_pool(config: &Config) -> PgPool {
+    PgPool::builder()
+        .max_size(config.db_pool.max_size)
+        .min_size(config.db_pool.min_size)
+        .build(config.database_url.expose())
+        .await
+        .unwrap()
 }
 
-async fn server(db_pool: PgPool) -> Server<State> {
-    let mut server: Server<State> = Server::with_state(State { db_pool });
+async fn server(
+    config: Config,
+    storage: Arc<dyn Storage>,
+    clock: Arc<dyn Clock>,
+    blob_store: Arc<dyn BlobStore>,
+) -> Server<State> {
+    let config = Arc::new(config);
+    let mut server: Server<State> = Server::with_state(State {
+        config: config.clone(),
+        clock,
+        storage,
+        blob_store,
+        login_throttle: Default::default(),
+        rate_limit_store: Arc::new(InMemoryRateLimitStore::default()),
+    });
 
     server.with(
         CorsMiddleware::new()
@@ -45,103 +115,228 @@ async fn server(db_pool: PgPool) -> Server<State> {
                     .parse::<HeaderValue>()
                     .unwrap(),
             )
-            .allow_origin(Origin::Any),
+            .allow_origin(Origin::from(config.cors.allowed_origins.clone())),
     );
     server.with(middlewares::ErrorReponseToJson);
 
-    add_endpoint::<CreateUser>(&mut server);
+    let mut routes = Routes {
+        server: &mut server,
+        rate_limits: &config.rate_limits,
+    };
+    add_endpoints(&mut routes, &config.features);
 
-    add_endpoint::<Login>(&mut server);
+    // Routes that can't be `ApiEndpoint`s since other specs define their requests and
+    // responses, or since they aren't JSON. These need to be listed in `tests::routes`.
+    if config.features.oauth {
+        server.at("/oauth/token").post(endpoints::oauth::token);
+        server.at("/oauth/revoke").post(endpoints::oauth::revoke);
+    }
 
+    let mut upload = server.at("/media");
+    let upload_rate_limit = config.rate_limits.policy_named(
+        endpoints::media::UPLOAD_NAME,
+        Some(endpoints::media::UPLOAD_RATE_LIMIT),
+    );
+    if let Some(policy) = upload_rate_limit {
+        upload.with(middlewares::RateLimit::new(Method::Post, "/media", policy));
+    }
+    upload.post(endpoints::media::upload);
+    server.at("/media/:id").get(endpoints::media::serve);
     server
-        .at("/users/:username/session")
-        .delete(endpoints::users::logout);
+        .at("/media/:id/thumbnail")
+        .get(endpoints::media::serve_thumbnail);
+
+    let api_docs = Arc::new(openapi::document(&config.features));
+    server.at("/openapi.json").get(move |_| {
+        let api_docs = api_docs.clone();
+        async move {
+            let mut resp = Response::new(StatusCode::Ok);
+            resp.set_body(Body::from_json(&*api_docs)?);
+            Ok(resp)
+        }
+    });
 
     server
-        .at("/users/:username/follow")
-        .post(endpoints::users::follow);
-    server
-        .at("/users/:username/following")
-        .get(endpoints::users::following);
-    server
-        .at("/users/:username/followers")
-        .get(endpoints::users::followers);
+}
 
-    add_endpoint::<GetUser>(&mut server);
+#[derive(Debug, Clone)]
+pub struct State {
+    config: Arc<Config>,
+    clock: Arc<dyn Clock>,
+    storage: Arc<dyn Storage>,
+    blob_store: Arc<dyn BlobStore>,
+    login_throttle: login_throttle::LoginThrottle,
+    rate_limit_store: Arc<dyn RateLimitStore>,
+}
 
-    add_endpoint::<Me>(&mut server);
-    add_endpoint::<Timeline>(&mut server);
+/// Endpoints are added through this so the same list is used for routing and for the API docs.
+trait Endpoints {
+    fn add<E>(&mut self)
+    where
+        E: 'static + BackendApiEndpoint,
+        E::Payload: Send,
+        E::Query: Send;
+}
 
-    add_endpoint::<PostTweet>(&mut server);
+struct Routes<'a> {
+    server: &'a mut Server<State>,
+    rate_limits: &'a RateLimitsConfig,
+}
 
-    server
+impl Endpoints for Routes<'_> {
+    fn add<E>(&mut self)
+    where
+        E: 'static + BackendApiEndpoint,
+        E::Payload: Send,
+        E::Query: Send,
+    {
+        add_endpoint::<E>(self.server, self.rate_limits.policy::<E>())
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct State {
-    db_pool: PgPool,
+fn add_endpoints(endpoints: &mut impl Endpoints, features: &Features) {
+    if features.signups {
+        endpoints.add::<CreateUser>();
+    }
+
+    endpoints.add::<Login>();
+    endpoints.add::<CompleteTwoFactorLogin>();
+    endpoints.add::<Logout>();
+
+    endpoints.add::<GetUser>();
+    endpoints.add::<Follow>();
+    endpoints.add::<Unfollow>();
+    endpoints.add::<Following>();
+    endpoints.add::<Followers>();
+
+    endpoints.add::<Me>();
+    if features.two_factor {
+        endpoints.add::<EnrollTwoFactor>();
+        endpoints.add::<ConfirmTwoFactor>();
+    }
+    endpoints.add::<DisableTwoFactor>();
+    if features.api_tokens {
+        endpoints.add::<CreateApiToken>();
+        endpoints.add::<ListApiTokens>();
+        endpoints.add::<RevokeApiToken>();
+    }
+    endpoints.add::<Timeline>();
+
+    endpoints.add::<PostTweet>();
+    endpoints.add::<EditTweet>();
+    endpoints.add::<TweetHistory>();
+    endpoints.add::<VoteInPoll>();
+    endpoints.add::<BookmarkTweet>();
+    endpoints.add::<RemoveBookmark>();
+    endpoints.add::<ListBookmarks>();
+    endpoints.add::<ListScheduledTweets>();
+    endpoints.add::<UpdateScheduledTweet>();
+    endpoints.add::<CancelScheduledTweet>();
+    endpoints.add::<CreateDraft>();
+    endpoints.add::<ListDrafts>();
+    endpoints.add::<GetDraft>();
+    endpoints.add::<UpdateDraft>();
+    endpoints.add::<DeleteDraft>();
+    endpoints.add::<PublishDraft>();
+
+    if features.oauth {
+        endpoints.add::<RegisterOAuthClient>();
+        endpoints.add::<GetOAuthClient>();
+        endpoints.add::<AuthorizeOAuthClient>();
+    }
+}
+
+/// The name used for an endpoint in the API docs and in config files, e.g. `CreateUser`.
+fn endpoint_name<E>() -> &'static str {
+    let name = std::any::type_name::<E>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Whether retries with the same `Idempotency-Key` get the first response replayed.
+fn accepts_idempotency_key<E: BackendApiEndpoint>() -> bool {
+    E::METHOD != Method::Get && !E::ISSUES_CREDENTIALS
+}
+
+/// Another endpoint's rate limit, for endpoints that count against it instead of having their own.
+#[derive(Clone, Copy)]
+struct SharedRateLimit {
+    name: fn() -> &'static str,
+    method: Method,
+    url_spec: &'static str,
+    policy: Option<RateLimitPolicy>,
+}
+
+impl SharedRateLimit {
+    const fn of<E: BackendApiEndpoint>() -> Self {
+        Self {
+            name: endpoint_name::<E>,
+            method: E::METHOD,
+            url_spec: <E::Url as shared::Url>::URL_SPEC,
+            policy: E::RATE_LIMIT,
+        }
+    }
 }
 
 #[async_trait]
 trait BackendApiEndpoint: ApiEndpoint {
+    const RATE_LIMIT: Option<RateLimitPolicy> = None;
+
+    /// For endpoints that are another way of doing what a different endpoint does. Calls count
+    /// against that endpoint's rate limit, so this one can't be used to get around it.
+    const SHARES_RATE_LIMIT_WITH: Option<SharedRateLimit> = None;
+
+    /// The scope API tokens need to call this endpoint. Endpoints without one can only be called
+    /// with a session token.
+    const REQUIRED_SCOPE: Option<Scope> = None;
+
+    /// Whether responses contain credentials like tokens, secrets or recovery codes. Those are
+    /// never stored for replaying to retries, so these endpoints don't take an `Idempotency-Key`.
+    const ISSUES_CREDENTIALS: bool = false;
+
     async fn handler(
         req: Request<State>,
         payload: Self::Payload,
+        query: Self::Query,
     ) -> tide::Result<(Self::Response, StatusCode)>;
 }
 
-#[async_trait]
-trait GetRequestPayload: Sized {
-    async fn get_payload(req: &mut Request<State>) -> tide::Result<Self>;
-}
-
-#[async_trait]
-impl GetRequestPayload for NoPayload {
-    async fn get_payload(_: &mut Request<State>) -> tide::Result<Self> {
-        Ok(NoPayload)
+async fn get_payload<E: ApiEndpoint>(req: &mut Request<State>) -> tide::Result<E::Payload> {
+    match E::PAYLOAD_ENCODING {
+        PayloadEncoding::None => Ok(serde_json::from_value(serde_json::Value::Null)?),
+        PayloadEncoding::Json => req.body_json().await,
     }
 }
 
-macro_rules! impl_get_request_payload {
-    ($name:ident) => {
-        #[async_trait]
-        impl GetRequestPayload for $name {
-            async fn get_payload(req: &mut Request<State>) -> tide::Result<Self> {
-                req.body_json().await
-            }
-        }
-    };
-}
-
-impl_get_request_payload!(CreateTweetPayload);
-impl_get_request_payload!(LoginPayload);
-impl_get_request_payload!(CreateUserPayload);
-
-fn add_endpoint<E>(server: &mut Server<State>)
+fn add_endpoint<E>(server: &mut Server<State>, rate_limit: Option<RateLimitPolicy>)
 where
     E: 'static + BackendApiEndpoint,
-    E::Payload: GetRequestPayload + Send,
+    E::Payload: Send,
+    E::Query: Send,
 {
-    let mut route = server.at(<E::Url as shared::Url>::URL_SPEC);
+    let url_spec = <E::Url as shared::Url>::URL_SPEC;
+    let mut route = server.at(url_spec);
+
+    if let Some(policy) = rate_limit {
+        let (method, bucket) = match E::SHARES_RATE_LIMIT_WITH {
+            Some(shared) => (shared.method, shared.url_spec),
+            None => (E::METHOD, url_spec),
+        };
+        route.with(middlewares::RateLimit::new(method, bucket, policy));
+    }
+    if accepts_idempotency_key::<E>() {
+        route.with(middlewares::Idempotency::new(E::METHOD, url_spec));
+    }
 
     let handler = |mut req: Request<State>| async {
-        let payload = E::Payload::get_payload(&mut req).await?;
-        let (data, status) = E::handler(req, payload).await?;
+        req.set_ext(endpoints::RequiredScope(E::REQUIRED_SCOPE));
+        let payload = get_payload::<E>(&mut req).await?;
+        let query = req.query::<E::Query>()?;
+        let (data, status) = E::handler(req, payload, query).await?;
         let mut resp = Response::new(status);
         let body = Body::from_json(&serde_json::json!({ "data": data }))?;
         resp.set_body(body);
         Ok(resp)
     };
 
-    match E::METHOD {
-        Method::Get => route.get(handler),
-        Method::Post => route.post(handler),
-        Method::Head => route.head(handler),
-        Method::Put => route.put(handler),
-        Method::Delete => route.delete(handler),
-        Method::Connect => route.connect(handler),
-        Method::Options => route.options(handler),
-        Method::Trace => route.trace(handler),
-        Method::Patch => route.patch(handler),
-    };
+    route.method(E::METHOD, handler);
 }
@@ -0,0 +1,202 @@
+//! Checks uploaded images and prepares them for serving.
+//!
+//! Images are decoded and encoded again, which leaves out all their metadata. That's how EXIF data
+//! such as GPS coordinates gets stripped. The orientation is applied to the pixels first so photos
+//! don't end up sideways.
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use std::io::Cursor;
+use thiserror::Error;
+
+/// Thumbnails fit in a square this many pixels wide.
+pub const THUMBNAIL_SIZE: u32 = 400;
+
+/// Decoding takes about 4 bytes per pixel, so this keeps it under 256 MiB.
+pub const MAX_DIMENSION: u32 = 8192;
+
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug)]
+pub struct ProcessedImage {
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub original: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+}
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum InvalidImage {
+    #[error("Only PNG and JPEG images are supported")]
+    UnsupportedFormat,
+    #[error("Images can be at most {max}x{max} pixels", max = MAX_DIMENSION)]
+    TooLarge,
+    #[error("The image couldn't be read")]
+    Corrupt,
+}
+
+/// The format is sniffed from the bytes. Whatever content type the client claimed is ignored.
+pub fn process(bytes: &[u8]) -> Result<ProcessedImage, InvalidImage> {
+    let (format, content_type, output_format) = match image::guess_format(bytes) {
+        Ok(ImageFormat::Png) => (ImageFormat::Png, "image/png", ImageOutputFormat::Png),
+        Ok(ImageFormat::Jpeg) => (
+            ImageFormat::Jpeg,
+            "image/jpeg",
+            ImageOutputFormat::Jpeg(JPEG_QUALITY),
+        ),
+        _ => return Err(InvalidImage::UnsupportedFormat),
+    };
+
+    // Checked before decoding, so a small file claiming to be huge can't use up the memory
+    let (width, height) = image::io::Reader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(|_| InvalidImage::Corrupt)?;
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(InvalidImage::TooLarge);
+    }
+
+    let image =
+        image::load_from_memory_with_format(bytes, format).map_err(|_| InvalidImage::Corrupt)?;
+    let image = apply_orientation(image, orientation(bytes));
+
+    let thumbnail = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+
+    Ok(ProcessedImage {
+        content_type,
+        width: image.width(),
+        height: image.height(),
+        original: encode(&image, output_format.clone())?,
+        thumbnail: encode(&thumbnail, output_format)?,
+    })
+}
+
+fn encode(image: &DynamicImage, format: ImageOutputFormat) -> Result<Vec<u8>, InvalidImage> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut bytes, format)
+        .map_err(|_| InvalidImage::Corrupt)?;
+    Ok(bytes)
+}
+
+/// The EXIF orientation, 1 to 8. 1 means the pixels are already the right way up.
+fn orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([255, 0, 0])));
+        encode(&image, ImageOutputFormat::Png).unwrap()
+    }
+
+    fn jpeg_with_orientation(width: u32, height: u32, orientation: u8) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([0, 0, 255])));
+        let jpeg = encode(&image, ImageOutputFormat::Jpeg(JPEG_QUALITY)).unwrap();
+
+        // A little-endian TIFF header followed by an IFD with just the orientation tag
+        let mut exif = b"Exif\0\0II\x2a\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        exif.extend_from_slice(&[orientation, 0, 0, 0, 0, 0, 0, 0]);
+        let length = (exif.len() + 2) as u16;
+
+        // The APP1 segment goes right after the start of image marker
+        let mut bytes = jpeg[..2].to_vec();
+        bytes.extend_from_slice(&[0xff, 0xe1]);
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.extend_from_slice(&exif);
+        bytes.extend_from_slice(&jpeg[2..]);
+        bytes
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn makes_thumbnails() {
+        let image = process(&png(1000, 500)).unwrap();
+        assert_eq!(image.content_type, "image/png");
+        assert_eq!((image.width, image.height), (1000, 500));
+
+        let thumbnail = image::load_from_memory(&image.thumbnail).unwrap();
+        assert_eq!(thumbnail.dimensions(), (400, 200));
+    }
+
+    #[test]
+    fn small_images_arent_scaled_up() {
+        let image = process(&png(10, 20)).unwrap();
+        let thumbnail = image::load_from_memory(&image.thumbnail).unwrap();
+        assert_eq!(thumbnail.dimensions(), (10, 20));
+    }
+
+    #[test]
+    fn strips_exif_and_applies_orientation() {
+        let bytes = jpeg_with_orientation(40, 20, 6);
+        assert_eq!(orientation(&bytes), 6);
+
+        let image = process(&bytes).unwrap();
+        assert_eq!(image.content_type, "image/jpeg");
+        assert_eq!((image.width, image.height), (20, 40));
+        assert!(!contains(&image.original, b"Exif"));
+        assert!(!contains(&image.thumbnail, b"Exif"));
+        assert_eq!(orientation(&image.original), 1);
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert_eq!(
+            process(b"GIF89a\x01\0\x01\0").unwrap_err(),
+            InvalidImage::UnsupportedFormat
+        );
+        assert_eq!(
+            process(b"<svg></svg>").unwrap_err(),
+            InvalidImage::UnsupportedFormat
+        );
+    }
+
+    #[test]
+    fn rejects_broken_images() {
+        let mut bytes = png(10, 10);
+        bytes.truncate(40);
+        assert_eq!(process(&bytes).unwrap_err(), InvalidImage::Corrupt);
+    }
+
+    #[test]
+    fn rejects_huge_images() {
+        assert_eq!(
+            process(&png(MAX_DIMENSION + 1, 1)).unwrap_err(),
+            InvalidImage::TooLarge
+        );
+    }
+}
@@ -1,11 +1,19 @@
+use crate::endpoints::{client_ip, find_authenticated, get_auth_token};
+use crate::login_throttle::LockedOut;
+use crate::rate_limit::{RateLimitPolicy, RateLimitStatus};
+use crate::responses::{constraint_violation, IntoError};
+use crate::storage::{IdempotentRequest, StorageError, StoredResponse};
 use crate::State;
 use futures::future::BoxFuture;
-use serde_json::json;
+use sha2::{Digest, Sha256};
+use shared::responses::{ApiError, ApiErrorResponse, ErrorCode};
+use std::convert::TryFrom;
 use std::future::Future;
 use std::pin::Pin;
 use tide::http::headers::HeaderValue;
-use tide::http::{headers, Method, StatusCode};
+use tide::http::{headers, mime, Method, StatusCode};
 use tide::security::Origin;
+use tide::Body;
 use tide::Middleware;
 use tide::Next;
 use tide::Request;
@@ -20,32 +28,42 @@ impl<State: Clone + Send + Sync + 'static> Middleware<State> for ErrorReponseToJ
         let mut resp = next.run(req).await;
 
         if let Some(err) = resp.error() {
-            let status = err.status();
-            let body = json!({
-                "error": {
-                    "status_code": status.to_string(),
-                    "message": format!("{}", err),
-                }
-            });
-            let mut resp = Response::new(status);
-            resp.set_body(body);
+            let mut status = err.status();
+            let error = if let Some(api_error) = err.downcast_ref::<ApiError>() {
+                api_error.clone()
+            } else if let Some(api_error) = err
+                .downcast_ref::<StorageError>()
+                .and_then(constraint_violation)
+            {
+                status = StatusCode::try_from(api_error.status()).unwrap_or(status);
+                api_error
+            } else if err.downcast_ref::<LockedOut>().is_some() {
+                ApiError::new(ErrorCode::TooManyLoginAttempts, err.to_string())
+            } else {
+                ApiError::from_status(status.into(), err.to_string())
+            };
+            let retry_after = err
+                .downcast_ref::<LockedOut>()
+                .map(|locked_out| locked_out.retry_after);
+
+            // Reuse the response so headers set by other middlewares are kept
+            resp.set_status(status);
+            if let Some(retry_after) = retry_after {
+                resp.insert_header(headers::RETRY_AFTER, retry_after.num_seconds().to_string());
+            }
+            resp.set_body(Body::from_json(&ApiErrorResponse { error })?);
             Ok(resp)
         } else {
             let status = resp.status();
 
-            if status.is_success() {
+            if status.is_success() || status == StatusCode::NotModified {
                 Ok(resp)
             } else {
                 let body = resp.take_body();
 
                 if body.is_empty().expect("no length on response body") {
-                    let new_body = json!({
-                        "error": {
-                            "status_code": status.to_string(),
-                            "message": "Something went wrong",
-                        }
-                    });
-                    resp.set_body(new_body);
+                    let error = ApiError::from_status(status.into(), "Something went wrong");
+                    resp.set_body(Body::from_json(&ApiErrorResponse { error })?);
                 } else {
                     resp.set_body(body);
                 }
@@ -55,3 +73,191 @@ impl<State: Clone + Send + Sync + 'static> Middleware<State> for ErrorReponseToJ
         }
     }
 }
+
+/// Limits how often each client can call an endpoint. Clients are identified by their auth token
+/// if they send a valid one, otherwise by their IP address.
+#[derive(Debug)]
+pub struct RateLimit {
+    endpoint: String,
+    policy: RateLimitPolicy,
+}
+
+impl RateLimit {
+    pub fn new(method: Method, url_spec: &str, policy: RateLimitPolicy) -> Self {
+        Self {
+            endpoint: format!("{} {}", method, url_spec),
+            policy,
+        }
+    }
+
+    async fn bucket_key(&self, req: &Request<State>) -> tide::Result<String> {
+        Ok(format!("{} {}", self.endpoint, client_key(req).await?))
+    }
+}
+
+/// Identifies the client by their auth token if it's valid, otherwise by their IP address. Made
+/// up tokens fall back to the IP, so they can't be used to get a fresh bucket for every request.
+async fn client_key(req: &Request<State>) -> tide::Result<String> {
+    if let Ok(token) = get_auth_token(req) {
+        if find_authenticated(req, token).await?.is_some() {
+            // Don't keep the raw tokens around in the store
+            return Ok(format!("token:{:x}", Sha256::digest(token.as_bytes())));
+        }
+    }
+
+    Ok(match client_ip(req) {
+        Some(ip) => format!("ip:{}", ip),
+        None => "unknown".to_string(),
+    })
+}
+
+#[async_trait::async_trait]
+impl Middleware<State> for RateLimit {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let key = self.bucket_key(&req).await?;
+        let now = req.state().clock.now();
+        let status = req
+            .state()
+            .rate_limit_store
+            .take(&key, self.policy, now)
+            .await;
+
+        let mut resp = if status.allowed {
+            next.run(req).await
+        } else {
+            let retry_after = status.retry_after.unwrap_or_else(chrono::Duration::zero);
+            let mut resp = Response::from(tide::Error::from_str(
+                StatusCode::TooManyRequests,
+                format!(
+                    "Rate limit exceeded. Try again in {} seconds",
+                    retry_after.num_seconds()
+                ),
+            ));
+            resp.insert_header(headers::RETRY_AFTER, retry_after.num_seconds().to_string());
+            resp
+        };
+
+        set_rate_limit_headers(&mut resp, &status);
+        Ok(resp)
+    }
+}
+
+fn set_rate_limit_headers(resp: &mut Response, status: &RateLimitStatus) {
+    resp.insert_header("X-RateLimit-Limit", status.limit.to_string());
+    resp.insert_header("X-RateLimit-Remaining", status.remaining.to_string());
+    resp.insert_header("X-RateLimit-Reset", status.reset_at.timestamp().to_string());
+}
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+const IDEMPOTENCY_KEY_LIFETIME_HOURS: i64 = 24;
+
+/// Lets clients safely retry requests by sending an `Idempotency-Key` header. The first successful
+/// response for a key is stored and replayed for retries, and reusing the key for a different
+/// request is a conflict. Keys are scoped to the endpoint and client like rate limits.
+#[derive(Debug)]
+pub struct Idempotency {
+    endpoint: String,
+}
+
+impl Idempotency {
+    pub fn new(method: Method, url_spec: &str) -> Self {
+        Self {
+            endpoint: format!("{} {}", method, url_spec),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware<State> for Idempotency {
+    async fn handle(&self, mut req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let key = match req.header(IDEMPOTENCY_KEY_HEADER) {
+            Some(values) => values.last().as_str().to_string(),
+            None => return Ok(next.run(req).await),
+        };
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Ok(Response::from(tide::Error::from_str(
+                StatusCode::BadRequest,
+                format!(
+                    "`{}` must be between 1 and {} characters",
+                    IDEMPOTENCY_KEY_HEADER, MAX_IDEMPOTENCY_KEY_LENGTH
+                ),
+            )));
+        }
+
+        let body = req.body_bytes().await?;
+        let url = req.url();
+        let request = [
+            url.path().as_bytes(),
+            b"?",
+            url.query().unwrap_or_default().as_bytes(),
+            b"\n",
+            &body,
+        ]
+        .concat();
+        let request_hash = format!("{:x}", Sha256::digest(&request));
+        req.set_body(body);
+
+        let scope = format!("{} {}", self.endpoint, client_key(&req).await?);
+        let storage = req.state().storage.clone();
+        let now = req.state().clock.now();
+        let expired_before = now - chrono::Duration::hours(IDEMPOTENCY_KEY_LIFETIME_HOURS);
+
+        let earlier = storage
+            .claim_idempotency_key(&scope, &key, &request_hash, now, expired_before)
+            .await?;
+        match earlier {
+            None => {}
+            Some(earlier) if earlier.request_hash != request_hash => {
+                return Ok(conflict(format!(
+                    "`{}` was already used for a different request",
+                    IDEMPOTENCY_KEY_HEADER
+                )));
+            }
+            Some(IdempotentRequest {
+                response: Some(response),
+                ..
+            }) => {
+                let mut resp = Response::new(response.status);
+                resp.set_body(Body::from_string(response.body));
+                resp.set_content_type(mime::JSON);
+                resp.insert_header("Idempotent-Replayed", "true");
+                return Ok(resp);
+            }
+            Some(IdempotentRequest { response: None, .. }) => {
+                return Ok(conflict(format!(
+                    "A request with this `{}` is still being handled",
+                    IDEMPOTENCY_KEY_HEADER
+                )));
+            }
+        }
+
+        let mut resp = next.run(req).await;
+
+        // Failed requests can be retried with the same key
+        if !resp.status().is_success() {
+            storage.release_idempotency_key(&scope, &key).await?;
+            return Ok(resp);
+        }
+
+        let body = resp.take_body();
+        let mime = body.mime().clone();
+        let body = body.into_string().await?;
+        let response = StoredResponse {
+            status: resp.status().into(),
+            body,
+        };
+        storage
+            .save_idempotent_response(&scope, &key, &response, now)
+            .await?;
+
+        let mut body = Body::from_string(response.body);
+        body.set_mime(mime);
+        resp.set_body(body);
+        Ok(resp)
+    }
+}
+
+fn conflict(message: String) -> Response {
+    Response::from(ApiError::new(ErrorCode::Conflict, message).into_error())
+}
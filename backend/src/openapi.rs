@@ -0,0 +1,181 @@
+//! Builds an OpenAPI 3 document from the endpoints registered in `add_endpoints`.
+
+use crate::config::Features;
+use crate::middlewares::IDEMPOTENCY_KEY_HEADER;
+use crate::{BackendApiEndpoint, Endpoints};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use shared::responses::{ApiErrorResponse, ApiResponse};
+use shared::PayloadEncoding;
+use std::collections::BTreeMap;
+
+pub fn document(features: &Features) -> Value {
+    let mut docs = ApiDocs::default();
+    crate::add_endpoints(&mut docs, features);
+    docs.into_document()
+}
+
+struct ApiDocs {
+    generator: SchemaGenerator,
+    paths: BTreeMap<String, Map<String, Value>>,
+}
+
+impl Default for ApiDocs {
+    fn default() -> Self {
+        ApiDocs {
+            generator: SchemaSettings::openapi3().into_generator(),
+            paths: BTreeMap::new(),
+        }
+    }
+}
+
+impl Endpoints for ApiDocs {
+    fn add<E>(&mut self)
+    where
+        E: 'static + BackendApiEndpoint,
+        E::Payload: Send,
+        E::Query: Send,
+    {
+        let mut parameters = params::<E::Url>(&mut self.generator, "path");
+        parameters.extend(params::<E::Query>(&mut self.generator, "query"));
+        if crate::accepts_idempotency_key::<E>() {
+            parameters.push(json!({
+                "name": IDEMPOTENCY_KEY_HEADER,
+                "in": "header",
+                "required": false,
+                "description": "Retries with the same key get the first response replayed",
+                "schema": { "type": "string" },
+            }));
+        }
+
+        let mut operation = json!({
+            "operationId": crate::endpoint_name::<E>(),
+            "parameters": parameters,
+            "responses": {
+                "2XX": {
+                    "description": "Success",
+                    "content": {
+                        "application/json": {
+                            "schema": self.generator.subschema_for::<ApiResponse<E::Response>>(),
+                        },
+                    },
+                },
+                "default": {
+                    "description": "Error",
+                    "content": {
+                        "application/json": {
+                            "schema": self.generator.subschema_for::<ApiErrorResponse<E::Error>>(),
+                        },
+                    },
+                },
+            },
+        });
+
+        if E::PAYLOAD_ENCODING == PayloadEncoding::Json {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": {
+                    "application/json": {
+                        "schema": self.generator.subschema_for::<E::Payload>(),
+                    },
+                },
+            });
+        }
+
+        if let Some(scope) = E::REQUIRED_SCOPE {
+            operation["x-required-scope"] = json!(scope);
+        }
+
+        let path = openapi_path(<E::Url as shared::Url>::URL_SPEC);
+        let method = E::METHOD.to_string().to_lowercase();
+        self.paths
+            .entry(path)
+            .or_default()
+            .insert(method, operation);
+    }
+}
+
+impl ApiDocs {
+    fn into_document(mut self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Witter",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": self.paths,
+            "components": {
+                "schemas": self.generator.take_definitions(),
+                "securitySchemes": {
+                    "bearerAuth": {
+                        "type": "http",
+                        "scheme": "bearer",
+                    },
+                },
+            },
+            // Most endpoints work without a token
+            "security": [{ "bearerAuth": [] }, {}],
+        })
+    }
+}
+
+/// Turns the fields of a struct into parameters.
+fn params<T: JsonSchema>(generator: &mut SchemaGenerator, location: &str) -> Vec<Value> {
+    let schema = match T::json_schema(generator) {
+        Schema::Object(schema) => schema,
+        Schema::Bool(_) => return Vec::new(),
+    };
+    let (properties, required) = match dereference(generator, schema).object {
+        Some(object) => (object.properties, object.required),
+        None => return Vec::new(),
+    };
+
+    properties
+        .into_iter()
+        .map(|(name, schema)| {
+            let required = location == "path" || required.contains(&name);
+            json!({
+                "name": name,
+                "in": location,
+                "required": required,
+                "schema": schema,
+            })
+        })
+        .collect()
+}
+
+fn dereference(generator: &SchemaGenerator, schema: SchemaObject) -> SchemaObject {
+    match generator.dereference(&Schema::Object(schema.clone())) {
+        Some(Schema::Object(target)) => target.clone(),
+        _ => schema,
+    }
+}
+
+/// OpenAPI writes path params as `{name}` rather than `:name`.
+fn openapi_path(url_spec: &str) -> String {
+    url_spec
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(param) => format!("{{{}}}", param),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn converting_paths() {
+        assert_eq!(openapi_path("/users"), "/users");
+        assert_eq!(
+            openapi_path("/users/:username/session"),
+            "/users/{username}/session"
+        );
+    }
+}
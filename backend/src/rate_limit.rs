@@ -0,0 +1,178 @@
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Allows `limit` requests per `period_seconds`. Requests are refilled continuously, so a client
+/// that has used up its limit gets a new request every `period_seconds / limit` seconds.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    pub limit: u32,
+    pub period_seconds: i64,
+}
+
+impl RateLimitPolicy {
+    pub const fn per_hours(limit: u32, hours: i64) -> Self {
+        Self {
+            limit,
+            period_seconds: hours * 60 * 60,
+        }
+    }
+
+    fn tokens_refilled(&self, elapsed_seconds: f64) -> f64 {
+        elapsed_seconds * self.limit as f64 / self.period_seconds as f64
+    }
+
+    fn time_to_refill(&self, tokens: f64) -> Duration {
+        let seconds = tokens * self.period_seconds as f64 / self.limit as f64;
+        Duration::seconds(seconds.ceil() as i64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// When the bucket will be full again.
+    pub reset_at: DateTime<Utc>,
+    /// How long to wait before the next request will be allowed. Only set if this request wasn't.
+    pub retry_after: Option<Duration>,
+}
+
+/// Where the token buckets are kept. The in-memory store only works for a single backend
+/// process, so running several requires a shared implementation.
+#[async_trait]
+pub trait RateLimitStore: fmt::Debug + Send + Sync + 'static {
+    /// Take one request from the bucket identified by `key`, creating a full bucket if there
+    /// isn't one.
+    async fn take(&self, key: &str, policy: RateLimitPolicy, now: DateTime<Utc>)
+        -> RateLimitStatus;
+}
+
+/// Once we're tracking this many buckets we start throwing away the ones that are full again.
+const MAX_BUCKETS_BEFORE_PRUNING: usize = 10_000;
+
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+    full_at: DateTime<Utc>,
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(
+        &self,
+        key: &str,
+        policy: RateLimitPolicy,
+        now: DateTime<Utc>,
+    ) -> RateLimitStatus {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_BUCKETS_BEFORE_PRUNING {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let limit = policy.limit as f64;
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: limit,
+            updated_at: now,
+            full_at: now,
+        });
+
+        let elapsed_seconds = (now - bucket.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        let mut tokens = (bucket.tokens + policy.tokens_refilled(elapsed_seconds)).min(limit);
+
+        let allowed = tokens >= 1.0;
+        let retry_after = if allowed {
+            tokens -= 1.0;
+            None
+        } else {
+            Some(policy.time_to_refill(1.0 - tokens))
+        };
+
+        let reset_at = now + policy.time_to_refill(limit - tokens);
+        *bucket = Bucket {
+            tokens,
+            updated_at: now,
+            full_at: reset_at,
+        };
+
+        RateLimitStatus {
+            allowed,
+            limit: policy.limit,
+            remaining: tokens.floor() as u32,
+            reset_at,
+            retry_after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    const POLICY: RateLimitPolicy = RateLimitPolicy::per_hours(5, 1);
+
+    #[async_std::test]
+    async fn allows_requests_until_the_bucket_is_empty() {
+        let store = InMemoryRateLimitStore::default();
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for remaining in (0..5).rev() {
+            let status = store.take("key", POLICY, now).await;
+            assert!(status.allowed);
+            assert_eq!(status.remaining, remaining);
+        }
+
+        let status = store.take("key", POLICY, now).await;
+        assert!(!status.allowed);
+        assert_eq!(status.remaining, 0);
+        assert_eq!(status.retry_after, Some(Duration::minutes(12)));
+        assert_eq!(status.reset_at, now + Duration::hours(1));
+    }
+
+    #[async_std::test]
+    async fn refills_over_time() {
+        let store = InMemoryRateLimitStore::default();
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for _ in 0..5 {
+            store.take("key", POLICY, now).await;
+        }
+
+        let later = now + Duration::minutes(12);
+        assert!(store.take("key", POLICY, later).await.allowed);
+        assert!(!store.take("key", POLICY, later).await.allowed);
+
+        let much_later = now + Duration::days(1);
+        let status = store.take("key", POLICY, much_later).await;
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 4);
+    }
+
+    #[async_std::test]
+    async fn buckets_are_separate_per_key() {
+        let store = InMemoryRateLimitStore::default();
+        let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        for _ in 0..5 {
+            store.take("bob", POLICY, now).await;
+        }
+
+        assert!(!store.take("bob", POLICY, now).await.allowed);
+        assert!(store.take("alice", POLICY, now).await.allowed);
+    }
+}
@@ -1,44 +1,33 @@
-use serde::Serialize;
-use shared::responses::ApiResponse;
+use crate::storage::StorageError;
+use shared::responses::{ApiError, ErrorCode};
+use std::convert::TryFrom;
 use tide::http::Error;
 use tide::http::StatusCode;
-use tide::Body;
-use tide::Response;
 
-pub trait ApiResponseExt {
-    fn to_response_with_status(self, status: StatusCode) -> Result<Response, Error>;
-
-    #[allow(dead_code)]
-    fn to_response(self) -> Result<Response, Error>;
+pub fn api_error(code: ErrorCode, message: impl Into<String>) -> Error {
+    ApiError::new(code, message).into_error()
 }
 
-impl<T> ApiResponseExt for ApiResponse<T>
-where
-    T: Serialize,
-{
-    fn to_response_with_status(self, status: StatusCode) -> Result<Response, Error> {
-        let mut resp = Response::new(status);
-        resp.set_body(Body::from_json(&self)?);
-        Ok(resp)
-    }
-
-    #[allow(dead_code)]
-    fn to_response(self) -> Result<Response, Error> {
-        let mut resp = Response::new(StatusCode::Ok);
-        resp.set_body(Body::from_json(&self)?);
-        Ok(resp)
-    }
+pub trait IntoError {
+    fn into_error(self) -> Error;
 }
 
-pub trait BuildApiResponse: Serialize + Sized {
-    fn to_response_with_status(self, status: StatusCode) -> Result<Response, Error> {
-        ApiResponseExt::to_response_with_status(ApiResponse::new(self), status)
+impl IntoError for ApiError {
+    /// `ErrorReponseToJson` picks the `ApiError` back out when building the response.
+    fn into_error(self) -> Error {
+        let status = StatusCode::try_from(self.status()).unwrap_or(StatusCode::InternalServerError);
+        Error::new(status, self)
     }
+}
 
-    #[allow(dead_code)]
-    fn to_response(self) -> Result<Response, Error> {
-        ApiResponseExt::to_response(ApiResponse::new(self))
+/// Constraint violations are caused by the request, e.g. following a user who was deleted in the
+/// meantime, so they're reported as client errors rather than 500s.
+pub fn constraint_violation(err: &StorageError) -> Option<ApiError> {
+    match err {
+        StorageError::UniqueViolation { .. } => {
+            Some(ApiError::new(ErrorCode::ValidationFailed, "Already exists"))
+        }
+        StorageError::ForeignKeyViolation => Some(ApiError::new(ErrorCode::NotFound, "Not found")),
+        StorageError::Database(_) => None,
     }
 }
-
-impl<T> BuildApiResponse for T where T: Serialize {}
@@ -0,0 +1,25 @@
+use crate::clock::Clock;
+use crate::storage::{Result, Storage};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Publishes the scheduled tweets that are due by the clock's current time.
+pub async fn publish_due_tweets(storage: &dyn Storage, clock: &dyn Clock) -> Result<Vec<Uuid>> {
+    let published = storage.publish_due_tweets(clock.now()).await?;
+    if !published.is_empty() {
+        log::info!("Published {} scheduled tweet(s)", published.len());
+    }
+    Ok(published)
+}
+
+/// Checks for due tweets every `interval` until the process exits. Errors are logged and retried
+/// on the next tick.
+pub async fn run(storage: Arc<dyn Storage>, clock: Arc<dyn Clock>, interval: Duration) {
+    loop {
+        if let Err(err) = publish_due_tweets(&*storage, &*clock).await {
+            log::error!("Failed to publish scheduled tweets: {}", err);
+        }
+        async_std::task::sleep(interval).await;
+    }
+}
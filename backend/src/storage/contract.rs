@@ -0,0 +1,1277 @@
+//! Tests every `Storage` implementation has to pass.
+
+use super::*;
+use chrono::Duration;
+
+/// Runs each test against both implementations.
+macro_rules! contract_tests {
+    ($($name:ident),* $(,)?) => {
+        mod in_memory {
+            $(
+                #[async_std::test]
+                async fn $name() {
+                    super::$name(&crate::storage::InMemoryStorage::default()).await;
+                }
+            )*
+        }
+
+        mod postgres {
+            use crate::tests::test_helpers::TestDb;
+
+            $(
+                #[async_std::test]
+                async fn $name() {
+                    let test_db = TestDb::new().await;
+                    super::$name(&crate::storage::PgStorage::new(test_db.db())).await;
+                }
+            )*
+        }
+    };
+}
+
+contract_tests!(
+    users,
+    usernames_are_unique,
+    auth_tokens,
+    two_factor,
+    second_factor_challenges,
+    api_tokens,
+    oauth_clients,
+    oauth_tokens,
+    tweets_need_a_user,
+    timeline,
+    timeline_pagination,
+    follows,
+    follows_need_users,
+    transactions_commit,
+    transactions_roll_back_when_dropped,
+    idempotency_keys,
+    idempotency_keys_expire,
+    scheduled_tweets,
+    scheduled_tweets_need_a_user,
+    publishing_scheduled_tweets,
+    drafts,
+    drafts_need_a_user,
+    editing_tweets,
+    media,
+    polls,
+    bookmarks,
+    deleting_tweets,
+);
+
+fn time(minutes: i64) -> DateTime<Utc> {
+    Utc.ymd(2020, 1, 1).and_hms(12, 0, 0) + Duration::minutes(minutes)
+}
+
+async fn create_user(storage: &dyn Storage, username: &str) -> UserResponse {
+    let id = storage
+        .create_user(username, "hash", time(0))
+        .await
+        .unwrap();
+    UserResponse {
+        id,
+        username: username.to_string(),
+    }
+}
+
+async fn users(storage: &dyn Storage) {
+    let id = storage.create_user("bob", "hash", time(0)).await.unwrap();
+
+    let user = storage.find_user_by_username("bob").await.unwrap();
+    assert_eq!(
+        user,
+        Some(User {
+            id,
+            username: "bob".to_string(),
+            hashed_password: "hash".to_string(),
+        })
+    );
+
+    assert_eq!(
+        storage
+            .find_user_by_username("BoB")
+            .await
+            .unwrap()
+            .map(|u| u.id),
+        Some(id)
+    );
+
+    assert_eq!(storage.find_user_by_username("alice").await.unwrap(), None);
+}
+
+async fn usernames_are_unique(storage: &dyn Storage) {
+    create_user(storage, "bob").await;
+
+    for username in &["bob", "Bob"] {
+        match storage.create_user(username, "hash", time(0)).await {
+            Err(StorageError::UniqueViolation { constraint }) => {
+                assert_eq!(constraint, "users_username")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+async fn auth_tokens(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    assert_eq!(storage.find_auth_token(bob.id).await.unwrap(), None);
+
+    storage
+        .create_auth_token(bob.id, "token", time(0))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.find_auth_token(bob.id).await.unwrap().as_deref(),
+        Some("token")
+    );
+    assert_eq!(
+        storage.find_user_by_auth_token("token").await.unwrap(),
+        Some(bob.clone())
+    );
+    assert_eq!(
+        storage.find_user_by_auth_token("other").await.unwrap(),
+        None
+    );
+
+    storage.delete_auth_token("token").await.unwrap();
+    assert_eq!(
+        storage.find_user_by_auth_token("token").await.unwrap(),
+        None
+    );
+    assert_eq!(storage.find_auth_token(bob.id).await.unwrap(), None);
+
+    let err = storage
+        .create_auth_token(Uuid::new_v4(), "token", time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn two_factor(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    assert_eq!(storage.find_totp_secret(bob.id).await.unwrap(), None);
+
+    storage
+        .set_pending_totp_secret(bob.id, "first", time(0))
+        .await
+        .unwrap();
+    storage
+        .set_pending_totp_secret(bob.id, "second", time(1))
+        .await
+        .unwrap();
+    storage.confirm_totp_secret(bob.id, time(2)).await.unwrap();
+    // Confirmed secrets aren't replaced
+    storage
+        .set_pending_totp_secret(bob.id, "third", time(3))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.find_totp_secret(bob.id).await.unwrap(),
+        Some(TotpSecret {
+            secret: "second".to_string(),
+            confirmed: true,
+        })
+    );
+
+    assert!(storage.use_totp_step(bob.id, 10, time(3)).await.unwrap());
+    assert!(!storage.use_totp_step(bob.id, 10, time(3)).await.unwrap());
+    assert!(!storage.use_totp_step(bob.id, 9, time(3)).await.unwrap());
+    assert!(storage.use_totp_step(bob.id, 11, time(4)).await.unwrap());
+    let alice = create_user(storage, "alice").await;
+    assert!(!storage.use_totp_step(alice.id, 11, time(4)).await.unwrap());
+
+    let codes = vec!["a".to_string(), "b".to_string()];
+    storage
+        .replace_recovery_codes(bob.id, &codes, time(2))
+        .await
+        .unwrap();
+    assert!(storage
+        .use_recovery_code(bob.id, "a", time(3))
+        .await
+        .unwrap());
+    assert!(!storage
+        .use_recovery_code(bob.id, "a", time(4))
+        .await
+        .unwrap());
+
+    storage
+        .replace_recovery_codes(bob.id, &["c".to_string()], time(5))
+        .await
+        .unwrap();
+    assert!(!storage
+        .use_recovery_code(bob.id, "b", time(6))
+        .await
+        .unwrap());
+    assert!(storage
+        .use_recovery_code(bob.id, "c", time(6))
+        .await
+        .unwrap());
+
+    storage
+        .create_second_factor_challenge(bob.id, "challenge", time(10), time(5))
+        .await
+        .unwrap();
+    storage.delete_two_factor(bob.id).await.unwrap();
+    assert_eq!(storage.find_totp_secret(bob.id).await.unwrap(), None);
+    assert_eq!(
+        storage
+            .find_second_factor_challenge("challenge", "bob", time(6))
+            .await
+            .unwrap(),
+        None
+    );
+
+    let err = storage
+        .set_pending_totp_secret(Uuid::new_v4(), "secret", time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn second_factor_challenges(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    create_user(storage, "alice").await;
+    storage
+        .create_second_factor_challenge(bob.id, "challenge", time(5), time(0))
+        .await
+        .unwrap();
+
+    let find = |token, username, now| storage.find_second_factor_challenge(token, username, now);
+    assert_eq!(
+        find("challenge", "BOB", time(4)).await.unwrap(),
+        Some(bob.id)
+    );
+    assert_eq!(find("challenge", "alice", time(4)).await.unwrap(), None);
+    assert_eq!(find("challenge", "bob", time(5)).await.unwrap(), None);
+    assert_eq!(find("other", "bob", time(4)).await.unwrap(), None);
+
+    let err = storage
+        .create_second_factor_challenge(bob.id, "challenge", time(5), time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::UniqueViolation { .. }));
+
+    storage
+        .delete_second_factor_challenge("challenge")
+        .await
+        .unwrap();
+    assert_eq!(find("challenge", "bob", time(4)).await.unwrap(), None);
+}
+
+async fn api_tokens(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+
+    let scopes = [Scope::TimelineRead, Scope::TweetsWrite];
+    let first = storage
+        .create_api_token(bob.id, "First", "hash1", &scopes, time(0))
+        .await
+        .unwrap();
+    assert_eq!(first.name, "First");
+    assert_eq!(first.scopes, scopes);
+    assert_eq!(first.created_at, time(0));
+    assert_eq!(first.last_used_at, None);
+    storage
+        .create_api_token(bob.id, "Second", "hash2", &[Scope::TweetsWrite], time(1))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        storage.use_api_token("hash1", time(2)).await.unwrap(),
+        Some(TokenGrant {
+            user: bob.clone(),
+            scopes: scopes.to_vec(),
+        })
+    );
+    assert_eq!(storage.use_api_token("other", time(2)).await.unwrap(), None);
+
+    let tokens = storage.api_tokens(bob.id).await.unwrap();
+    assert_eq!(
+        tokens
+            .iter()
+            .map(|token| (token.name.as_str(), token.last_used_at))
+            .collect::<Vec<_>>(),
+        vec![("First", Some(time(2))), ("Second", None)]
+    );
+
+    // Only the owner can delete a token
+    assert!(!storage.delete_api_token(alice.id, first.id).await.unwrap());
+    assert!(storage.delete_api_token(bob.id, first.id).await.unwrap());
+    assert!(!storage.delete_api_token(bob.id, first.id).await.unwrap());
+    assert_eq!(storage.use_api_token("hash1", time(3)).await.unwrap(), None);
+
+    let err = storage
+        .create_api_token(alice.id, "Copy", "hash2", &scopes, time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::UniqueViolation { .. }));
+    let err = storage
+        .create_api_token(Uuid::new_v4(), "Nobody", "hash3", &scopes, time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn oauth_clients(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let redirect_uris = vec![
+        "https://example.com/callback".to_string(),
+        "http://localhost:8080/callback".to_string(),
+    ];
+
+    let id = storage
+        .create_oauth_client(bob.id, "App", Some("hash"), &redirect_uris, time(0))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.find_oauth_client(id).await.unwrap(),
+        Some(OAuthClient {
+            id,
+            name: "App".to_string(),
+            hashed_client_secret: Some("hash".to_string()),
+            redirect_uris: redirect_uris.clone(),
+        })
+    );
+
+    let public_id = storage
+        .create_oauth_client(bob.id, "Public", None, &redirect_uris, time(0))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage
+            .find_oauth_client(public_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .hashed_client_secret,
+        None
+    );
+    assert_eq!(
+        storage.find_oauth_client(Uuid::new_v4()).await.unwrap(),
+        None
+    );
+
+    let err = storage
+        .create_oauth_client(Uuid::new_v4(), "App", None, &redirect_uris, time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn oauth_tokens(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let redirect_uris = vec!["https://example.com/callback".to_string()];
+    let client_id = storage
+        .create_oauth_client(bob.id, "App", None, &redirect_uris, time(0))
+        .await
+        .unwrap();
+    let other_client_id = storage
+        .create_oauth_client(bob.id, "Other", None, &redirect_uris, time(0))
+        .await
+        .unwrap();
+
+    let grant = AuthorizationGrant {
+        client_id,
+        user_id: bob.id,
+        redirect_uri: redirect_uris[0].clone(),
+        scopes: vec![Scope::TimelineRead],
+        code_challenge: "challenge".to_string(),
+        expires_at: time(10),
+    };
+    storage
+        .create_authorization_code("code", &grant, time(0))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.take_authorization_code("code").await.unwrap(),
+        Some(grant.clone())
+    );
+    assert_eq!(storage.take_authorization_code("code").await.unwrap(), None);
+
+    let err = storage
+        .create_authorization_code(
+            "code",
+            &AuthorizationGrant {
+                client_id: Uuid::new_v4(),
+                ..grant
+            },
+            time(0),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+
+    storage
+        .create_access_token(
+            client_id,
+            bob.id,
+            "token",
+            &[Scope::TimelineRead],
+            time(10),
+            time(0),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.find_access_token("token", time(9)).await.unwrap(),
+        Some(TokenGrant {
+            user: bob.clone(),
+            scopes: vec![Scope::TimelineRead],
+        })
+    );
+    assert_eq!(
+        storage.find_access_token("token", time(10)).await.unwrap(),
+        None
+    );
+
+    // Other clients can't revoke the token
+    storage
+        .delete_access_token(other_client_id, "token")
+        .await
+        .unwrap();
+    assert!(storage
+        .find_access_token("token", time(9))
+        .await
+        .unwrap()
+        .is_some());
+    storage
+        .delete_access_token(client_id, "token")
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.find_access_token("token", time(9)).await.unwrap(),
+        None
+    );
+}
+
+async fn tweets_need_a_user(storage: &dyn Storage) {
+    let err = storage
+        .create_tweet(Uuid::new_v4(), "Hello", time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn timeline(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    let carol = create_user(storage, "carol").await;
+    storage
+        .create_follow(bob.id, alice.id, time(0))
+        .await
+        .unwrap();
+
+    let first = storage
+        .create_tweet(bob.id, "first", time(1))
+        .await
+        .unwrap();
+    let second = storage
+        .create_tweet(alice.id, "second", time(2))
+        .await
+        .unwrap();
+    storage
+        .create_tweet(carol.id, "not followed", time(3))
+        .await
+        .unwrap();
+
+    let tweets = storage.timeline(bob.id, 10, 0).await.unwrap();
+    assert_eq!(
+        tweets,
+        vec![
+            TweetResponse {
+                id: second,
+                text: "second".to_string(),
+                created_at: time(2),
+                user: alice,
+                edited: false,
+                edit_count: 0,
+                media: Vec::new(),
+                poll: None,
+                bookmarked_by_me: false,
+            },
+            TweetResponse {
+                id: first,
+                text: "first".to_string(),
+                created_at: time(1),
+                user: bob,
+                edited: false,
+                edit_count: 0,
+                media: Vec::new(),
+                poll: None,
+                bookmarked_by_me: false,
+            },
+        ]
+    );
+}
+
+async fn timeline_pagination(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    for minute in 0..5 {
+        storage
+            .create_tweet(bob.id, &minute.to_string(), time(minute))
+            .await
+            .unwrap();
+    }
+
+    let texts = |tweets: Vec<TweetResponse>| {
+        tweets
+            .into_iter()
+            .map(|tweet| tweet.text)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        texts(storage.timeline(bob.id, 2, 0).await.unwrap()),
+        vec!["4", "3"]
+    );
+    assert_eq!(
+        texts(storage.timeline(bob.id, 2, 4).await.unwrap()),
+        vec!["0"]
+    );
+    assert!(storage.timeline(bob.id, 2, 6).await.unwrap().is_empty());
+}
+
+async fn follows(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    let carol = create_user(storage, "carol").await;
+
+    storage
+        .create_follow(bob.id, carol.id, time(0))
+        .await
+        .unwrap();
+    storage
+        .create_follow(bob.id, alice.id, time(0))
+        .await
+        .unwrap();
+    assert!(storage.is_following(bob.id, alice.id).await.unwrap());
+    assert!(!storage.is_following(alice.id, bob.id).await.unwrap());
+
+    assert_eq!(
+        storage.following(bob.id).await.unwrap(),
+        vec![alice.clone(), carol.clone()]
+    );
+    assert_eq!(
+        storage.followers(alice.id).await.unwrap(),
+        vec![bob.clone()]
+    );
+    assert!(storage.followers(bob.id).await.unwrap().is_empty());
+
+    match storage.create_follow(bob.id, alice.id, time(0)).await {
+        Err(StorageError::UniqueViolation { constraint }) => {
+            assert_eq!(constraint, "follows_follower_followee")
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    assert!(storage.delete_follow(bob.id, alice.id).await.unwrap());
+    assert!(!storage.delete_follow(bob.id, alice.id).await.unwrap());
+    assert_eq!(storage.following(bob.id).await.unwrap(), vec![carol]);
+}
+
+async fn follows_need_users(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+
+    let err = storage
+        .create_follow(bob.id, Uuid::new_v4(), time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn transactions_commit(storage: &dyn Storage) {
+    let tx = storage.begin().await.unwrap();
+    let id = tx.create_user("bob", "hash", time(0)).await.unwrap();
+    tx.create_auth_token(id, "token", time(0)).await.unwrap();
+    tx.commit().await.unwrap();
+
+    assert!(storage
+        .find_user_by_username("bob")
+        .await
+        .unwrap()
+        .is_some());
+    assert_eq!(
+        storage.find_auth_token(id).await.unwrap().as_deref(),
+        Some("token")
+    );
+}
+
+async fn transactions_roll_back_when_dropped(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    storage
+        .create_follow(bob.id, alice.id, time(0))
+        .await
+        .unwrap();
+
+    let tx = storage.begin().await.unwrap();
+    let carol_id = tx.create_user("carol", "hash", time(0)).await.unwrap();
+    tx.create_tweet(bob.id, "Hello", time(0)).await.unwrap();
+    assert!(tx.delete_follow(bob.id, alice.id).await.unwrap());
+    let err = tx
+        .create_auth_token(Uuid::new_v4(), "token", time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+    drop(tx);
+
+    assert_eq!(storage.find_user_by_username("carol").await.unwrap(), None);
+    assert_eq!(storage.find_auth_token(carol_id).await.unwrap(), None);
+    assert!(storage.timeline(bob.id, 10, 0).await.unwrap().is_empty());
+    assert!(storage.is_following(bob.id, alice.id).await.unwrap());
+}
+
+async fn idempotency_keys(storage: &dyn Storage) {
+    let claim = |scope, key| storage.claim_idempotency_key(scope, key, "hash", time(1), time(0));
+
+    assert_eq!(claim("bob", "key").await.unwrap(), None);
+    assert_eq!(claim("alice", "key").await.unwrap(), None);
+    assert_eq!(
+        claim("bob", "key").await.unwrap(),
+        Some(IdempotentRequest {
+            request_hash: "hash".to_string(),
+            response: None,
+        })
+    );
+
+    let response = StoredResponse {
+        status: 201,
+        body: "{}".to_string(),
+    };
+    storage
+        .save_idempotent_response("bob", "key", &response, time(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        claim("bob", "key").await.unwrap(),
+        Some(IdempotentRequest {
+            request_hash: "hash".to_string(),
+            response: Some(response),
+        })
+    );
+
+    storage
+        .release_idempotency_key("alice", "key")
+        .await
+        .unwrap();
+    assert_eq!(claim("alice", "key").await.unwrap(), None);
+}
+
+async fn idempotency_keys_expire(storage: &dyn Storage) {
+    storage
+        .claim_idempotency_key("bob", "key", "hash", time(0), time(-10))
+        .await
+        .unwrap();
+
+    let earlier = storage
+        .claim_idempotency_key("bob", "key", "other", time(5), time(0))
+        .await
+        .unwrap();
+    assert!(earlier.is_some());
+
+    let earlier = storage
+        .claim_idempotency_key("bob", "key", "other", time(10), time(1))
+        .await
+        .unwrap();
+    assert_eq!(earlier, None);
+}
+
+async fn scheduled_tweets(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+
+    let later = storage
+        .create_scheduled_tweet(bob.id, "later", time(20), time(0))
+        .await
+        .unwrap();
+    let sooner = storage
+        .create_scheduled_tweet(bob.id, "sooner", time(10), time(0))
+        .await
+        .unwrap();
+
+    let scheduled = storage.scheduled_tweets(bob.id).await.unwrap();
+    assert_eq!(
+        scheduled,
+        vec![
+            ScheduledTweetResponse {
+                id: sooner,
+                text: "sooner".to_string(),
+                publish_at: time(10),
+                created_at: time(0),
+            },
+            ScheduledTweetResponse {
+                id: later,
+                text: "later".to_string(),
+                publish_at: time(20),
+                created_at: time(0),
+            },
+        ]
+    );
+    assert!(storage.scheduled_tweets(alice.id).await.unwrap().is_empty());
+
+    let updated = storage
+        .update_scheduled_tweet(bob.id, later, None, Some(time(5)), time(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        updated,
+        Some(ScheduledTweetResponse {
+            id: later,
+            text: "later".to_string(),
+            publish_at: time(5),
+            created_at: time(0),
+        })
+    );
+    let updated = storage
+        .update_scheduled_tweet(bob.id, later, Some("edited"), None, time(1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.text, "edited");
+    assert_eq!(updated.publish_at, time(5));
+
+    let not_bobs = storage
+        .update_scheduled_tweet(alice.id, later, Some("hijacked"), None, time(1))
+        .await
+        .unwrap();
+    assert_eq!(not_bobs, None);
+    assert!(!storage
+        .delete_scheduled_tweet(alice.id, later)
+        .await
+        .unwrap());
+
+    assert!(storage.delete_scheduled_tweet(bob.id, later).await.unwrap());
+    assert!(!storage.delete_scheduled_tweet(bob.id, later).await.unwrap());
+    let ids = storage
+        .scheduled_tweets(bob.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|tweet| tweet.id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![sooner]);
+}
+
+async fn scheduled_tweets_need_a_user(storage: &dyn Storage) {
+    let err = storage
+        .create_scheduled_tweet(Uuid::new_v4(), "Hello", time(10), time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn publishing_scheduled_tweets(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let due = storage
+        .create_scheduled_tweet(bob.id, "due", time(10), time(0))
+        .await
+        .unwrap();
+    let not_due = storage
+        .create_scheduled_tweet(bob.id, "not due", time(20), time(0))
+        .await
+        .unwrap();
+
+    assert!(storage
+        .publish_due_tweets(time(9))
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage.timeline(bob.id, 10, 0).await.unwrap().is_empty());
+
+    assert_eq!(
+        storage.publish_due_tweets(time(15)).await.unwrap(),
+        vec![due]
+    );
+    assert!(storage
+        .publish_due_tweets(time(15))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        storage.timeline(bob.id, 10, 0).await.unwrap(),
+        vec![TweetResponse {
+            id: due,
+            text: "due".to_string(),
+            created_at: time(15),
+            user: bob.clone(),
+            edited: false,
+            edit_count: 0,
+            media: Vec::new(),
+            poll: None,
+            bookmarked_by_me: false,
+        }]
+    );
+
+    let ids = storage
+        .scheduled_tweets(bob.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|tweet| tweet.id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![not_due]);
+}
+
+async fn drafts(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+
+    let first = storage
+        .create_draft(bob.id, "first", time(0))
+        .await
+        .unwrap();
+    let second = storage
+        .create_draft(bob.id, "second", time(1))
+        .await
+        .unwrap();
+
+    let ids =
+        |drafts: Vec<DraftResponse>| drafts.into_iter().map(|draft| draft.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(storage.drafts(bob.id).await.unwrap()),
+        vec![second, first]
+    );
+    assert!(storage.drafts(alice.id).await.unwrap().is_empty());
+    assert_eq!(storage.count_drafts(bob.id).await.unwrap(), 2);
+    assert_eq!(storage.count_drafts(alice.id).await.unwrap(), 0);
+
+    let updated = storage
+        .update_draft(bob.id, first, "first, edited", time(2))
+        .await
+        .unwrap();
+    let expected = DraftResponse {
+        id: first,
+        text: "first, edited".to_string(),
+        created_at: time(0),
+        updated_at: time(2),
+    };
+    assert_eq!(updated, Some(expected.clone()));
+    assert_eq!(
+        storage.find_draft(bob.id, first).await.unwrap(),
+        Some(expected.clone())
+    );
+    assert_eq!(
+        ids(storage.drafts(bob.id).await.unwrap()),
+        vec![first, second]
+    );
+
+    assert_eq!(storage.find_draft(alice.id, first).await.unwrap(), None);
+    assert_eq!(
+        storage
+            .update_draft(alice.id, first, "hijacked", time(3))
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(storage.delete_draft(alice.id, first).await.unwrap(), None);
+
+    assert_eq!(
+        storage.delete_draft(bob.id, first).await.unwrap(),
+        Some(expected)
+    );
+    assert_eq!(storage.delete_draft(bob.id, first).await.unwrap(), None);
+    assert_eq!(ids(storage.drafts(bob.id).await.unwrap()), vec![second]);
+}
+
+async fn drafts_need_a_user(storage: &dyn Storage) {
+    let err = storage
+        .create_draft(Uuid::new_v4(), "Hello", time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+}
+
+async fn editing_tweets(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let id = storage.create_tweet(bob.id, "Helo", time(0)).await.unwrap();
+
+    let edited = storage.edit_tweet(id, "Hello", time(1)).await.unwrap();
+    assert_eq!(
+        edited,
+        Some(TweetResponse {
+            id,
+            text: "Hello".to_string(),
+            created_at: time(0),
+            user: bob.clone(),
+            edited: true,
+            edit_count: 1,
+            media: Vec::new(),
+            poll: None,
+            bookmarked_by_me: false,
+        })
+    );
+    let edited = storage
+        .edit_tweet(id, "Hello!", time(2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edited.edit_count, 2);
+
+    assert_eq!(
+        storage.find_tweet(id, bob.id).await.unwrap(),
+        Some(edited.clone())
+    );
+    assert_eq!(storage.timeline(bob.id, 10, 0).await.unwrap(), vec![edited]);
+
+    let revision = |text: &str, created_at| TweetRevisionResponse {
+        text: text.to_string(),
+        created_at,
+    };
+    assert_eq!(
+        storage.tweet_history(id).await.unwrap(),
+        vec![
+            revision("Helo", time(0)),
+            revision("Hello", time(1)),
+            revision("Hello!", time(2)),
+        ]
+    );
+
+    let missing = Uuid::new_v4();
+    assert_eq!(storage.find_tweet(missing, bob.id).await.unwrap(), None);
+    assert_eq!(
+        storage.edit_tweet(missing, "Hi", time(3)).await.unwrap(),
+        None
+    );
+    assert!(storage.tweet_history(missing).await.unwrap().is_empty());
+}
+
+async fn media(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    let tweet_id = storage.create_tweet(bob.id, "Look", time(0)).await.unwrap();
+
+    let first = Uuid::new_v4();
+    storage
+        .create_media(first, bob.id, "image/png", 800, 600, time(0))
+        .await
+        .unwrap();
+    let second = Uuid::new_v4();
+    storage
+        .create_media(second, bob.id, "image/jpeg", 10, 20, time(0))
+        .await
+        .unwrap();
+    let err = storage
+        .create_media(second, bob.id, "image/jpeg", 10, 20, time(0))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::UniqueViolation { .. }));
+    assert_eq!(
+        storage.find_media(first).await.unwrap(),
+        Some(Media {
+            id: first,
+            user_id: bob.id,
+            content_type: "image/png".to_string(),
+            width: 800,
+            height: 600,
+            tweet_id: None,
+        })
+    );
+    assert_eq!(storage.find_media(Uuid::new_v4()).await.unwrap(), None);
+
+    // Only the uploader can attach it, and only once
+    assert!(!storage
+        .attach_media(alice.id, first, tweet_id, 0)
+        .await
+        .unwrap());
+    assert!(storage
+        .attach_media(bob.id, second, tweet_id, 0)
+        .await
+        .unwrap());
+    assert!(storage
+        .attach_media(bob.id, first, tweet_id, 1)
+        .await
+        .unwrap());
+    assert!(!storage
+        .attach_media(bob.id, first, tweet_id, 2)
+        .await
+        .unwrap());
+
+    assert_eq!(
+        storage.find_media(first).await.unwrap().unwrap().tweet_id,
+        Some(tweet_id)
+    );
+    let tweet = storage
+        .find_tweet(tweet_id, alice.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        tweet.media,
+        vec![
+            MediaResponse::new(second, "image/jpeg", 10, 20),
+            MediaResponse::new(first, "image/png", 800, 600),
+        ]
+    );
+    assert_eq!(
+        storage.timeline(bob.id, 10, 0).await.unwrap()[0].media,
+        tweet.media
+    );
+}
+
+async fn polls(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    let carol = create_user(storage, "carol").await;
+    let tweet_id = storage
+        .create_tweet(bob.id, "Tabs or spaces?", time(0))
+        .await
+        .unwrap();
+    let options = vec!["Tabs".to_string(), "Spaces".to_string()];
+    storage
+        .create_poll(tweet_id, &options, time(60), time(0))
+        .await
+        .unwrap();
+
+    let err = storage
+        .create_poll(tweet_id, &options, time(60), time(0))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, StorageError::UniqueViolation { constraint } if constraint == "polls_tweet_id")
+    );
+
+    assert!(storage
+        .vote_in_poll(tweet_id, alice.id, 1, time(1))
+        .await
+        .unwrap());
+    assert!(storage
+        .vote_in_poll(tweet_id, carol.id, 1, time(1))
+        .await
+        .unwrap());
+    let err = storage
+        .vote_in_poll(tweet_id, alice.id, 0, time(2))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        StorageError::UniqueViolation { constraint } if constraint == "poll_votes_poll_id_user_id"
+    ));
+
+    // There's no such option, or no poll at all
+    assert!(!storage
+        .vote_in_poll(tweet_id, bob.id, 2, time(2))
+        .await
+        .unwrap());
+    let other = storage
+        .create_tweet(bob.id, "No poll", time(1))
+        .await
+        .unwrap();
+    assert!(!storage
+        .vote_in_poll(other, bob.id, 0, time(2))
+        .await
+        .unwrap());
+    assert_eq!(
+        storage
+            .find_tweet(other, bob.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .poll,
+        None
+    );
+
+    let option = |text: &str, votes| PollOptionResponse {
+        text: text.to_string(),
+        votes: Some(votes),
+    };
+    let poll = |voted_for| PollResponse {
+        options: vec![option("Tabs", 0), option("Spaces", 2)],
+        closes_at: time(60),
+        closed: false,
+        voted_for,
+    };
+    assert_eq!(
+        storage
+            .find_tweet(tweet_id, alice.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .poll,
+        Some(poll(Some(1)))
+    );
+    assert_eq!(
+        storage
+            .find_tweet(tweet_id, bob.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .poll,
+        Some(poll(None))
+    );
+    assert_eq!(
+        storage.timeline(bob.id, 10, 0).await.unwrap()[1].poll,
+        Some(poll(None))
+    );
+    assert_eq!(
+        storage
+            .edit_tweet(tweet_id, "Spaces or tabs?", time(3))
+            .await
+            .unwrap()
+            .unwrap()
+            .poll,
+        Some(poll(None))
+    );
+}
+
+async fn bookmarks(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    let mut tweets = Vec::new();
+    for minute in 0..4 {
+        let id = storage
+            .create_tweet(alice.id, &minute.to_string(), time(minute))
+            .await
+            .unwrap();
+        tweets.push(id);
+    }
+
+    // Bookmarked out of order, and the last two at the same time
+    for (tweet, minute) in [
+        (tweets[1], 10),
+        (tweets[0], 11),
+        (tweets[3], 12),
+        (tweets[2], 12),
+    ] {
+        storage
+            .create_bookmark(bob.id, tweet, time(minute))
+            .await
+            .unwrap();
+    }
+
+    let err = storage
+        .create_bookmark(bob.id, tweets[0], time(13))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        StorageError::UniqueViolation { constraint } if constraint == "bookmarks_user_id_tweet_id"
+    ));
+    let err = storage
+        .create_bookmark(bob.id, Uuid::new_v4(), time(13))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, StorageError::ForeignKeyViolation));
+
+    let all = storage.bookmarks(bob.id, None, 10).await.unwrap();
+    let texts = |bookmarks: &[Bookmark]| {
+        bookmarks
+            .iter()
+            .map(|bookmark| bookmark.tweet.text.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(all.len(), 4);
+    assert_eq!(texts(&all[2..]), vec!["0", "1"]);
+    assert!(all.iter().all(|bookmark| bookmark.tweet.bookmarked_by_me));
+    assert_eq!(all[0].cursor.created_at, time(12));
+
+    // Pages pick up right after their cursor, even among bookmarks made at the same time
+    let first_page = storage.bookmarks(bob.id, None, 1).await.unwrap();
+    let second_page = storage
+        .bookmarks(bob.id, Some(first_page[0].cursor), 2)
+        .await
+        .unwrap();
+    let last_page = storage
+        .bookmarks(bob.id, Some(second_page[1].cursor), 2)
+        .await
+        .unwrap();
+    assert_eq!([first_page, second_page, last_page].concat(), all.clone());
+
+    // Nobody else sees them
+    assert!(storage
+        .bookmarks(alice.id, None, 10)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(
+        storage
+            .find_tweet(tweets[0], bob.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .bookmarked_by_me
+    );
+    assert!(
+        !storage
+            .find_tweet(tweets[0], alice.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .bookmarked_by_me
+    );
+    assert!(storage
+        .timeline(alice.id, 10, 0)
+        .await
+        .unwrap()
+        .iter()
+        .all(|tweet| !tweet.bookmarked_by_me));
+
+    assert!(storage.delete_bookmark(bob.id, tweets[0]).await.unwrap());
+    assert!(!storage.delete_bookmark(bob.id, tweets[0]).await.unwrap());
+    assert!(!storage.delete_bookmark(alice.id, tweets[1]).await.unwrap());
+    assert_eq!(
+        texts(&storage.bookmarks(bob.id, None, 10).await.unwrap())[2..],
+        ["1".to_string()]
+    );
+}
+
+async fn deleting_tweets(storage: &dyn Storage) {
+    let bob = create_user(storage, "bob").await;
+    let alice = create_user(storage, "alice").await;
+    let tweet_id = storage
+        .create_tweet(bob.id, "Hello", time(0))
+        .await
+        .unwrap();
+    let other = storage
+        .create_tweet(bob.id, "Other", time(0))
+        .await
+        .unwrap();
+
+    storage
+        .edit_tweet(tweet_id, "Hello, World!", time(1))
+        .await
+        .unwrap();
+    let media_id = Uuid::new_v4();
+    storage
+        .create_media(media_id, bob.id, "image/png", 10, 10, time(0))
+        .await
+        .unwrap();
+    storage
+        .attach_media(bob.id, media_id, tweet_id, 0)
+        .await
+        .unwrap();
+    let options = vec!["Yes".to_string(), "No".to_string()];
+    storage
+        .create_poll(tweet_id, &options, time(60), time(0))
+        .await
+        .unwrap();
+    storage
+        .vote_in_poll(tweet_id, alice.id, 0, time(2))
+        .await
+        .unwrap();
+    storage
+        .create_bookmark(alice.id, tweet_id, time(3))
+        .await
+        .unwrap();
+    storage
+        .create_bookmark(alice.id, other, time(3))
+        .await
+        .unwrap();
+
+    assert!(storage.delete_tweet(tweet_id).await.unwrap());
+    assert!(!storage.delete_tweet(tweet_id).await.unwrap());
+
+    assert_eq!(storage.find_tweet(tweet_id, alice.id).await.unwrap(), None);
+    assert!(storage.tweet_history(tweet_id).await.unwrap().is_empty());
+    assert_eq!(storage.find_media(media_id).await.unwrap(), None);
+    assert!(!storage
+        .vote_in_poll(tweet_id, bob.id, 0, time(4))
+        .await
+        .unwrap());
+    let bookmarks = storage.bookmarks(alice.id, None, 10).await.unwrap();
+    assert_eq!(
+        bookmarks
+            .iter()
+            .map(|bookmark| bookmark.tweet.id)
+            .collect::<Vec<_>>(),
+        vec![other]
+    );
+}
@@ -0,0 +1,1598 @@
+use super::*;
+use shared::credentials::canonical_username;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Keeps everything in memory. Enforces the same unique and foreign key constraints as the
+/// database so the two behave alike.
+///
+/// Writes made in a transaction are visible to everyone straight away and undone if it's
+/// rolled back. That's enough for tests but doesn't isolate anything.
+#[derive(Default)]
+pub struct InMemoryStorage {
+    data: Arc<Mutex<Data>>,
+    /// Only set for transactions. Undoes the writes made so far, newest last.
+    undo_log: Option<Mutex<Vec<Undo>>>,
+}
+
+type Undo = Box<dyn FnOnce(&mut Data) + Send>;
+
+impl fmt::Debug for InMemoryStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryStorage")
+            .field("data", &self.data)
+            .field("in_transaction", &self.undo_log.is_some())
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Data {
+    users: Vec<User>,
+    auth_tokens: Vec<AuthToken>,
+    totp_secrets: Vec<StoredTotpSecret>,
+    recovery_codes: Vec<RecoveryCode>,
+    second_factor_challenges: Vec<SecondFactorChallenge>,
+    api_tokens: Vec<ApiToken>,
+    oauth_clients: Vec<OAuthClient>,
+    authorization_codes: Vec<AuthorizationCode>,
+    access_tokens: Vec<AccessToken>,
+    tweets: Vec<Tweet>,
+    scheduled_tweets: Vec<ScheduledTweet>,
+    drafts: Vec<Draft>,
+    media: Vec<StoredMedia>,
+    polls: Vec<Poll>,
+    poll_votes: Vec<PollVote>,
+    bookmarks: Vec<StoredBookmark>,
+    follows: Vec<Follow>,
+    idempotency_keys: Vec<IdempotencyKey>,
+}
+
+#[derive(Debug, Clone)]
+struct AuthToken {
+    user_id: Uuid,
+    token: String,
+}
+
+#[derive(Debug, Clone)]
+struct StoredTotpSecret {
+    user_id: Uuid,
+    secret: TotpSecret,
+    last_used_step: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+struct RecoveryCode {
+    user_id: Uuid,
+    hashed_code: String,
+    used: bool,
+}
+
+#[derive(Debug, Clone)]
+struct SecondFactorChallenge {
+    user_id: Uuid,
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct ApiToken {
+    user_id: Uuid,
+    hashed_token: String,
+    token: ApiTokenResponse,
+}
+
+#[derive(Debug, Clone)]
+struct AuthorizationCode {
+    hashed_code: String,
+    grant: AuthorizationGrant,
+}
+
+#[derive(Debug, Clone)]
+struct AccessToken {
+    client_id: Uuid,
+    user_id: Uuid,
+    hashed_token: String,
+    scopes: Vec<Scope>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct Tweet {
+    id: Uuid,
+    user_id: Uuid,
+    text: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// Earlier versions, oldest first.
+    revisions: Vec<TweetRevisionResponse>,
+}
+
+#[derive(Debug, Clone)]
+struct ScheduledTweet {
+    user_id: Uuid,
+    tweet: ScheduledTweetResponse,
+}
+
+#[derive(Debug, Clone)]
+struct Draft {
+    user_id: Uuid,
+    draft: DraftResponse,
+}
+
+#[derive(Debug, Clone)]
+struct StoredMedia {
+    media: Media,
+    position: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Poll {
+    id: Uuid,
+    tweet_id: Uuid,
+    options: Vec<String>,
+    closes_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct PollVote {
+    poll_id: Uuid,
+    user_id: Uuid,
+    option: u32,
+}
+
+#[derive(Debug, Clone)]
+struct StoredBookmark {
+    user_id: Uuid,
+    tweet_id: Uuid,
+    cursor: BookmarkCursor,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Follow {
+    follower_id: Uuid,
+    followee_id: Uuid,
+}
+
+#[derive(Debug, Clone)]
+struct IdempotencyKey {
+    scope: String,
+    key: String,
+    request: IdempotentRequest,
+    created_at: DateTime<Utc>,
+}
+
+impl IdempotencyKey {
+    fn is(&self, scope: &str, key: &str) -> bool {
+        self.scope == scope && self.key == key
+    }
+}
+
+impl Data {
+    fn user(&self, id: Uuid) -> Result<&User> {
+        self.users
+            .iter()
+            .find(|user| user.id == id)
+            .ok_or(StorageError::ForeignKeyViolation)
+    }
+
+    fn oauth_client(&self, id: Uuid) -> Result<&OAuthClient> {
+        self.oauth_clients
+            .iter()
+            .find(|client| client.id == id)
+            .ok_or(StorageError::ForeignKeyViolation)
+    }
+
+    fn user_response(&self, id: Uuid) -> Result<UserResponse> {
+        let user = self.user(id)?;
+        Ok(UserResponse {
+            id: user.id,
+            username: user.username.clone(),
+        })
+    }
+
+    fn tweet_response(&self, tweet: &Tweet, viewer_id: Uuid) -> Result<TweetResponse> {
+        Ok(TweetResponse {
+            id: tweet.id,
+            text: tweet.text.clone(),
+            created_at: tweet.created_at,
+            user: self.user_response(tweet.user_id)?,
+            edited: !tweet.revisions.is_empty(),
+            edit_count: tweet.revisions.len() as u32,
+            media: self.tweet_media(tweet.id),
+            poll: self.tweet_poll(tweet.id, viewer_id),
+            bookmarked_by_me: self
+                .bookmarks
+                .iter()
+                .any(|row| row.tweet_id == tweet.id && row.user_id == viewer_id),
+        })
+    }
+
+    fn tweet_poll(&self, tweet_id: Uuid, viewer_id: Uuid) -> Option<PollResponse> {
+        let poll = self.polls.iter().find(|poll| poll.tweet_id == tweet_id)?;
+        let votes = self
+            .poll_votes
+            .iter()
+            .filter(|vote| vote.poll_id == poll.id)
+            .collect::<Vec<_>>();
+
+        Some(PollResponse {
+            options: poll
+                .options
+                .iter()
+                .enumerate()
+                .map(|(position, text)| PollOptionResponse {
+                    text: text.clone(),
+                    votes: Some(
+                        votes
+                            .iter()
+                            .filter(|vote| vote.option == position as u32)
+                            .count() as u32,
+                    ),
+                })
+                .collect(),
+            closes_at: poll.closes_at,
+            closed: false,
+            voted_for: votes
+                .iter()
+                .find(|vote| vote.user_id == viewer_id)
+                .map(|vote| vote.option),
+        })
+    }
+
+    fn tweet_media(&self, tweet_id: Uuid) -> Vec<MediaResponse> {
+        let mut media = self
+            .media
+            .iter()
+            .filter(|row| row.media.tweet_id == Some(tweet_id))
+            .collect::<Vec<_>>();
+        media.sort_by_key(|row| row.position);
+        media.into_iter().map(|row| row.media.response()).collect()
+    }
+
+    fn sorted_by_username(&self, ids: impl Iterator<Item = Uuid>) -> Result<Vec<UserResponse>> {
+        let mut users = ids
+            .map(|id| self.user_response(id))
+            .collect::<Result<Vec<_>>>()?;
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        Ok(users)
+    }
+}
+
+fn unique_violation(constraint: &str) -> StorageError {
+    StorageError::UniqueViolation {
+        constraint: constraint.to_string(),
+    }
+}
+
+impl InMemoryStorage {
+    fn data(&self) -> MutexGuard<'_, Data> {
+        self.data.lock().unwrap()
+    }
+
+    fn on_rollback(&self, undo: impl FnOnce(&mut Data) + Send + 'static) {
+        if let Some(undo_log) = &self.undo_log {
+            undo_log.lock().unwrap().push(Box::new(undo));
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for InMemoryStorage {
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        Ok(Box::new(Self {
+            data: self.data.clone(),
+            undo_log: Some(Mutex::new(Vec::new())),
+        }))
+    }
+}
+
+#[async_trait]
+impl Transaction for InMemoryStorage {
+    async fn commit(self: Box<Self>) -> Result<()> {
+        if let Some(undo_log) = &self.undo_log {
+            undo_log.lock().unwrap().clear();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InMemoryStorage {
+    fn drop(&mut self) {
+        if let Some(undo_log) = self.undo_log.take() {
+            let mut data = self.data();
+            for undo in undo_log.into_inner().unwrap().into_iter().rev() {
+                undo(&mut data);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryStorage {
+    async fn create_user(
+        &self,
+        username: &str,
+        hashed_password: &str,
+        _: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let mut data = self.data();
+        let canonical = canonical_username(username);
+        if data
+            .users
+            .iter()
+            .any(|user| canonical_username(&user.username) == canonical)
+        {
+            return Err(unique_violation("users_username"));
+        }
+
+        let id = Uuid::new_v4();
+        data.users.push(User {
+            id,
+            username: username.to_string(),
+            hashed_password: hashed_password.to_string(),
+        });
+        self.on_rollback(move |data| data.users.retain(|user| user.id != id));
+        Ok(id)
+    }
+
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let data = self.data();
+        Ok(data
+            .users
+            .iter()
+            .find(|user| canonical_username(&user.username) == canonical_username(username))
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl TokenRepository for InMemoryStorage {
+    async fn create_auth_token(&self, user_id: Uuid, token: &str, _: DateTime<Utc>) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        if data.auth_tokens.iter().any(|row| row.token == token) {
+            return Err(unique_violation("auth_tokens_token"));
+        }
+
+        data.auth_tokens.push(AuthToken {
+            user_id,
+            token: token.to_string(),
+        });
+        let token = token.to_string();
+        self.on_rollback(move |data| data.auth_tokens.retain(|row| row.token != token));
+        Ok(())
+    }
+
+    async fn find_auth_token(&self, user_id: Uuid) -> Result<Option<String>> {
+        let data = self.data();
+        Ok(data
+            .auth_tokens
+            .iter()
+            .find(|row| row.user_id == user_id)
+            .map(|row| row.token.clone()))
+    }
+
+    async fn find_user_by_auth_token(&self, token: &str) -> Result<Option<UserResponse>> {
+        let data = self.data();
+        data.auth_tokens
+            .iter()
+            .find(|row| row.token == token)
+            .map(|row| data.user_response(row.user_id))
+            .transpose()
+    }
+
+    async fn delete_auth_token(&self, token: &str) -> Result<()> {
+        let mut data = self.data();
+        let (deleted, kept) = data
+            .auth_tokens
+            .drain(..)
+            .partition(|row| row.token == token);
+        data.auth_tokens = kept;
+        self.on_rollback(move |data| data.auth_tokens.extend(deleted));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for InMemoryStorage {
+    async fn set_pending_totp_secret(
+        &self,
+        user_id: Uuid,
+        secret: &str,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        let secret = TotpSecret {
+            secret: secret.to_string(),
+            confirmed: false,
+        };
+
+        match data
+            .totp_secrets
+            .iter_mut()
+            .find(|row| row.user_id == user_id)
+        {
+            Some(row) if row.secret.confirmed => {}
+            Some(row) => {
+                let earlier = std::mem::replace(&mut row.secret, secret);
+                let earlier_step = row.last_used_step.take();
+                self.on_rollback(move |data| {
+                    if let Some(row) = data
+                        .totp_secrets
+                        .iter_mut()
+                        .find(|row| row.user_id == user_id)
+                    {
+                        row.secret = earlier;
+                        row.last_used_step = earlier_step;
+                    }
+                });
+            }
+            None => {
+                data.totp_secrets.push(StoredTotpSecret {
+                    user_id,
+                    secret,
+                    last_used_step: None,
+                });
+                self.on_rollback(move |data| {
+                    data.totp_secrets.retain(|row| row.user_id != user_id)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_totp_secret(&self, user_id: Uuid) -> Result<Option<TotpSecret>> {
+        Ok(self
+            .data()
+            .totp_secrets
+            .iter()
+            .find(|row| row.user_id == user_id)
+            .map(|row| row.secret.clone()))
+    }
+
+    async fn confirm_totp_secret(&self, user_id: Uuid, _: DateTime<Utc>) -> Result<()> {
+        let mut data = self.data();
+        if let Some(row) = data
+            .totp_secrets
+            .iter_mut()
+            .find(|row| row.user_id == user_id)
+        {
+            let was_confirmed = std::mem::replace(&mut row.secret.confirmed, true);
+            self.on_rollback(move |data| {
+                if let Some(row) = data
+                    .totp_secrets
+                    .iter_mut()
+                    .find(|row| row.user_id == user_id)
+                {
+                    row.secret.confirmed = was_confirmed;
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, user_id: Uuid, step: i64, _: DateTime<Utc>) -> Result<bool> {
+        let mut data = self.data();
+        let row = data
+            .totp_secrets
+            .iter_mut()
+            .find(|row| row.user_id == user_id);
+        let row = match row {
+            Some(row) if row.last_used_step < Some(step) => row,
+            _ => return Ok(false),
+        };
+
+        let earlier = row.last_used_step.replace(step);
+        self.on_rollback(move |data| {
+            if let Some(row) = data
+                .totp_secrets
+                .iter_mut()
+                .find(|row| row.user_id == user_id)
+            {
+                row.last_used_step = earlier;
+            }
+        });
+        Ok(true)
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: Uuid,
+        hashed_codes: &[String],
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        let (replaced, kept) = data
+            .recovery_codes
+            .drain(..)
+            .partition(|row| row.user_id == user_id);
+        data.recovery_codes = kept;
+        data.recovery_codes
+            .extend(hashed_codes.iter().map(|hashed_code| RecoveryCode {
+                user_id,
+                hashed_code: hashed_code.clone(),
+                used: false,
+            }));
+        self.on_rollback(move |data| {
+            data.recovery_codes.retain(|row| row.user_id != user_id);
+            data.recovery_codes.extend(replaced);
+        });
+        Ok(())
+    }
+
+    async fn use_recovery_code(
+        &self,
+        user_id: Uuid,
+        hashed_code: &str,
+        _: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut data = self.data();
+        let row = data
+            .recovery_codes
+            .iter_mut()
+            .find(|row| row.user_id == user_id && row.hashed_code == hashed_code && !row.used);
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(false),
+        };
+
+        row.used = true;
+        let hashed_code = hashed_code.to_string();
+        self.on_rollback(move |data| {
+            if let Some(row) = data
+                .recovery_codes
+                .iter_mut()
+                .find(|row| row.user_id == user_id && row.hashed_code == hashed_code)
+            {
+                row.used = false;
+            }
+        });
+        Ok(true)
+    }
+
+    async fn create_second_factor_challenge(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        if data
+            .second_factor_challenges
+            .iter()
+            .any(|row| row.token == token)
+        {
+            return Err(unique_violation("second_factor_challenges_token"));
+        }
+
+        data.second_factor_challenges.push(SecondFactorChallenge {
+            user_id,
+            token: token.to_string(),
+            expires_at,
+        });
+        let token = token.to_string();
+        self.on_rollback(move |data| {
+            data.second_factor_challenges
+                .retain(|row| row.token != token)
+        });
+        Ok(())
+    }
+
+    async fn find_second_factor_challenge(
+        &self,
+        token: &str,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Uuid>> {
+        let data = self.data();
+        let challenge = data
+            .second_factor_challenges
+            .iter()
+            .find(|row| row.token == token && row.expires_at > now);
+        let challenge = match challenge {
+            Some(challenge) => challenge,
+            None => return Ok(None),
+        };
+
+        let user = data.user(challenge.user_id)?;
+        if canonical_username(&user.username) == canonical_username(username) {
+            Ok(Some(user.id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn delete_second_factor_challenge(&self, token: &str) -> Result<()> {
+        let mut data = self.data();
+        let (deleted, kept) = data
+            .second_factor_challenges
+            .drain(..)
+            .partition(|row| row.token == token);
+        data.second_factor_challenges = kept;
+        self.on_rollback(move |data| data.second_factor_challenges.extend(deleted));
+        Ok(())
+    }
+
+    async fn delete_two_factor(&self, user_id: Uuid) -> Result<()> {
+        let mut data = self.data();
+        let (secrets, kept) = data
+            .totp_secrets
+            .drain(..)
+            .partition(|row| row.user_id == user_id);
+        data.totp_secrets = kept;
+        let (codes, kept) = data
+            .recovery_codes
+            .drain(..)
+            .partition(|row| row.user_id == user_id);
+        data.recovery_codes = kept;
+        let (challenges, kept) = data
+            .second_factor_challenges
+            .drain(..)
+            .partition(|row| row.user_id == user_id);
+        data.second_factor_challenges = kept;
+
+        self.on_rollback(move |data| {
+            data.totp_secrets.extend(secrets);
+            data.recovery_codes.extend(codes);
+            data.second_factor_challenges.extend(challenges);
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepository for InMemoryStorage {
+    async fn create_api_token(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        hashed_token: &str,
+        scopes: &[Scope],
+        now: DateTime<Utc>,
+    ) -> Result<ApiTokenResponse> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        if data
+            .api_tokens
+            .iter()
+            .any(|row| row.hashed_token == hashed_token)
+        {
+            return Err(unique_violation("api_tokens_hashed_token"));
+        }
+
+        let token = ApiTokenResponse {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            created_at: now,
+            last_used_at: None,
+        };
+        data.api_tokens.push(ApiToken {
+            user_id,
+            hashed_token: hashed_token.to_string(),
+            token: token.clone(),
+        });
+        let id = token.id;
+        self.on_rollback(move |data| data.api_tokens.retain(|row| row.token.id != id));
+        Ok(token)
+    }
+
+    async fn api_tokens(&self, user_id: Uuid) -> Result<Vec<ApiTokenResponse>> {
+        let mut tokens = self
+            .data()
+            .api_tokens
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .map(|row| row.token.clone())
+            .collect::<Vec<_>>();
+        tokens.sort_by_key(|token| token.created_at);
+        Ok(tokens)
+    }
+
+    async fn delete_api_token(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let mut data = self.data();
+        let (deleted, kept): (Vec<_>, _) = data
+            .api_tokens
+            .drain(..)
+            .partition(|row| row.user_id == user_id && row.token.id == id);
+        data.api_tokens = kept;
+        let was_deleted = !deleted.is_empty();
+        self.on_rollback(move |data| data.api_tokens.extend(deleted));
+        Ok(was_deleted)
+    }
+
+    async fn use_api_token(
+        &self,
+        hashed_token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TokenGrant>> {
+        let mut data = self.data();
+        let row = data
+            .api_tokens
+            .iter_mut()
+            .find(|row| row.hashed_token == hashed_token);
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let last_used_at = row.token.last_used_at.replace(now);
+        let (id, user_id, scopes) = (row.token.id, row.user_id, row.token.scopes.clone());
+        self.on_rollback(move |data| {
+            if let Some(row) = data.api_tokens.iter_mut().find(|row| row.token.id == id) {
+                row.token.last_used_at = last_used_at;
+            }
+        });
+
+        Ok(Some(TokenGrant {
+            user: data.user_response(user_id)?,
+            scopes,
+        }))
+    }
+}
+
+#[async_trait]
+impl OAuthRepository for InMemoryStorage {
+    async fn create_oauth_client(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        hashed_client_secret: Option<&str>,
+        redirect_uris: &[String],
+        _: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let mut data = self.data();
+        data.user(user_id)?;
+
+        let id = Uuid::new_v4();
+        data.oauth_clients.push(OAuthClient {
+            id,
+            name: name.to_string(),
+            hashed_client_secret: hashed_client_secret.map(str::to_string),
+            redirect_uris: redirect_uris.to_vec(),
+        });
+        self.on_rollback(move |data| data.oauth_clients.retain(|row| row.id != id));
+        Ok(id)
+    }
+
+    async fn find_oauth_client(&self, id: Uuid) -> Result<Option<OAuthClient>> {
+        Ok(self
+            .data()
+            .oauth_clients
+            .iter()
+            .find(|row| row.id == id)
+            .cloned())
+    }
+
+    async fn create_authorization_code(
+        &self,
+        hashed_code: &str,
+        grant: &AuthorizationGrant,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(grant.user_id)?;
+        data.oauth_client(grant.client_id)?;
+        if data
+            .authorization_codes
+            .iter()
+            .any(|row| row.hashed_code == hashed_code)
+        {
+            return Err(unique_violation("oauth_authorization_codes_hashed_code"));
+        }
+
+        data.authorization_codes.push(AuthorizationCode {
+            hashed_code: hashed_code.to_string(),
+            grant: grant.clone(),
+        });
+        let hashed_code = hashed_code.to_string();
+        self.on_rollback(move |data| {
+            data.authorization_codes
+                .retain(|row| row.hashed_code != hashed_code)
+        });
+        Ok(())
+    }
+
+    async fn take_authorization_code(
+        &self,
+        hashed_code: &str,
+    ) -> Result<Option<AuthorizationGrant>> {
+        let mut data = self.data();
+        let index = data
+            .authorization_codes
+            .iter()
+            .position(|row| row.hashed_code == hashed_code);
+        let code = match index {
+            Some(index) => data.authorization_codes.remove(index),
+            None => return Ok(None),
+        };
+
+        let grant = code.grant.clone();
+        self.on_rollback(move |data| data.authorization_codes.push(code));
+        Ok(Some(grant))
+    }
+
+    async fn create_access_token(
+        &self,
+        client_id: Uuid,
+        user_id: Uuid,
+        hashed_token: &str,
+        scopes: &[Scope],
+        expires_at: DateTime<Utc>,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        data.oauth_client(client_id)?;
+        if data
+            .access_tokens
+            .iter()
+            .any(|row| row.hashed_token == hashed_token)
+        {
+            return Err(unique_violation("oauth_access_tokens_hashed_token"));
+        }
+
+        data.access_tokens.push(AccessToken {
+            client_id,
+            user_id,
+            hashed_token: hashed_token.to_string(),
+            scopes: scopes.to_vec(),
+            expires_at,
+        });
+        let hashed_token = hashed_token.to_string();
+        self.on_rollback(move |data| {
+            data.access_tokens
+                .retain(|row| row.hashed_token != hashed_token)
+        });
+        Ok(())
+    }
+
+    async fn find_access_token(
+        &self,
+        hashed_token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TokenGrant>> {
+        let data = self.data();
+        data.access_tokens
+            .iter()
+            .find(|row| row.hashed_token == hashed_token && row.expires_at > now)
+            .map(|row| {
+                Ok(TokenGrant {
+                    user: data.user_response(row.user_id)?,
+                    scopes: row.scopes.clone(),
+                })
+            })
+            .transpose()
+    }
+
+    async fn delete_access_token(&self, client_id: Uuid, hashed_token: &str) -> Result<()> {
+        let mut data = self.data();
+        let (deleted, kept) = data
+            .access_tokens
+            .drain(..)
+            .partition(|row| row.client_id == client_id && row.hashed_token == hashed_token);
+        data.access_tokens = kept;
+        self.on_rollback(move |data| data.access_tokens.extend(deleted));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TweetRepository for InMemoryStorage {
+    async fn create_tweet(&self, user_id: Uuid, text: &str, now: DateTime<Utc>) -> Result<Uuid> {
+        let mut data = self.data();
+        data.user(user_id)?;
+
+        let id = Uuid::new_v4();
+        data.tweets.push(Tweet {
+            id,
+            user_id,
+            text: text.to_string(),
+            created_at: now,
+            updated_at: now,
+            revisions: Vec::new(),
+        });
+        self.on_rollback(move |data| data.tweets.retain(|tweet| tweet.id != id));
+        Ok(id)
+    }
+
+    async fn find_tweet(&self, id: Uuid, viewer_id: Uuid) -> Result<Option<TweetResponse>> {
+        let data = self.data();
+        data.tweets
+            .iter()
+            .find(|tweet| tweet.id == id)
+            .map(|tweet| data.tweet_response(tweet, viewer_id))
+            .transpose()
+    }
+
+    async fn edit_tweet(
+        &self,
+        id: Uuid,
+        text: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TweetResponse>> {
+        let mut data = self.data();
+        let tweet = match data.tweets.iter_mut().find(|tweet| tweet.id == id) {
+            Some(tweet) => tweet,
+            None => return Ok(None),
+        };
+
+        let earlier_text = std::mem::replace(&mut tweet.text, text.to_string());
+        let earlier_updated_at = std::mem::replace(&mut tweet.updated_at, now);
+        tweet.revisions.push(TweetRevisionResponse {
+            text: earlier_text.clone(),
+            created_at: earlier_updated_at,
+        });
+
+        let data = &*data;
+        let edited = data
+            .tweets
+            .iter()
+            .find(|tweet| tweet.id == id)
+            .map(|tweet| data.tweet_response(tweet, tweet.user_id))
+            .transpose()?;
+
+        self.on_rollback(move |data| {
+            if let Some(tweet) = data.tweets.iter_mut().find(|tweet| tweet.id == id) {
+                tweet.text = earlier_text;
+                tweet.updated_at = earlier_updated_at;
+                tweet.revisions.pop();
+            }
+        });
+        Ok(edited)
+    }
+
+    async fn tweet_history(&self, id: Uuid) -> Result<Vec<TweetRevisionResponse>> {
+        Ok(self
+            .data()
+            .tweets
+            .iter()
+            .find(|tweet| tweet.id == id)
+            .map(|tweet| {
+                let mut history = tweet.revisions.clone();
+                history.push(TweetRevisionResponse {
+                    text: tweet.text.clone(),
+                    created_at: tweet.updated_at,
+                });
+                history
+            })
+            .unwrap_or_default())
+    }
+
+    async fn timeline(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<TweetResponse>> {
+        let data = self.data();
+        let is_visible = |author_id: Uuid| {
+            author_id == user_id
+                || data.follows.contains(&Follow {
+                    follower_id: user_id,
+                    followee_id: author_id,
+                })
+        };
+
+        // Newest first, and among tweets posted at the same time the last one inserted first
+        let mut tweets = data
+            .tweets
+            .iter()
+            .rev()
+            .filter(|tweet| is_visible(tweet.user_id))
+            .collect::<Vec<_>>();
+        tweets.sort_by_key(|tweet| std::cmp::Reverse(tweet.created_at));
+
+        tweets
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .map(|tweet| data.tweet_response(tweet, user_id))
+            .collect()
+    }
+    async fn delete_tweet(&self, id: Uuid) -> Result<bool> {
+        let mut data = self.data();
+        let (tweets, kept): (Vec<_>, _) = data.tweets.drain(..).partition(|tweet| tweet.id == id);
+        data.tweets = kept;
+        let (media, kept): (Vec<_>, _) = data
+            .media
+            .drain(..)
+            .partition(|row| row.media.tweet_id == Some(id));
+        data.media = kept;
+        let (polls, kept): (Vec<_>, _) = data.polls.drain(..).partition(|poll| poll.tweet_id == id);
+        data.polls = kept;
+        let (poll_votes, kept): (Vec<_>, _) = data
+            .poll_votes
+            .drain(..)
+            .partition(|vote| polls.iter().any(|poll| poll.id == vote.poll_id));
+        data.poll_votes = kept;
+        let (bookmarks, kept): (Vec<_>, _) =
+            data.bookmarks.drain(..).partition(|row| row.tweet_id == id);
+        data.bookmarks = kept;
+
+        let was_deleted = !tweets.is_empty();
+        self.on_rollback(move |data| {
+            data.tweets.extend(tweets);
+            data.media.extend(media);
+            data.polls.extend(polls);
+            data.poll_votes.extend(poll_votes);
+            data.bookmarks.extend(bookmarks);
+        });
+        Ok(was_deleted)
+    }
+}
+
+#[async_trait]
+impl ScheduledTweetRepository for InMemoryStorage {
+    async fn create_scheduled_tweet(
+        &self,
+        user_id: Uuid,
+        text: &str,
+        publish_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let mut data = self.data();
+        data.user(user_id)?;
+
+        let id = Uuid::new_v4();
+        data.scheduled_tweets.push(ScheduledTweet {
+            user_id,
+            tweet: ScheduledTweetResponse {
+                id,
+                text: text.to_string(),
+                publish_at,
+                created_at: now,
+            },
+        });
+        self.on_rollback(move |data| data.scheduled_tweets.retain(|row| row.tweet.id != id));
+        Ok(id)
+    }
+
+    async fn scheduled_tweets(&self, user_id: Uuid) -> Result<Vec<ScheduledTweetResponse>> {
+        let mut tweets = self
+            .data()
+            .scheduled_tweets
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .map(|row| row.tweet.clone())
+            .collect::<Vec<_>>();
+        tweets.sort_by_key(|tweet| (tweet.publish_at, tweet.created_at));
+        Ok(tweets)
+    }
+
+    async fn update_scheduled_tweet(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        text: Option<&str>,
+        publish_at: Option<DateTime<Utc>>,
+        _: DateTime<Utc>,
+    ) -> Result<Option<ScheduledTweetResponse>> {
+        let mut data = self.data();
+        let row = match data
+            .scheduled_tweets
+            .iter_mut()
+            .find(|row| row.tweet.id == id && row.user_id == user_id)
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let earlier = row.tweet.clone();
+        if let Some(text) = text {
+            row.tweet.text = text.to_string();
+        }
+        if let Some(publish_at) = publish_at {
+            row.tweet.publish_at = publish_at;
+        }
+        let updated = row.tweet.clone();
+
+        self.on_rollback(move |data| {
+            if let Some(row) = data
+                .scheduled_tweets
+                .iter_mut()
+                .find(|row| row.tweet.id == id)
+            {
+                row.tweet = earlier;
+            }
+        });
+        Ok(Some(updated))
+    }
+
+    async fn delete_scheduled_tweet(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let mut data = self.data();
+        let (deleted, kept): (Vec<_>, _) = data
+            .scheduled_tweets
+            .drain(..)
+            .partition(|row| row.tweet.id == id && row.user_id == user_id);
+        data.scheduled_tweets = kept;
+
+        let was_deleted = !deleted.is_empty();
+        self.on_rollback(move |data| data.scheduled_tweets.extend(deleted));
+        Ok(was_deleted)
+    }
+
+    async fn publish_due_tweets(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>> {
+        let mut data = self.data();
+        let (due, kept): (Vec<_>, _) = data
+            .scheduled_tweets
+            .drain(..)
+            .partition(|row| row.tweet.publish_at <= now);
+        data.scheduled_tweets = kept;
+
+        let ids = due.iter().map(|row| row.tweet.id).collect::<Vec<_>>();
+        data.tweets.extend(due.iter().map(|row| Tweet {
+            id: row.tweet.id,
+            user_id: row.user_id,
+            text: row.tweet.text.clone(),
+            created_at: now,
+            updated_at: now,
+            revisions: Vec::new(),
+        }));
+
+        let published = ids.clone();
+        self.on_rollback(move |data| {
+            data.tweets.retain(|tweet| !published.contains(&tweet.id));
+            data.scheduled_tweets.extend(due);
+        });
+        Ok(ids)
+    }
+}
+
+#[async_trait]
+impl DraftRepository for InMemoryStorage {
+    async fn create_draft(&self, user_id: Uuid, text: &str, now: DateTime<Utc>) -> Result<Uuid> {
+        let mut data = self.data();
+        data.user(user_id)?;
+
+        let id = Uuid::new_v4();
+        data.drafts.push(Draft {
+            user_id,
+            draft: DraftResponse {
+                id,
+                text: text.to_string(),
+                created_at: now,
+                updated_at: now,
+            },
+        });
+        self.on_rollback(move |data| data.drafts.retain(|row| row.draft.id != id));
+        Ok(id)
+    }
+
+    async fn drafts(&self, user_id: Uuid) -> Result<Vec<DraftResponse>> {
+        let mut drafts = self
+            .data()
+            .drafts
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .map(|row| row.draft.clone())
+            .collect::<Vec<_>>();
+        drafts.sort_by_key(|draft| std::cmp::Reverse((draft.updated_at, draft.created_at)));
+        Ok(drafts)
+    }
+
+    async fn count_drafts(&self, user_id: Uuid) -> Result<usize> {
+        Ok(self
+            .data()
+            .drafts
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .count())
+    }
+
+    async fn find_draft(&self, user_id: Uuid, id: Uuid) -> Result<Option<DraftResponse>> {
+        Ok(self
+            .data()
+            .drafts
+            .iter()
+            .find(|row| row.draft.id == id && row.user_id == user_id)
+            .map(|row| row.draft.clone()))
+    }
+
+    async fn update_draft(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        text: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DraftResponse>> {
+        let mut data = self.data();
+        let row = match data
+            .drafts
+            .iter_mut()
+            .find(|row| row.draft.id == id && row.user_id == user_id)
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let earlier = row.draft.clone();
+        row.draft.text = text.to_string();
+        row.draft.updated_at = now;
+        let updated = row.draft.clone();
+
+        self.on_rollback(move |data| {
+            if let Some(row) = data.drafts.iter_mut().find(|row| row.draft.id == id) {
+                row.draft = earlier;
+            }
+        });
+        Ok(Some(updated))
+    }
+
+    async fn delete_draft(&self, user_id: Uuid, id: Uuid) -> Result<Option<DraftResponse>> {
+        let mut data = self.data();
+        let index = match data
+            .drafts
+            .iter()
+            .position(|row| row.draft.id == id && row.user_id == user_id)
+        {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let deleted = data.drafts.remove(index);
+        let draft = deleted.draft.clone();
+        self.on_rollback(move |data| data.drafts.push(deleted));
+        Ok(Some(draft))
+    }
+}
+
+#[async_trait]
+impl MediaRepository for InMemoryStorage {
+    async fn create_media(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        content_type: &str,
+        width: u32,
+        height: u32,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        if data.media.iter().any(|row| row.media.id == id) {
+            return Err(unique_violation("media_pkey"));
+        }
+
+        data.media.push(StoredMedia {
+            media: Media {
+                id,
+                user_id,
+                content_type: content_type.to_string(),
+                width,
+                height,
+                tweet_id: None,
+            },
+            position: 0,
+        });
+        self.on_rollback(move |data| data.media.retain(|row| row.media.id != id));
+        Ok(())
+    }
+
+    async fn find_media(&self, id: Uuid) -> Result<Option<Media>> {
+        Ok(self
+            .data()
+            .media
+            .iter()
+            .find(|row| row.media.id == id)
+            .map(|row| row.media.clone()))
+    }
+
+    async fn attach_media(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        tweet_id: Uuid,
+        position: u32,
+    ) -> Result<bool> {
+        let mut data = self.data();
+        if !data.tweets.iter().any(|tweet| tweet.id == tweet_id) {
+            return Err(StorageError::ForeignKeyViolation);
+        }
+
+        let row = match data.media.iter_mut().find(|row| {
+            row.media.id == id && row.media.user_id == user_id && row.media.tweet_id.is_none()
+        }) {
+            Some(row) => row,
+            None => return Ok(false),
+        };
+        row.media.tweet_id = Some(tweet_id);
+        row.position = position;
+        self.on_rollback(move |data| {
+            if let Some(row) = data.media.iter_mut().find(|row| row.media.id == id) {
+                row.media.tweet_id = None;
+            }
+        });
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl PollRepository for InMemoryStorage {
+    async fn create_poll(
+        &self,
+        tweet_id: Uuid,
+        options: &[String],
+        closes_at: DateTime<Utc>,
+        _: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let mut data = self.data();
+        if !data.tweets.iter().any(|tweet| tweet.id == tweet_id) {
+            return Err(StorageError::ForeignKeyViolation);
+        }
+        if data.polls.iter().any(|poll| poll.tweet_id == tweet_id) {
+            return Err(unique_violation("polls_tweet_id"));
+        }
+
+        let id = Uuid::new_v4();
+        data.polls.push(Poll {
+            id,
+            tweet_id,
+            options: options.to_vec(),
+            closes_at,
+        });
+        self.on_rollback(move |data| data.polls.retain(|poll| poll.id != id));
+        Ok(id)
+    }
+
+    async fn vote_in_poll(
+        &self,
+        tweet_id: Uuid,
+        user_id: Uuid,
+        option: u32,
+        _: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut data = self.data();
+        let poll_id = match data
+            .polls
+            .iter()
+            .find(|poll| poll.tweet_id == tweet_id && (option as usize) < poll.options.len())
+        {
+            Some(poll) => poll.id,
+            None => return Ok(false),
+        };
+        data.user(user_id)?;
+        if data
+            .poll_votes
+            .iter()
+            .any(|vote| vote.poll_id == poll_id && vote.user_id == user_id)
+        {
+            return Err(unique_violation("poll_votes_poll_id_user_id"));
+        }
+
+        data.poll_votes.push(PollVote {
+            poll_id,
+            user_id,
+            option,
+        });
+        self.on_rollback(move |data| {
+            data.poll_votes
+                .retain(|vote| !(vote.poll_id == poll_id && vote.user_id == user_id))
+        });
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl BookmarkRepository for InMemoryStorage {
+    async fn create_bookmark(
+        &self,
+        user_id: Uuid,
+        tweet_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(user_id)?;
+        if !data.tweets.iter().any(|tweet| tweet.id == tweet_id) {
+            return Err(StorageError::ForeignKeyViolation);
+        }
+        if data
+            .bookmarks
+            .iter()
+            .any(|row| row.user_id == user_id && row.tweet_id == tweet_id)
+        {
+            return Err(unique_violation("bookmarks_user_id_tweet_id"));
+        }
+
+        let id = Uuid::new_v4();
+        data.bookmarks.push(StoredBookmark {
+            user_id,
+            tweet_id,
+            cursor: BookmarkCursor {
+                created_at: now,
+                id,
+            },
+        });
+        self.on_rollback(move |data| data.bookmarks.retain(|row| row.cursor.id != id));
+        Ok(())
+    }
+
+    async fn delete_bookmark(&self, user_id: Uuid, tweet_id: Uuid) -> Result<bool> {
+        let mut data = self.data();
+        let (deleted, kept): (Vec<_>, _) = data
+            .bookmarks
+            .drain(..)
+            .partition(|row| row.user_id == user_id && row.tweet_id == tweet_id);
+        data.bookmarks = kept;
+        let was_deleted = !deleted.is_empty();
+        self.on_rollback(move |data| data.bookmarks.extend(deleted));
+        Ok(was_deleted)
+    }
+
+    async fn bookmarks(
+        &self,
+        user_id: Uuid,
+        after: Option<BookmarkCursor>,
+        limit: i64,
+    ) -> Result<Vec<Bookmark>> {
+        let data = self.data();
+        let position = |cursor: &BookmarkCursor| (cursor.created_at, cursor.id);
+
+        // Bookmarks made at the same time are ordered by id, like in the database
+        let mut bookmarks = data
+            .bookmarks
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .filter(|row| match &after {
+                Some(after) => position(&row.cursor) < position(after),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        bookmarks.sort_by_key(|row| std::cmp::Reverse(position(&row.cursor)));
+
+        bookmarks
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|row| {
+                let tweet = data
+                    .tweets
+                    .iter()
+                    .find(|tweet| tweet.id == row.tweet_id)
+                    .ok_or(StorageError::ForeignKeyViolation)?;
+                Ok(Bookmark {
+                    cursor: row.cursor,
+                    tweet: data.tweet_response(tweet, user_id)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl FollowRepository for InMemoryStorage {
+    async fn create_follow(
+        &self,
+        follower_id: Uuid,
+        followee_id: Uuid,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        data.user(follower_id)?;
+        data.user(followee_id)?;
+
+        let follow = Follow {
+            follower_id,
+            followee_id,
+        };
+        if data.follows.contains(&follow) {
+            return Err(unique_violation("follows_follower_followee"));
+        }
+        data.follows.push(follow.clone());
+        self.on_rollback(move |data| data.follows.retain(|row| *row != follow));
+        Ok(())
+    }
+
+    async fn delete_follow(&self, follower_id: Uuid, followee_id: Uuid) -> Result<bool> {
+        let follow = Follow {
+            follower_id,
+            followee_id,
+        };
+        let mut data = self.data();
+        let count = data.follows.len();
+        data.follows.retain(|row| *row != follow);
+        let deleted = data.follows.len() < count;
+        if deleted {
+            self.on_rollback(move |data| data.follows.push(follow));
+        }
+        Ok(deleted)
+    }
+
+    async fn is_following(&self, follower_id: Uuid, followee_id: Uuid) -> Result<bool> {
+        Ok(self.data().follows.contains(&Follow {
+            follower_id,
+            followee_id,
+        }))
+    }
+
+    async fn following(&self, user_id: Uuid) -> Result<Vec<UserResponse>> {
+        let data = self.data();
+        let ids = data
+            .follows
+            .iter()
+            .filter(|follow| follow.follower_id == user_id)
+            .map(|follow| follow.followee_id);
+        data.sorted_by_username(ids)
+    }
+
+    async fn followers(&self, user_id: Uuid) -> Result<Vec<UserResponse>> {
+        let data = self.data();
+        let ids = data
+            .follows
+            .iter()
+            .filter(|follow| follow.followee_id == user_id)
+            .map(|follow| follow.follower_id);
+        data.sorted_by_username(ids)
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for InMemoryStorage {
+    async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        request_hash: &str,
+        now: DateTime<Utc>,
+        expired_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotentRequest>> {
+        let mut data = self.data();
+        // Expired keys can't be claimed either way, so this isn't undone on rollback
+        data.idempotency_keys
+            .retain(|row| row.created_at >= expired_before);
+
+        if let Some(earlier) = data.idempotency_keys.iter().find(|row| row.is(scope, key)) {
+            return Ok(Some(earlier.request.clone()));
+        }
+
+        data.idempotency_keys.push(IdempotencyKey {
+            scope: scope.to_string(),
+            key: key.to_string(),
+            request: IdempotentRequest {
+                request_hash: request_hash.to_string(),
+                response: None,
+            },
+            created_at: now,
+        });
+        let (scope, key) = (scope.to_string(), key.to_string());
+        self.on_rollback(move |data| data.idempotency_keys.retain(|row| !row.is(&scope, &key)));
+        Ok(None)
+    }
+
+    async fn save_idempotent_response(
+        &self,
+        scope: &str,
+        key: &str,
+        response: &StoredResponse,
+        _: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut data = self.data();
+        if let Some(row) = data
+            .idempotency_keys
+            .iter_mut()
+            .find(|row| row.is(scope, key))
+        {
+            let earlier = row.request.response.replace(response.clone());
+            let (scope, key) = (scope.to_string(), key.to_string());
+            self.on_rollback(move |data| {
+                if let Some(row) = data
+                    .idempotency_keys
+                    .iter_mut()
+                    .find(|row| row.is(&scope, &key))
+                {
+                    row.request.response = earlier;
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<()> {
+        let mut data = self.data();
+        let (released, kept) = data
+            .idempotency_keys
+            .drain(..)
+            .partition(|row| row.is(scope, key));
+        data.idempotency_keys = kept;
+        self.on_rollback(move |data| data.idempotency_keys.extend(released));
+        Ok(())
+    }
+}
@@ -0,0 +1,543 @@
+//! Repositories for users, session tokens, two-factor auth, API tokens, OAuth, tweets, scheduled
+//! tweets, drafts, media, polls, bookmarks, follows and idempotency keys.
+//!
+//! Handlers go through `State::storage` rather than querying those tables directly, so they can
+//! run against `InMemoryStorage` in tests. Both implementations have to pass the suite in
+//! `contract`.
+
+use async_trait::async_trait;
+use chrono::prelude::*;
+use shared::responses::{
+    ApiTokenResponse, DraftResponse, MediaResponse, PollOptionResponse, PollResponse,
+    ScheduledTweetResponse, TweetResponse, TweetRevisionResponse, UserResponse,
+};
+use shared::Scope;
+use std::fmt;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[cfg(test)]
+mod contract;
+#[cfg(test)]
+mod memory;
+mod postgres;
+
+#[cfg(test)]
+pub use memory::InMemoryStorage;
+pub use postgres::PgStorage;
+
+pub type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    /// `constraint` is the name of the unique index, e.g. `users_username`.
+    #[error("duplicate value violates `{constraint}`")]
+    UniqueViolation { constraint: String },
+    #[error("referenced row doesn't exist")]
+    ForeignKeyViolation,
+    #[error("{0}")]
+    Database(sqlx::Error),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    pub hashed_password: String,
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create_user(
+        &self,
+        username: &str,
+        hashed_password: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid>;
+
+    /// Usernames are compared ignoring case.
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>>;
+}
+
+/// Session tokens from logging in with a password. Each user has at most one.
+#[async_trait]
+pub trait TokenRepository: Send + Sync {
+    async fn create_auth_token(&self, user_id: Uuid, token: &str, now: DateTime<Utc>)
+        -> Result<()>;
+
+    async fn find_auth_token(&self, user_id: Uuid) -> Result<Option<String>>;
+
+    async fn find_user_by_auth_token(&self, token: &str) -> Result<Option<UserResponse>>;
+
+    async fn delete_auth_token(&self, token: &str) -> Result<()>;
+}
+
+/// A user's authenticator app secret. It can only be used to log in once it's been confirmed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TotpSecret {
+    pub secret: String,
+    pub confirmed: bool,
+}
+
+/// Recovery codes are looked up by a hash, like API tokens.
+#[async_trait]
+pub trait TwoFactorRepository: Send + Sync {
+    /// Replaces the user's secret if it hasn't been confirmed yet.
+    async fn set_pending_totp_secret(
+        &self,
+        user_id: Uuid,
+        secret: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    async fn find_totp_secret(&self, user_id: Uuid) -> Result<Option<TotpSecret>>;
+
+    async fn confirm_totp_secret(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<()>;
+
+    /// Records that a code for `step` was accepted. Returns `false` if a code for the same or a
+    /// later step was accepted before, since that code could be replayed otherwise.
+    async fn use_totp_step(&self, user_id: Uuid, step: i64, now: DateTime<Utc>) -> Result<bool>;
+
+    /// Replaces any recovery codes the user had.
+    async fn replace_recovery_codes(
+        &self,
+        user_id: Uuid,
+        hashed_codes: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Marks the recovery code as used. Returns `false` if the user has no such unused code.
+    async fn use_recovery_code(
+        &self,
+        user_id: Uuid,
+        hashed_code: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool>;
+
+    /// Challenges are issued when logging in with a password, for the second factor.
+    async fn create_second_factor_challenge(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// The user the challenge was issued to, if it hasn't expired and they have `username`.
+    /// Usernames are compared ignoring case.
+    async fn find_second_factor_challenge(
+        &self,
+        token: &str,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Uuid>>;
+
+    async fn delete_second_factor_challenge(&self, token: &str) -> Result<()>;
+
+    /// Deletes the user's secret, recovery codes and challenges.
+    async fn delete_two_factor(&self, user_id: Uuid) -> Result<()>;
+}
+
+/// Who a scoped token was issued to and what it allows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenGrant {
+    pub user: UserResponse,
+    pub scopes: Vec<Scope>,
+}
+
+/// API tokens are looked up by a hash, so the tokens themselves are never stored.
+#[async_trait]
+pub trait ApiTokenRepository: Send + Sync {
+    async fn create_api_token(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        hashed_token: &str,
+        scopes: &[Scope],
+        now: DateTime<Utc>,
+    ) -> Result<ApiTokenResponse>;
+
+    /// Oldest first.
+    async fn api_tokens(&self, user_id: Uuid) -> Result<Vec<ApiTokenResponse>>;
+
+    /// Returns whether the user had a token with that id.
+    async fn delete_api_token(&self, user_id: Uuid, id: Uuid) -> Result<bool>;
+
+    /// Also records that the token was used at `now`.
+    async fn use_api_token(
+        &self,
+        hashed_token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TokenGrant>>;
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OAuthClient {
+    pub id: Uuid,
+    pub name: String,
+    /// `None` for public clients.
+    pub hashed_client_secret: Option<String>,
+    pub redirect_uris: Vec<String>,
+}
+
+/// What a user approved for a client. Clients exchange an authorization code for it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AuthorizationGrant {
+    pub client_id: Uuid,
+    pub user_id: Uuid,
+    pub redirect_uri: String,
+    pub scopes: Vec<Scope>,
+    pub code_challenge: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Authorization codes, access tokens and client secrets are all looked up by a hash.
+#[async_trait]
+pub trait OAuthRepository: Send + Sync {
+    async fn create_oauth_client(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        hashed_client_secret: Option<&str>,
+        redirect_uris: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<Uuid>;
+
+    async fn find_oauth_client(&self, id: Uuid) -> Result<Option<OAuthClient>>;
+
+    async fn create_authorization_code(
+        &self,
+        hashed_code: &str,
+        grant: &AuthorizationGrant,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Deletes the code and returns what it granted, so each code can only be used once. Expired
+    /// codes are returned too.
+    async fn take_authorization_code(
+        &self,
+        hashed_code: &str,
+    ) -> Result<Option<AuthorizationGrant>>;
+
+    async fn create_access_token(
+        &self,
+        client_id: Uuid,
+        user_id: Uuid,
+        hashed_token: &str,
+        scopes: &[Scope],
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Expired tokens aren't found.
+    async fn find_access_token(
+        &self,
+        hashed_token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TokenGrant>>;
+
+    /// Only deletes the token if it was issued to the client.
+    async fn delete_access_token(&self, client_id: Uuid, hashed_token: &str) -> Result<()>;
+}
+
+/// Tweets are returned as the viewer sees them, e.g. with the option they voted for in a poll and
+/// whether they bookmarked it.
+/// Polls come with every vote counted and never `closed`, since that depends on the time;
+/// `endpoints::polls::for_viewer` sorts that out.
+#[async_trait]
+pub trait TweetRepository: Send + Sync {
+    async fn create_tweet(&self, user_id: Uuid, text: &str, now: DateTime<Utc>) -> Result<Uuid>;
+
+    async fn find_tweet(&self, id: Uuid, viewer_id: Uuid) -> Result<Option<TweetResponse>>;
+
+    /// Keeps the current text as a revision. Returns `None` if there is no tweet with that id.
+    /// Only authors can edit tweets, so it's returned as its author sees it.
+    async fn edit_tweet(
+        &self,
+        id: Uuid,
+        text: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TweetResponse>>;
+
+    /// Every version of the tweet, oldest first. Empty if there is no tweet with that id.
+    async fn tweet_history(&self, id: Uuid) -> Result<Vec<TweetRevisionResponse>>;
+
+    /// Tweets by the user and the users they follow, newest first, as the user sees them.
+    async fn timeline(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<TweetResponse>>;
+
+    /// Deletes its revisions, media, poll and bookmarks with it, but not the media files. Returns
+    /// whether there was a tweet to delete. Nothing but the contract tests deletes tweets yet.
+    #[allow(dead_code)]
+    async fn delete_tweet(&self, id: Uuid) -> Result<bool>;
+}
+
+/// Tweets waiting for their `publish_at`. They keep their id when they're published.
+#[async_trait]
+pub trait ScheduledTweetRepository: Send + Sync {
+    async fn create_scheduled_tweet(
+        &self,
+        user_id: Uuid,
+        text: &str,
+        publish_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid>;
+
+    /// Soonest first.
+    async fn scheduled_tweets(&self, user_id: Uuid) -> Result<Vec<ScheduledTweetResponse>>;
+
+    /// Leaves out the fields that are `None`. Returns `None` if the user has no scheduled tweet
+    /// with that id.
+    async fn update_scheduled_tweet(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        text: Option<&str>,
+        publish_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ScheduledTweetResponse>>;
+
+    /// Returns whether there was a scheduled tweet to delete.
+    async fn delete_scheduled_tweet(&self, user_id: Uuid, id: Uuid) -> Result<bool>;
+
+    /// Turns the scheduled tweets that are due by `now` into tweets posted at `now`, so they don't
+    /// show up behind newer tweets if the scheduler runs late. Returns their ids.
+    async fn publish_due_tweets(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>>;
+}
+
+#[async_trait]
+pub trait DraftRepository: Send + Sync {
+    async fn create_draft(&self, user_id: Uuid, text: &str, now: DateTime<Utc>) -> Result<Uuid>;
+
+    /// Most recently updated first.
+    async fn drafts(&self, user_id: Uuid) -> Result<Vec<DraftResponse>>;
+
+    async fn count_drafts(&self, user_id: Uuid) -> Result<usize>;
+
+    async fn find_draft(&self, user_id: Uuid, id: Uuid) -> Result<Option<DraftResponse>>;
+
+    /// Returns `None` if the user has no draft with that id.
+    async fn update_draft(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        text: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DraftResponse>>;
+
+    /// Returns the deleted draft, if there was one.
+    async fn delete_draft(&self, user_id: Uuid, id: Uuid) -> Result<Option<DraftResponse>>;
+}
+
+/// An uploaded image. Its files are kept in a `BlobStore`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Media {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// Set once it's attached to a tweet.
+    pub tweet_id: Option<Uuid>,
+}
+
+impl Media {
+    pub fn response(&self) -> MediaResponse {
+        MediaResponse::new(self.id, &self.content_type, self.width, self.height)
+    }
+}
+
+#[async_trait]
+pub trait MediaRepository: Send + Sync {
+    /// The id is picked by the caller since the files are stored under it before this is called.
+    async fn create_media(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        content_type: &str,
+        width: u32,
+        height: u32,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    async fn find_media(&self, id: Uuid) -> Result<Option<Media>>;
+
+    /// Only attaches media the user uploaded that isn't attached to a tweet yet. Returns whether
+    /// it did. Tweets list their media by `position`.
+    async fn attach_media(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        tweet_id: Uuid,
+        position: u32,
+    ) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait PollRepository: Send + Sync {
+    /// A tweet can only have one poll. Its options are shown in the order given.
+    async fn create_poll(
+        &self,
+        tweet_id: Uuid,
+        options: &[String],
+        closes_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid>;
+
+    /// Votes for the option at position `option` of the tweet's poll. Returns `false` if there is
+    /// no such poll or option. Voting twice in a poll is a `UniqueViolation` of
+    /// `poll_votes_poll_id_user_id`.
+    async fn vote_in_poll(
+        &self,
+        tweet_id: Uuid,
+        user_id: Uuid,
+        option: u32,
+        now: DateTime<Utc>,
+    ) -> Result<bool>;
+}
+
+/// Where a page of bookmarks starts: right after the bookmark made at `created_at` with `id`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BookmarkCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    /// Points at this bookmark, for starting the next page after it.
+    pub cursor: BookmarkCursor,
+    pub tweet: TweetResponse,
+}
+
+/// Bookmarks are private, so they're only looked up by the user who made them.
+#[async_trait]
+pub trait BookmarkRepository: Send + Sync {
+    /// Bookmarking a tweet twice is a `UniqueViolation` of `bookmarks_user_id_tweet_id`.
+    async fn create_bookmark(
+        &self,
+        user_id: Uuid,
+        tweet_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Returns whether there was a bookmark to delete.
+    async fn delete_bookmark(&self, user_id: Uuid, tweet_id: Uuid) -> Result<bool>;
+
+    /// Most recently bookmarked first, starting after `after` if it's given.
+    async fn bookmarks(
+        &self,
+        user_id: Uuid,
+        after: Option<BookmarkCursor>,
+        limit: i64,
+    ) -> Result<Vec<Bookmark>>;
+}
+
+#[async_trait]
+pub trait FollowRepository: Send + Sync {
+    async fn create_follow(
+        &self,
+        follower_id: Uuid,
+        followee_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Returns whether there was a follow to delete.
+    async fn delete_follow(&self, follower_id: Uuid, followee_id: Uuid) -> Result<bool>;
+
+    async fn is_following(&self, follower_id: Uuid, followee_id: Uuid) -> Result<bool>;
+
+    /// The users `user_id` follows.
+    async fn following(&self, user_id: Uuid) -> Result<Vec<UserResponse>>;
+
+    /// The users following `user_id`.
+    async fn followers(&self, user_id: Uuid) -> Result<Vec<UserResponse>>;
+}
+
+/// An earlier request made with the same idempotency key.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct IdempotentRequest {
+    pub request_hash: String,
+    /// `None` while the earlier request is still being handled.
+    pub response: Option<StoredResponse>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Keys are unique per `scope`, so clients can't see each other's responses.
+#[async_trait]
+pub trait IdempotencyRepository: Send + Sync {
+    /// Claims `key` for a request, unless it has already been claimed. Returns the earlier request
+    /// if it has. Keys claimed before `expired_before` are deleted first.
+    async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        request_hash: &str,
+        now: DateTime<Utc>,
+        expired_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotentRequest>>;
+
+    async fn save_idempotent_response(
+        &self,
+        scope: &str,
+        key: &str,
+        response: &StoredResponse,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Lets the key be claimed again, e.g. because the request failed.
+    async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<()>;
+}
+
+/// Everything handlers can read and write, whether or not they're in a transaction.
+pub trait Repositories:
+    UserRepository
+    + TokenRepository
+    + TwoFactorRepository
+    + ApiTokenRepository
+    + OAuthRepository
+    + TweetRepository
+    + ScheduledTweetRepository
+    + DraftRepository
+    + MediaRepository
+    + PollRepository
+    + BookmarkRepository
+    + FollowRepository
+    + IdempotencyRepository
+    + fmt::Debug
+{
+}
+
+impl<T> Repositories for T where
+    T: UserRepository
+        + TokenRepository
+        + TwoFactorRepository
+        + ApiTokenRepository
+        + OAuthRepository
+        + TweetRepository
+        + ScheduledTweetRepository
+        + DraftRepository
+        + MediaRepository
+        + PollRepository
+        + BookmarkRepository
+        + FollowRepository
+        + IdempotencyRepository
+        + fmt::Debug
+{
+}
+
+#[async_trait]
+pub trait Storage: Repositories + 'static {
+    /// Starts a transaction. It's rolled back if it's dropped without calling `commit`, e.g.
+    /// when a handler returns early with an error.
+    async fn begin(&self) -> Result<Box<dyn Transaction>>;
+}
+
+#[async_trait]
+pub trait Transaction: Repositories {
+    async fn commit(self: Box<Self>) -> Result<()>;
+}
@@ -0,0 +1,1679 @@
+use super::*;
+use async_std::sync::Mutex;
+use sqlx::pool::PoolConnection;
+use sqlx::{query, query_as, PgConnection, PgPool};
+
+type PgTransaction = sqlx::Transaction<PoolConnection<PgConnection>>;
+
+/// Runs the queries on the pool, or on the open transaction when the storage came from `begin`.
+#[derive(Debug)]
+pub struct PgStorage {
+    conn: Conn,
+}
+
+enum Conn {
+    Pool(PgPool),
+    Transaction(Box<Mutex<PgTransaction>>),
+}
+
+impl fmt::Debug for Conn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conn::Pool(db_pool) => f.debug_tuple("Pool").field(db_pool).finish(),
+            Conn::Transaction(_) => f.write_str("Transaction"),
+        }
+    }
+}
+
+/// Runs `$query` with `$method`, e.g. `fetch_one`, on whichever connection `$storage` has.
+macro_rules! run {
+    ($storage:expr, $method:ident, $query:expr) => {{
+        // The block is needed since `query_as!` expands to an expression with an attribute
+        let query = { $query };
+        match &$storage.conn {
+            Conn::Pool(db_pool) => query.$method(db_pool).await,
+            Conn::Transaction(tx) => query.$method(&mut *tx.lock().await).await,
+        }
+    }};
+}
+
+impl PgStorage {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self {
+            conn: Conn::Pool(db_pool),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        let tx = match &self.conn {
+            Conn::Pool(db_pool) => db_pool.begin().await?,
+            Conn::Transaction(_) => {
+                unreachable!("transactions are only handed out as `dyn Transaction`")
+            }
+        };
+
+        Ok(Box::new(Self {
+            conn: Conn::Transaction(Box::new(Mutex::new(tx))),
+        }))
+    }
+}
+
+#[async_trait]
+impl Transaction for PgStorage {
+    async fn commit(self: Box<Self>) -> Result<()> {
+        if let Conn::Transaction(tx) = self.conn {
+            tx.into_inner().commit().await?;
+        }
+        Ok(())
+    }
+}
+
+impl From<sqlx::Error> for StorageError {
+    fn from(err: sqlx::Error) -> Self {
+        // https://www.postgresql.org/docs/current/errcodes-appendix.html
+        if let sqlx::Error::Database(db_err) = &err {
+            match db_err.code() {
+                Some("23505") => {
+                    return StorageError::UniqueViolation {
+                        constraint: db_err.constraint_name().unwrap_or_default().to_string(),
+                    }
+                }
+                Some("23503") => return StorageError::ForeignKeyViolation,
+                _ => {}
+            }
+        }
+        StorageError::Database(err)
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgStorage {
+    async fn create_user(
+        &self,
+        username: &str,
+        hashed_password: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into users (id, username, hashed_password, created_at, updated_at)
+                values ($1, $2, $3, $4, $5) returning id
+            "#,
+                Uuid::new_v4(),
+                username,
+                hashed_password,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(row.id)
+    }
+
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let user = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                User,
+                r#"
+                select id, username, hashed_password
+                from users
+                where lower(username) = lower($1)
+            "#,
+                username
+            )
+        )?;
+
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl TokenRepository for PgStorage {
+    async fn create_auth_token(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into auth_tokens (id, user_id, token, created_at, updated_at)
+                values ($1, $2, $3, $4, $5)
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                token,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn find_auth_token(&self, user_id: Uuid) -> Result<Option<String>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!("select token from auth_tokens where user_id = $1", user_id)
+        )?;
+
+        Ok(row.map(|row| row.token))
+    }
+
+    async fn find_user_by_auth_token(&self, token: &str) -> Result<Option<UserResponse>> {
+        let user = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                UserResponse,
+                r#"
+                select users.id, users.username
+                from users
+                inner join auth_tokens
+                    on auth_tokens.user_id = users.id
+                    and auth_tokens.token = $1
+            "#,
+                token
+            )
+        )?;
+
+        Ok(user)
+    }
+
+    async fn delete_auth_token(&self, token: &str) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!("delete from auth_tokens where token = $1", token)
+        )?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for PgStorage {
+    async fn set_pending_totp_secret(
+        &self,
+        user_id: Uuid,
+        secret: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into totp_secrets (id, user_id, secret, created_at, updated_at)
+                values ($1, $2, $3, $4, $5)
+                on conflict (user_id) do update
+                set secret = excluded.secret, last_used_step = null, updated_at = excluded.updated_at
+                where totp_secrets.confirmed_at is null
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                secret,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn find_totp_secret(&self, user_id: Uuid) -> Result<Option<TotpSecret>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                "select secret, confirmed_at from totp_secrets where user_id = $1",
+                user_id
+            )
+        )?;
+
+        Ok(row.map(|row| TotpSecret {
+            secret: row.secret,
+            confirmed: row.confirmed_at.is_some(),
+        }))
+    }
+
+    async fn confirm_totp_secret(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                "update totp_secrets set confirmed_at = $1, updated_at = $1 where user_id = $2",
+                now,
+                user_id,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, user_id: Uuid, step: i64, now: DateTime<Utc>) -> Result<bool> {
+        let rows_updated = run!(
+            self,
+            execute,
+            query!(
+                r#"
+                update totp_secrets
+                set last_used_step = $1, updated_at = $2
+                where user_id = $3 and (last_used_step is null or last_used_step < $1)
+            "#,
+                step,
+                now,
+                user_id,
+            )
+        )?;
+
+        Ok(rows_updated > 0)
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: Uuid,
+        hashed_codes: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!("delete from recovery_codes where user_id = $1", user_id)
+        )?;
+
+        for hashed_code in hashed_codes {
+            run!(
+                self,
+                execute,
+                query!(
+                    r#"
+                    insert into recovery_codes (id, user_id, hashed_code, created_at, updated_at)
+                    values ($1, $2, $3, $4, $5)
+                "#,
+                    Uuid::new_v4(),
+                    user_id,
+                    hashed_code,
+                    now,
+                    now,
+                )
+            )?;
+        }
+
+        Ok(())
+    }
+
+    async fn use_recovery_code(
+        &self,
+        user_id: Uuid,
+        hashed_code: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        let rows_updated = run!(
+            self,
+            execute,
+            query!(
+                r#"
+                update recovery_codes
+                set used_at = $1, updated_at = $1
+                where user_id = $2 and hashed_code = $3 and used_at is null
+            "#,
+                now,
+                user_id,
+                hashed_code,
+            )
+        )?;
+
+        Ok(rows_updated > 0)
+    }
+
+    async fn create_second_factor_challenge(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into second_factor_challenges
+                    (id, user_id, token, expires_at, created_at, updated_at)
+                values ($1, $2, $3, $4, $5, $6)
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                token,
+                expires_at,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn find_second_factor_challenge(
+        &self,
+        token: &str,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Uuid>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                select second_factor_challenges.user_id
+                from second_factor_challenges
+                inner join users on users.id = second_factor_challenges.user_id
+                where
+                    second_factor_challenges.token = $1
+                    and lower(users.username) = lower($2)
+                    and second_factor_challenges.expires_at > $3
+            "#,
+                token,
+                username,
+                now,
+            )
+        )?;
+
+        Ok(row.map(|row| row.user_id))
+    }
+
+    async fn delete_second_factor_challenge(&self, token: &str) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                "delete from second_factor_challenges where token = $1",
+                token
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_two_factor(&self, user_id: Uuid) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                with recovery_codes as (
+                    delete from recovery_codes where user_id = $1
+                ), challenges as (
+                    delete from second_factor_challenges where user_id = $1
+                )
+                delete from totp_secrets where user_id = $1
+            "#,
+                user_id
+            )
+        )?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepository for PgStorage {
+    async fn create_api_token(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        hashed_token: &str,
+        scopes: &[Scope],
+        now: DateTime<Utc>,
+    ) -> Result<ApiTokenResponse> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into api_tokens
+                    (id, user_id, name, hashed_token, scopes, created_at, updated_at)
+                values ($1, $2, $3, $4, $5, $6, $7) returning id, name, created_at
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                name,
+                hashed_token,
+                Scope::format_list(scopes),
+                now,
+                now,
+            )
+        )?;
+
+        Ok(ApiTokenResponse {
+            id: row.id,
+            name: row.name,
+            scopes: scopes.to_vec(),
+            created_at: row.created_at,
+            last_used_at: None,
+        })
+    }
+
+    async fn api_tokens(&self, user_id: Uuid) -> Result<Vec<ApiTokenResponse>> {
+        let rows = run!(
+            self,
+            fetch_all,
+            query!(
+                r#"
+                select id, name, scopes, created_at, last_used_at
+                from api_tokens
+                where user_id = $1
+                order by created_at
+            "#,
+                user_id
+            )
+        )?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ApiTokenResponse {
+                    id: row.id,
+                    name: row.name,
+                    scopes: parse_scopes(&row.scopes)?,
+                    created_at: row.created_at,
+                    last_used_at: row.last_used_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_api_token(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let rows_deleted = run!(
+            self,
+            execute,
+            query!(
+                "delete from api_tokens where id = $1 and user_id = $2",
+                id,
+                user_id
+            )
+        )?;
+
+        Ok(rows_deleted > 0)
+    }
+
+    async fn use_api_token(
+        &self,
+        hashed_token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TokenGrant>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                with used as (
+                    update api_tokens set last_used_at = $2
+                    where hashed_token = $1
+                    returning user_id, scopes
+                )
+                select used.scopes, users.id, users.username
+                from used
+                inner join users on users.id = used.user_id
+            "#,
+                hashed_token,
+                now,
+            )
+        )?;
+
+        row.map(|row| {
+            Ok(TokenGrant {
+                user: UserResponse {
+                    id: row.id,
+                    username: row.username,
+                },
+                scopes: parse_scopes(&row.scopes)?,
+            })
+        })
+        .transpose()
+    }
+}
+
+#[async_trait]
+impl OAuthRepository for PgStorage {
+    async fn create_oauth_client(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        hashed_client_secret: Option<&str>,
+        redirect_uris: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into oauth_clients
+                    (id, user_id, name, hashed_client_secret, redirect_uris, created_at, updated_at)
+                values ($1, $2, $3, $4, $5, $6, $7) returning id
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                name,
+                hashed_client_secret,
+                redirect_uris.join(" "),
+                now,
+                now,
+            )
+        )?;
+
+        Ok(row.id)
+    }
+
+    async fn find_oauth_client(&self, id: Uuid) -> Result<Option<OAuthClient>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                select id, name, hashed_client_secret, redirect_uris
+                from oauth_clients
+                where id = $1
+            "#,
+                id
+            )
+        )?;
+
+        // Redirect URIs can't contain spaces, so they're stored space separated
+        Ok(row.map(|row| OAuthClient {
+            id: row.id,
+            name: row.name,
+            hashed_client_secret: row.hashed_client_secret,
+            redirect_uris: row.redirect_uris.split(' ').map(str::to_string).collect(),
+        }))
+    }
+
+    async fn create_authorization_code(
+        &self,
+        hashed_code: &str,
+        grant: &AuthorizationGrant,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into oauth_authorization_codes
+                    (id, client_id, user_id, hashed_code, redirect_uri, scopes, code_challenge,
+                     expires_at, created_at, updated_at)
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+                Uuid::new_v4(),
+                grant.client_id,
+                grant.user_id,
+                hashed_code,
+                grant.redirect_uri,
+                Scope::format_list(&grant.scopes),
+                grant.code_challenge,
+                grant.expires_at,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn take_authorization_code(
+        &self,
+        hashed_code: &str,
+    ) -> Result<Option<AuthorizationGrant>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                delete from oauth_authorization_codes
+                where hashed_code = $1
+                returning client_id, user_id, redirect_uri, scopes, code_challenge, expires_at
+            "#,
+                hashed_code
+            )
+        )?;
+
+        row.map(|row| {
+            Ok(AuthorizationGrant {
+                client_id: row.client_id,
+                user_id: row.user_id,
+                redirect_uri: row.redirect_uri,
+                scopes: parse_scopes(&row.scopes)?,
+                code_challenge: row.code_challenge,
+                expires_at: row.expires_at,
+            })
+        })
+        .transpose()
+    }
+
+    async fn create_access_token(
+        &self,
+        client_id: Uuid,
+        user_id: Uuid,
+        hashed_token: &str,
+        scopes: &[Scope],
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into oauth_access_tokens
+                    (id, client_id, user_id, hashed_token, scopes, expires_at, created_at,
+                     updated_at)
+                values ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+                Uuid::new_v4(),
+                client_id,
+                user_id,
+                hashed_token,
+                Scope::format_list(scopes),
+                expires_at,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn find_access_token(
+        &self,
+        hashed_token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TokenGrant>> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                select oauth_access_tokens.scopes, users.id, users.username
+                from oauth_access_tokens
+                inner join users on users.id = oauth_access_tokens.user_id
+                where oauth_access_tokens.hashed_token = $1
+                    and oauth_access_tokens.expires_at > $2
+            "#,
+                hashed_token,
+                now,
+            )
+        )?;
+
+        row.map(|row| {
+            Ok(TokenGrant {
+                user: UserResponse {
+                    id: row.id,
+                    username: row.username,
+                },
+                scopes: parse_scopes(&row.scopes)?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn delete_access_token(&self, client_id: Uuid, hashed_token: &str) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                "delete from oauth_access_tokens where hashed_token = $1 and client_id = $2",
+                hashed_token,
+                client_id,
+            )
+        )?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TweetRepository for PgStorage {
+    async fn create_tweet(&self, user_id: Uuid, text: &str, now: DateTime<Utc>) -> Result<Uuid> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into tweets (id, user_id, text, created_at, updated_at)
+                values ($1, $2, $3, $4, $5) returning id
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                text,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(row.id)
+    }
+
+    async fn find_tweet(&self, id: Uuid, viewer_id: Uuid) -> Result<Option<TweetResponse>> {
+        let tweet = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                TweetRow,
+                "select * from viewed_tweets($2) where id = $1",
+                id,
+                viewer_id,
+            )
+        )?;
+
+        tweet.map(TweetRow::into_response).transpose()
+    }
+
+    async fn edit_tweet(
+        &self,
+        id: Uuid,
+        text: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<TweetResponse>> {
+        // `for update` makes concurrent edits wait, so each one keeps the text it replaced
+        let edited = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                with previous as (
+                    select id, text, updated_at from tweets where id = $1 for update
+                ), revision as (
+                    insert into tweet_revisions (id, tweet_id, text, created_at)
+                    select $2, id, text, updated_at from previous
+                )
+                update tweets
+                set text = $3, updated_at = $4, edit_count = edit_count + 1
+                where id in (select id from previous)
+                returning user_id
+            "#,
+                id,
+                Uuid::new_v4(),
+                text,
+                now,
+            )
+        )?;
+
+        match edited {
+            Some(edited) => self.find_tweet(id, edited.user_id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn tweet_history(&self, id: Uuid) -> Result<Vec<TweetRevisionResponse>> {
+        let revisions = run!(
+            self,
+            fetch_all,
+            query!(
+                r#"
+                select text, created_at
+                from (
+                    select text, created_at from tweet_revisions where tweet_id = $1
+
+                    union all
+
+                    select text, updated_at from tweets where id = $1
+                ) revisions
+                order by created_at
+            "#,
+                id,
+            )
+        )?;
+
+        Ok(revisions
+            .into_iter()
+            .map(|revision| TweetRevisionResponse {
+                text: revision.text.unwrap(),
+                created_at: revision.created_at.unwrap(),
+            })
+            .collect())
+    }
+
+    async fn timeline(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<TweetResponse>> {
+        let tweets = run!(
+            self,
+            fetch_all,
+            query_as!(
+                TweetRow,
+                r#"
+                select *
+                from viewed_tweets($1)
+                where user_id = $1
+                    or user_id in (select followee_id from follows where follower_id = $1)
+                order by created_at desc
+                limit $2
+                offset $3
+            "#,
+                user_id,
+                limit,
+                offset,
+            )
+        )?;
+
+        tweets.into_iter().map(TweetRow::into_response).collect()
+    }
+
+    async fn delete_tweet(&self, id: Uuid) -> Result<bool> {
+        let rows_deleted = run!(
+            self,
+            execute,
+            query!("delete from tweets where id = $1", id)
+        )?;
+
+        Ok(rows_deleted > 0)
+    }
+}
+
+#[async_trait]
+impl ScheduledTweetRepository for PgStorage {
+    async fn create_scheduled_tweet(
+        &self,
+        user_id: Uuid,
+        text: &str,
+        publish_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into scheduled_tweets (id, user_id, text, publish_at, created_at, updated_at)
+                values ($1, $2, $3, $4, $5, $6) returning id
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                text,
+                publish_at,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(row.id)
+    }
+
+    async fn scheduled_tweets(&self, user_id: Uuid) -> Result<Vec<ScheduledTweetResponse>> {
+        let tweets = run!(
+            self,
+            fetch_all,
+            query_as!(
+                ScheduledTweetResponse,
+                r#"
+                select id, text, publish_at, created_at
+                from scheduled_tweets
+                where user_id = $1
+                order by publish_at, created_at
+            "#,
+                user_id,
+            )
+        )?;
+
+        Ok(tweets)
+    }
+
+    async fn update_scheduled_tweet(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        text: Option<&str>,
+        publish_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ScheduledTweetResponse>> {
+        let tweet = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                ScheduledTweetResponse,
+                r#"
+                update scheduled_tweets
+                set
+                    text = coalesce($1, text),
+                    publish_at = coalesce($2, publish_at),
+                    updated_at = $3
+                where id = $4 and user_id = $5
+                returning id, text, publish_at, created_at
+            "#,
+                text,
+                publish_at,
+                now,
+                id,
+                user_id,
+            )
+        )?;
+
+        Ok(tweet)
+    }
+
+    async fn delete_scheduled_tweet(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let rows_deleted = run!(
+            self,
+            execute,
+            query!(
+                "delete from scheduled_tweets where id = $1 and user_id = $2",
+                id,
+                user_id,
+            )
+        )?;
+
+        Ok(rows_deleted > 0)
+    }
+
+    async fn publish_due_tweets(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>> {
+        // A single statement, so tweets can't be published twice by schedulers running at the
+        // same time
+        let rows = run!(
+            self,
+            fetch_all,
+            query!(
+                r#"
+                with due as (
+                    delete from scheduled_tweets
+                    where publish_at <= $1
+                    returning id, user_id, text, publish_at
+                )
+                insert into tweets (id, user_id, text, created_at, updated_at)
+                select id, user_id, text, $1, $1 from due
+                returning id
+            "#,
+                now,
+            )
+        )?;
+
+        Ok(rows.into_iter().map(|row| row.id).collect())
+    }
+}
+
+#[async_trait]
+impl DraftRepository for PgStorage {
+    async fn create_draft(&self, user_id: Uuid, text: &str, now: DateTime<Utc>) -> Result<Uuid> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into drafts (id, user_id, text, created_at, updated_at)
+                values ($1, $2, $3, $4, $5) returning id
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                text,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(row.id)
+    }
+
+    async fn drafts(&self, user_id: Uuid) -> Result<Vec<DraftResponse>> {
+        let drafts = run!(
+            self,
+            fetch_all,
+            query_as!(
+                DraftResponse,
+                r#"
+                select id, text, created_at, updated_at
+                from drafts
+                where user_id = $1
+                order by updated_at desc, created_at desc
+            "#,
+                user_id,
+            )
+        )?;
+
+        Ok(drafts)
+    }
+
+    async fn count_drafts(&self, user_id: Uuid) -> Result<usize> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!("select count(*) from drafts where user_id = $1", user_id,)
+        )?;
+
+        Ok(row.count.unwrap_or(0) as usize)
+    }
+
+    async fn find_draft(&self, user_id: Uuid, id: Uuid) -> Result<Option<DraftResponse>> {
+        let draft = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                DraftResponse,
+                r#"
+                select id, text, created_at, updated_at
+                from drafts
+                where id = $1 and user_id = $2
+            "#,
+                id,
+                user_id,
+            )
+        )?;
+
+        Ok(draft)
+    }
+
+    async fn update_draft(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        text: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DraftResponse>> {
+        let draft = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                DraftResponse,
+                r#"
+                update drafts
+                set text = $1, updated_at = $2
+                where id = $3 and user_id = $4
+                returning id, text, created_at, updated_at
+            "#,
+                text,
+                now,
+                id,
+                user_id,
+            )
+        )?;
+
+        Ok(draft)
+    }
+
+    async fn delete_draft(&self, user_id: Uuid, id: Uuid) -> Result<Option<DraftResponse>> {
+        let draft = run!(
+            self,
+            fetch_optional,
+            query_as!(
+                DraftResponse,
+                r#"
+                delete from drafts
+                where id = $1 and user_id = $2
+                returning id, text, created_at, updated_at
+            "#,
+                id,
+                user_id,
+            )
+        )?;
+
+        Ok(draft)
+    }
+}
+
+fn decode_error(err: serde_json::Error) -> StorageError {
+    StorageError::Database(sqlx::Error::Decode(err.into()))
+}
+
+/// Scopes are stored as a space separated list.
+fn parse_scopes(scopes: &str) -> Result<Vec<Scope>> {
+    Scope::parse_list(scopes).map_err(|err| StorageError::Database(sqlx::Error::Decode(err.into())))
+}
+
+/// Tweets select their media as a JSON array, so they can be loaded in the same query.
+/// A row of the `viewed_tweets` function.
+struct TweetRow {
+    id: Uuid,
+    text: String,
+    edit_count: i32,
+    created_at: DateTime<Utc>,
+    user_id: Uuid,
+    username: String,
+    media: Option<serde_json::Value>,
+    poll: Option<serde_json::Value>,
+    bookmark_id: Option<Uuid>,
+    bookmarked_at: Option<DateTime<Utc>>,
+}
+
+impl TweetRow {
+    fn into_response(self) -> Result<TweetResponse> {
+        Ok(TweetResponse {
+            id: self.id,
+            text: self.text,
+            created_at: self.created_at,
+            user: UserResponse {
+                id: self.user_id,
+                username: self.username,
+            },
+            edited: self.edit_count > 0,
+            edit_count: self.edit_count as u32,
+            media: media_from_json(self.media)?,
+            poll: poll_from_json(self.poll)?,
+            bookmarked_by_me: self.bookmark_id.is_some(),
+        })
+    }
+}
+
+fn media_from_json(media: Option<serde_json::Value>) -> Result<Vec<MediaResponse>> {
+    #[derive(serde::Deserialize)]
+    struct Row {
+        id: Uuid,
+        content_type: String,
+        width: u32,
+        height: u32,
+    }
+
+    let rows = match media {
+        Some(media) => serde_json::from_value::<Vec<Row>>(media).map_err(decode_error)?,
+        None => Vec::new(),
+    };
+    Ok(rows
+        .into_iter()
+        .map(|row| MediaResponse::new(row.id, &row.content_type, row.width, row.height))
+        .collect())
+}
+
+/// Like media, a tweet's poll is selected as JSON. It's null if the tweet doesn't have one.
+fn poll_from_json(poll: Option<serde_json::Value>) -> Result<Option<PollResponse>> {
+    #[derive(serde::Deserialize)]
+    struct Row {
+        closes_at: DateTime<Utc>,
+        options: Vec<OptionRow>,
+        voted_for: Option<u32>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct OptionRow {
+        text: String,
+        votes: u32,
+    }
+
+    let row = match poll {
+        Some(serde_json::Value::Null) | None => return Ok(None),
+        Some(poll) => serde_json::from_value::<Row>(poll).map_err(decode_error)?,
+    };
+    Ok(Some(PollResponse {
+        options: row
+            .options
+            .into_iter()
+            .map(|option| PollOptionResponse {
+                text: option.text,
+                votes: Some(option.votes),
+            })
+            .collect(),
+        closes_at: row.closes_at,
+        closed: false,
+        voted_for: row.voted_for,
+    }))
+}
+
+#[async_trait]
+impl MediaRepository for PgStorage {
+    async fn create_media(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        content_type: &str,
+        width: u32,
+        height: u32,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into media (id, user_id, content_type, width, height, created_at)
+                values ($1, $2, $3, $4, $5, $6)
+            "#,
+                id,
+                user_id,
+                content_type,
+                width as i32,
+                height as i32,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn find_media(&self, id: Uuid) -> Result<Option<Media>> {
+        let media = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                select id, user_id, content_type, width, height, tweet_id
+                from media
+                where id = $1
+            "#,
+                id,
+            )
+        )?;
+
+        Ok(media.map(|media| Media {
+            id: media.id,
+            user_id: media.user_id,
+            content_type: media.content_type,
+            width: media.width as u32,
+            height: media.height as u32,
+            tweet_id: media.tweet_id,
+        }))
+    }
+
+    async fn attach_media(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        tweet_id: Uuid,
+        position: u32,
+    ) -> Result<bool> {
+        let rows_updated = run!(
+            self,
+            execute,
+            query!(
+                r#"
+                update media
+                set tweet_id = $1, position = $2
+                where id = $3 and user_id = $4 and tweet_id is null
+            "#,
+                tweet_id,
+                position as i32,
+                id,
+                user_id,
+            )
+        )?;
+
+        Ok(rows_updated > 0)
+    }
+}
+
+#[async_trait]
+impl PollRepository for PgStorage {
+    async fn create_poll(
+        &self,
+        tweet_id: Uuid,
+        options: &[String],
+        closes_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let row = run!(
+            self,
+            fetch_one,
+            query!(
+                r#"
+                insert into polls (id, tweet_id, closes_at, created_at, updated_at)
+                values ($1, $2, $3, $4, $5) returning id
+            "#,
+                Uuid::new_v4(),
+                tweet_id,
+                closes_at,
+                now,
+                now,
+            )
+        )?;
+
+        for (position, text) in options.iter().enumerate() {
+            run!(
+                self,
+                execute,
+                query!(
+                    r#"
+                    insert into poll_options (id, poll_id, position, text)
+                    values ($1, $2, $3, $4)
+                "#,
+                    Uuid::new_v4(),
+                    row.id,
+                    position as i32,
+                    text,
+                )
+            )?;
+        }
+
+        Ok(row.id)
+    }
+
+    async fn vote_in_poll(
+        &self,
+        tweet_id: Uuid,
+        user_id: Uuid,
+        option: u32,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        let rows_inserted = run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into poll_votes (id, poll_id, poll_option_id, user_id, created_at)
+                select $1, polls.id, poll_options.id, $2, $3
+                from polls
+                inner join poll_options
+                    on poll_options.poll_id = polls.id
+                    and poll_options.position = $4
+                where polls.tweet_id = $5
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                now,
+                option as i32,
+                tweet_id,
+            )
+        )?;
+
+        Ok(rows_inserted > 0)
+    }
+}
+
+#[async_trait]
+impl BookmarkRepository for PgStorage {
+    async fn create_bookmark(
+        &self,
+        user_id: Uuid,
+        tweet_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into bookmarks (id, user_id, tweet_id, created_at)
+                values ($1, $2, $3, $4)
+            "#,
+                Uuid::new_v4(),
+                user_id,
+                tweet_id,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_bookmark(&self, user_id: Uuid, tweet_id: Uuid) -> Result<bool> {
+        let rows_deleted = run!(
+            self,
+            execute,
+            query!(
+                "delete from bookmarks where user_id = $1 and tweet_id = $2",
+                user_id,
+                tweet_id,
+            )
+        )?;
+
+        Ok(rows_deleted > 0)
+    }
+
+    async fn bookmarks(
+        &self,
+        user_id: Uuid,
+        after: Option<BookmarkCursor>,
+        limit: i64,
+    ) -> Result<Vec<Bookmark>> {
+        // Bookmarks made at the same time are ordered by id, so the cursor always points at one
+        let tweets = run!(
+            self,
+            fetch_all,
+            query_as!(
+                TweetRow,
+                r#"
+                select *
+                from viewed_tweets($1)
+                where bookmark_id is not null
+                    and ($2::timestamptz is null or (bookmarked_at, bookmark_id) < ($2, $3))
+                order by bookmarked_at desc, bookmark_id desc
+                limit $4
+            "#,
+                user_id,
+                after.map(|cursor| cursor.created_at),
+                after.map(|cursor| cursor.id),
+                limit,
+            )
+        )?;
+
+        tweets
+            .into_iter()
+            .map(|tweet| {
+                let cursor = match (tweet.bookmark_id, tweet.bookmarked_at) {
+                    (Some(id), Some(created_at)) => BookmarkCursor { created_at, id },
+                    _ => {
+                        let err =
+                            sqlx::Error::Decode("bookmark without an id or created_at".into());
+                        return Err(StorageError::Database(err));
+                    }
+                };
+                Ok(Bookmark {
+                    cursor,
+                    tweet: tweet.into_response()?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl FollowRepository for PgStorage {
+    async fn create_follow(
+        &self,
+        follower_id: Uuid,
+        followee_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                insert into follows (id, follower_id, followee_id, created_at, updated_at)
+                values ($1, $2, $3, $4, $5)
+            "#,
+                Uuid::new_v4(),
+                follower_id,
+                followee_id,
+                now,
+                now,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_follow(&self, follower_id: Uuid, followee_id: Uuid) -> Result<bool> {
+        let rows_deleted = run!(
+            self,
+            execute,
+            query!(
+                "delete from follows where follower_id = $1 and followee_id = $2",
+                follower_id,
+                followee_id,
+            )
+        )?;
+
+        Ok(rows_deleted > 0)
+    }
+
+    async fn is_following(&self, follower_id: Uuid, followee_id: Uuid) -> Result<bool> {
+        let row = run!(
+            self,
+            fetch_optional,
+            query!(
+                r#"
+                select 1 as one from follows
+                where follower_id = $1 and followee_id = $2
+            "#,
+                follower_id,
+                followee_id
+            )
+        )?;
+
+        Ok(row.is_some())
+    }
+
+    async fn following(&self, user_id: Uuid) -> Result<Vec<UserResponse>> {
+        let users = run!(
+            self,
+            fetch_all,
+            query_as!(
+                UserResponse,
+                r#"
+                select users.id, users.username
+                from users
+                inner join follows on
+                    follows.follower_id = $1
+                    and follows.followee_id = users.id
+                order by users.username
+            "#,
+                user_id,
+            )
+        )?;
+
+        Ok(users)
+    }
+
+    async fn followers(&self, user_id: Uuid) -> Result<Vec<UserResponse>> {
+        let users = run!(
+            self,
+            fetch_all,
+            query_as!(
+                UserResponse,
+                r#"
+                select users.id, users.username
+                from users
+                inner join follows on
+                    follows.followee_id = $1
+                    and follows.follower_id = users.id
+                order by users.username
+            "#,
+                user_id,
+            )
+        )?;
+
+        Ok(users)
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for PgStorage {
+    async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        request_hash: &str,
+        now: DateTime<Utc>,
+        expired_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotentRequest>> {
+        run!(
+            self,
+            execute,
+            query!(
+                "delete from idempotency_keys where created_at < $1",
+                expired_before
+            )
+        )?;
+
+        loop {
+            let claimed = run!(
+                self,
+                fetch_optional,
+                query!(
+                    r#"
+                    insert into idempotency_keys
+                        (id, scope, key, request_hash, created_at, updated_at)
+                    values ($1, $2, $3, $4, $5, $6)
+                    on conflict (scope, key) do nothing
+                    returning id
+                "#,
+                    Uuid::new_v4(),
+                    scope,
+                    key,
+                    request_hash,
+                    now,
+                    now,
+                )
+            )?;
+            if claimed.is_some() {
+                return Ok(None);
+            }
+
+            let earlier = run!(
+                self,
+                fetch_optional,
+                query!(
+                    r#"
+                    select request_hash, response_status, response_body
+                    from idempotency_keys
+                    where scope = $1 and key = $2
+                "#,
+                    scope,
+                    key,
+                )
+            )?;
+
+            // Otherwise the key was released in the meantime and can be claimed again
+            if let Some(earlier) = earlier {
+                let response = match (earlier.response_status, earlier.response_body) {
+                    (Some(status), Some(body)) => Some(StoredResponse {
+                        status: status as u16,
+                        body,
+                    }),
+                    _ => None,
+                };
+                return Ok(Some(IdempotentRequest {
+                    request_hash: earlier.request_hash,
+                    response,
+                }));
+            }
+        }
+    }
+
+    async fn save_idempotent_response(
+        &self,
+        scope: &str,
+        key: &str,
+        response: &StoredResponse,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                r#"
+                update idempotency_keys
+                set response_status = $1, response_body = $2, updated_at = $3
+                where scope = $4 and key = $5
+            "#,
+                response.status as i32,
+                response.body,
+                now,
+                scope,
+                key,
+            )
+        )?;
+
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<()> {
+        run!(
+            self,
+            execute,
+            query!(
+                "delete from idempotency_keys where scope = $1 and key = $2",
+                scope,
+                key,
+            )
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use crate::tests::test_helpers::*;
+
+#[async_std::test]
+async fn read_only_token_cannot_post_tweets() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+    let api_token = create_api_token(&server, &session_token, json!(["timeline:read"])).await;
+    assert!(api_token.starts_with("wit_"));
+
+    let (_, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: "Build passed".to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", api_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 403);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "missing_scope",
+                "message": "Token is missing the `tweets:write` scope"
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn posting_tweets_with_a_token() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+    let api_token = create_api_token(&server, &session_token, json!(["tweets:write"])).await;
+
+    let (_, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: "Build passed".to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", api_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 201);
+
+    // Not allowed to read the timeline
+    let (_, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 403);
+}
+
+#[async_std::test]
+async fn following_with_a_token() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    create_user_and_authenticate(&mut server, Some("alice".to_string())).await;
+
+    let read_only = create_api_token(&server, &session_token, json!(["timeline:read"])).await;
+    let (_, status, _) = empty_post("/users/alice/follow")
+        .header("Authorization", format!("Bearer {}", read_only))
+        .send(&server)
+        .await;
+    assert_eq!(status, 403);
+
+    let api_token = create_api_token(&server, &session_token, json!(["follows:write"])).await;
+    let (_, status, _) = empty_post("/users/alice/follow")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn tokens_cannot_manage_tokens() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+    let api_token = create_api_token(
+        &server,
+        &session_token,
+        json!([
+            "profile:read",
+            "timeline:read",
+            "tweets:write",
+            "follows:write"
+        ]),
+    )
+    .await;
+
+    let (json, status, _) = post(
+        "/me/api_tokens",
+        Some(json!({ "name": "sneaky", "scopes": ["tweets:write"] })),
+    )
+    .header("Authorization", format!("Bearer {}", api_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 403);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "message": "API tokens cannot be used for this endpoint"
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn listing_and_revoking_tokens() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+    let api_token = create_api_token(
+        &server,
+        &session_token,
+        json!(["timeline:read", "profile:read"]),
+    )
+    .await;
+
+    let (_, status, _) = get("/me")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = get("/me/api_tokens")
+        .header("Authorization", format!("Bearer {}", session_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: &json,
+        expected: json!({
+            "data": [
+                {
+                    "name": "CI bot",
+                    "scopes": ["profile:read", "timeline:read"],
+                }
+            ]
+        })
+    );
+    let token = &json["data"][0];
+    assert!(token.get("token").is_none());
+    assert!(!token["last_used_at"].is_null());
+    let id = token["id"].as_str().unwrap();
+
+    let (_, status, _) = delete(&format!("/me/api_tokens/{}", id))
+        .header("Authorization", format!("Bearer {}", session_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (_, status, _) = get("/me")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 401);
+
+    let (_, status, _) = delete(&format!("/me/api_tokens/{}", id))
+        .header("Authorization", format!("Bearer {}", session_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn creating_a_token_with_an_unknown_scope() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, _) = post(
+        "/me/api_tokens",
+        Some(json!({ "name": "CI bot", "scopes": ["everything:write"] })),
+    )
+    .header("Authorization", format!("Bearer {}", session_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+}
+
+#[async_std::test]
+async fn bookmarks_need_their_own_scopes() {
+    let mut server = test_setup().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+    let timeline_token = create_api_token(&server, &session_token, json!(["timeline:read"])).await;
+    let bookmarks_token =
+        create_api_token(&server, &session_token, json!(["bookmarks:read"])).await;
+
+    let (json, status, _) = get("/me/bookmarks")
+        .header("Authorization", format!("Bearer {}", timeline_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 403);
+    assert_eq!(
+        json["error"]["message"],
+        "Token is missing the `bookmarks:read` scope"
+    );
+
+    let (_, status, _) = get("/me/bookmarks")
+        .header("Authorization", format!("Bearer {}", bookmarks_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (_, status, _) = empty_post(&format!("/tweets/{}/bookmark", uuid::Uuid::new_v4()))
+        .header("Authorization", format!("Bearer {}", bookmarks_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 403);
+}
+
+#[async_std::test]
+async fn tokens_work_without_a_database() {
+    let mut server = test_setup_in_memory().await;
+
+    let session_token = create_user_and_authenticate(&mut server, None).await.token;
+    let api_token = create_api_token(&server, &session_token, json!(["timeline:read"])).await;
+
+    let (_, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = get("/me/api_tokens")
+        .header("Authorization", format!("Bearer {}", session_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert!(json["data"][0]["last_used_at"].is_string());
+}
+
+async fn create_api_token(server: &TestServer, session_token: &str, scopes: Value) -> String {
+    let (json, status, _) = post(
+        "/me/api_tokens",
+        Some(json!({ "name": "CI bot", "scopes": scopes })),
+    )
+    .header("Authorization", format!("Bearer {}", session_token))
+    .send(server)
+    .await;
+    assert_eq!(status, 201);
+
+    json["data"]["token"].as_str().unwrap().to_string()
+}
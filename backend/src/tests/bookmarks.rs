@@ -0,0 +1,175 @@
+use crate::clock::ManualClock;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+use chrono::Duration;
+
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2020, 1, 1).and_hms(12, 0, 0)
+}
+
+async fn post_tweet(text: &str, token: &str, server: &TestServer) -> String {
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: text.to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    assert_eq!(status, 201);
+    json["data"]["id"].as_str().unwrap().to_string()
+}
+
+async fn bookmark(id: &str, token: &str, server: &TestServer) -> (Value, StatusCode) {
+    let (json, status, _) = empty_post(&format!("/tweets/{}/bookmark", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    (json, status)
+}
+
+async fn bookmarks(cursor: Option<&str>, token: &str, server: &TestServer) -> (Value, StatusCode) {
+    let url = match cursor {
+        Some(cursor) => format!("/me/bookmarks?cursor={}", cursor),
+        None => "/me/bookmarks".to_string(),
+    };
+    let (json, status, _) = get(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    (json, status)
+}
+
+fn texts(json: &Value) -> Vec<&str> {
+    json["data"]["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tweet| tweet["text"].as_str().unwrap())
+        .collect()
+}
+
+#[async_std::test]
+async fn bookmarking_a_tweet() {
+    let mut server = test_setup().await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = post_tweet("Release on Friday", &bob_token, &server).await;
+
+    let (_, status) = bookmark(&id, &alice_token, &server).await;
+    assert_eq!(status, 201);
+
+    let (json, status) = bookmarks(None, &alice_token, &server).await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "items": [{
+                    "id": id,
+                    "text": "Release on Friday",
+                    "user": { "username": "bob" },
+                    "bookmarked_by_me": true,
+                }],
+                "next_cursor": null,
+            }
+        })
+    );
+
+    let (json, status) = bookmark(&id, &alice_token, &server).await;
+    assert_eq!(status, 409);
+    assert_eq!(
+        json["error"]["message"],
+        "You've already bookmarked this tweet"
+    );
+
+    let (_, status, _) = delete(&format!("/tweets/{}/bookmark", id))
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    let (json, _) = bookmarks(None, &alice_token, &server).await;
+    assert!(texts(&json).is_empty());
+
+    let (json, status, _) = delete(&format!("/tweets/{}/bookmark", id))
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+    assert_eq!(
+        json["error"]["message"],
+        "You haven't bookmarked this tweet"
+    );
+
+    let (_, status) = bookmark(&uuid::Uuid::new_v4().to_string(), &alice_token, &server).await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn bookmarks_are_private() {
+    let mut server = test_setup().await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = post_tweet("Secret plans", &bob_token, &server).await;
+    let (_, status) = bookmark(&id, &alice_token, &server).await;
+    assert_eq!(status, 201);
+
+    let (json, status) = bookmarks(None, &bob_token, &server).await;
+    assert_eq!(status, 200);
+    assert!(texts(&json).is_empty());
+
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", bob_token))
+        .send(&server)
+        .await;
+    assert_eq!(json["data"][0]["id"], id);
+    assert_eq!(json["data"][0]["bookmarked_by_me"], false);
+}
+
+#[async_std::test]
+async fn bookmarks_are_paginated_with_cursors() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    for i in 0..25 {
+        let id = post_tweet(&i.to_string(), &token, &server).await;
+        bookmark(&id, &token, &server).await;
+        clock.advance(Duration::minutes(1));
+    }
+
+    let (json, status) = bookmarks(None, &token, &server).await;
+    assert_eq!(status, 200);
+    let first_page = texts(&json);
+    assert_eq!(first_page.len(), 20);
+    assert_eq!(first_page[0], "24");
+    assert_eq!(first_page[19], "5");
+    let cursor = json["data"]["next_cursor"].as_str().unwrap().to_string();
+
+    // Newer bookmarks don't push anything onto the next page twice
+    let id = post_tweet("25", &token, &server).await;
+    bookmark(&id, &token, &server).await;
+
+    let (json, status) = bookmarks(Some(&cursor), &token, &server).await;
+    assert_eq!(status, 200);
+    assert_eq!(texts(&json), vec!["4", "3", "2", "1", "0"]);
+    assert_eq!(json["data"]["next_cursor"], Value::Null);
+
+    let (json, status) = bookmarks(Some("nope"), &token, &server).await;
+    assert_eq!(status, 400);
+    assert_eq!(json["error"]["message"], "Invalid cursor");
+}
@@ -0,0 +1,104 @@
+use crate::tests::test_helpers::*;
+use structopt::StructOpt;
+use witter_cli::{run, Cli, CliError, Config};
+
+async fn witter(
+    server: &TestServer,
+    config: &mut Config,
+    args: &[&str],
+    input: &str,
+) -> Result<String, CliError> {
+    let cli = Cli::from_iter(std::iter::once("witter").chain(args.iter().copied()));
+    let mut output = Vec::new();
+    run(&cli, server, config, &mut input.as_bytes(), &mut output).await?;
+    Ok(String::from_utf8(output).unwrap())
+}
+
+#[async_std::test]
+async fn logging_in_and_posting() {
+    let mut server = test_setup().await;
+    create_user_and_authenticate(&mut server, Some("bob".to_string())).await;
+    let mut config = Config::default();
+
+    let output = witter(&server, &mut config, &["login", "bob"], "correct horse\n")
+        .await
+        .unwrap();
+    assert_eq!(output, "Logged in as @bob\n");
+    assert_eq!(config.username.as_deref(), Some("bob"));
+    assert!(config.token.is_some());
+
+    let output = witter(&server, &mut config, &["post", "Hello, World!"], "")
+        .await
+        .unwrap();
+    assert!(output.starts_with("Posted "));
+
+    let output = witter(&server, &mut config, &["timeline"], "")
+        .await
+        .unwrap();
+    assert!(output.ends_with("@bob: Hello, World!\n"));
+
+    let output = witter(&server, &mut config, &["--json", "timeline"], "")
+        .await
+        .unwrap();
+    let tweet: Value = serde_json::from_str(output.trim()).unwrap();
+    assert_json_include!(
+        actual: tweet,
+        expected: json!({ "text": "Hello, World!", "user": { "username": "bob" } })
+    );
+}
+
+#[async_std::test]
+async fn following_and_whois() {
+    let mut server = test_setup().await;
+    create_user_and_authenticate(&mut server, Some("bob".to_string())).await;
+    create_user_and_authenticate(&mut server, Some("alice".to_string())).await;
+    let mut config = Config::default();
+    witter(&server, &mut config, &["login", "bob"], "correct horse\n")
+        .await
+        .unwrap();
+
+    let output = witter(&server, &mut config, &["follow", "alice"], "")
+        .await
+        .unwrap();
+    assert_eq!(output, "Following @alice\n");
+
+    let output = witter(&server, &mut config, &["unfollow", "alice"], "")
+        .await
+        .unwrap();
+    assert_eq!(output, "Unfollowed @alice\n");
+
+    let output = witter(&server, &mut config, &["--json", "whois", "alice"], "")
+        .await
+        .unwrap();
+    let user: Value = serde_json::from_str(output.trim()).unwrap();
+    assert_eq!(user["username"], "alice");
+}
+
+#[async_std::test]
+async fn commands_that_need_a_login() {
+    let server = test_setup().await;
+    let mut config = Config::default();
+
+    let err = witter(&server, &mut config, &["post", "hi"], "")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, CliError::NotLoggedIn));
+}
+
+#[async_std::test]
+async fn wrong_password() {
+    let mut server = test_setup().await;
+    create_user_and_authenticate(&mut server, Some("bob".to_string())).await;
+    let mut config = Config::default();
+
+    let err = witter(&server, &mut config, &["login", "bob"], "wrong\n")
+        .await
+        .unwrap_err();
+    match err {
+        CliError::Client(err) => {
+            assert_eq!(err.api_error().unwrap().code, ErrorCode::InvalidCredentials)
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(config, Config::default());
+}
@@ -0,0 +1,56 @@
+use crate::tests::test_helpers::*;
+use shared::{Me, MeUrl, NoPayload, NoQuery, PostTweet, PostTweetUrl, Timeline, TimelineUrl};
+use witter_client::Error;
+
+#[async_std::test]
+async fn calling_endpoints_with_the_client() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = server.client().with_auth_token(token);
+
+    let me = client
+        .call::<Me>(&MeUrl, &NoQuery {}, &NoPayload)
+        .await
+        .unwrap();
+    assert_eq!(me.username, "bob");
+
+    for i in 0..3 {
+        client
+            .call::<PostTweet>(
+                &PostTweetUrl,
+                &NoQuery {},
+                &CreateTweetPayload {
+                    text: format!("tweet {}", i),
+                    publish_at: None,
+                    media_ids: Vec::new(),
+                    poll: None,
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    let tweets = client
+        .pages::<Timeline, _>(TimelineUrl)
+        .page_size(2)
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(tweets.len(), 3);
+}
+
+#[async_std::test]
+async fn client_returns_typed_errors() {
+    let server = test_setup().await;
+    let client = server.client().with_auth_token("foo");
+
+    let err = client
+        .call::<Me>(&MeUrl, &NoQuery {}, &NoPayload)
+        .await
+        .unwrap_err();
+
+    match err {
+        Error::Api(err) => assert_eq!(err.code, ErrorCode::InvalidToken),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
@@ -0,0 +1,245 @@
+use crate::tests::test_helpers::*;
+use shared::{MAX_DRAFTS_PER_USER, MAX_DRAFT_SIZE};
+
+async fn create_draft(text: &str, token: &str, server: &TestServer) -> String {
+    let (json, status, _) = post(
+        "/me/drafts",
+        Some(DraftPayload {
+            text: text.to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    assert_eq!(status, 201);
+    json["data"]["id"].as_str().unwrap().to_string()
+}
+
+#[async_std::test]
+async fn saving_and_editing_drafts() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = create_draft("Hello", &token, &server).await;
+
+    let (json, status, _) = put(
+        &format!("/me/drafts/{}", id),
+        Some(DraftPayload {
+            text: "Hello, World!".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({ "data": { "id": id, "text": "Hello, World!" } })
+    );
+
+    let (json, status, _) = get(&format!("/me/drafts/{}", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({ "data": { "id": id, "text": "Hello, World!" } })
+    );
+
+    let (json, status, _) = get("/me/drafts")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({ "data": [{ "id": id, "text": "Hello, World!" }] })
+    );
+
+    let (_, status, _) = delete(&format!("/me/drafts/{}", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = get(&format!("/me/drafts/{}", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error"]["code"], "not_found");
+}
+
+#[async_std::test]
+async fn drafts_can_be_too_long_to_publish() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let text = "a".repeat(1000);
+    let id = create_draft(&text, &token, &server).await;
+
+    let (json, status, _) = empty_post(&format!("/me/drafts/{}/publish", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["code"], "tweet_too_long");
+
+    // The draft is kept so it can be shortened
+    let (json, status, _) = get(&format!("/me/drafts/{}", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(json["data"]["text"], text);
+}
+
+#[async_std::test]
+async fn drafts_have_a_size_limit() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    create_draft(&"å".repeat(MAX_DRAFT_SIZE / 2), &token, &server).await;
+
+    let (json, status, _) = post(
+        "/me/drafts",
+        Some(DraftPayload {
+            text: "å".repeat(MAX_DRAFT_SIZE / 2 + 1),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["field_errors"][0]["field"], "text");
+
+    let id = create_draft("Hello", &token, &server).await;
+    let (_, status, _) = put(
+        &format!("/me/drafts/{}", id),
+        Some(DraftPayload {
+            text: "a".repeat(MAX_DRAFT_SIZE + 1),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+}
+
+#[async_std::test]
+async fn users_have_a_limited_number_of_drafts() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    for _ in 0..MAX_DRAFTS_PER_USER {
+        create_draft("Hello", &token, &server).await;
+    }
+
+    let (json, status, _) = post(
+        "/me/drafts",
+        Some(DraftPayload {
+            text: "One too many".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["code"], "validation_failed");
+}
+
+#[async_std::test]
+async fn publishing_a_draft() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = create_draft("Hello, World!", &token, &server).await;
+
+    let (json, status, _) = empty_post(&format!("/me/drafts/{}/publish", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json.clone(),
+        expected: json!({ "data": { "text": "Hello, World!" } })
+    );
+    let tweet_id = json["data"]["id"].clone();
+
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_json_include!(
+        actual: json,
+        expected: json!({ "data": [{ "id": tweet_id, "text": "Hello, World!" }] })
+    );
+
+    let (json, _, _) = get("/me/drafts")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_json_eq!(json, json!({ "data": [] }));
+
+    let (_, status, _) = empty_post(&format!("/me/drafts/{}/publish", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn cant_see_or_change_other_users_drafts() {
+    let mut server = test_setup_in_memory().await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = create_draft("Secret", &bob_token, &server).await;
+    let url = format!("/me/drafts/{}", id);
+
+    let (_, status, _) = get(&url)
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+
+    let (_, status, _) = put(
+        &url,
+        Some(DraftPayload {
+            text: "Not so secret".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", alice_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 404);
+
+    let (_, status, _) = empty_post(&format!("{}/publish", url))
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+
+    let (_, status, _) = delete(&url)
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+
+    let (json, _, _) = get("/me/drafts")
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_json_eq!(json, json!({ "data": [] }));
+
+    let (json, _, _) = get(&url)
+        .header("Authorization", format!("Bearer {}", bob_token))
+        .send(&server)
+        .await;
+    assert_eq!(json["data"]["text"], "Secret");
+}
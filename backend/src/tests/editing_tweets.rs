@@ -0,0 +1,176 @@
+use crate::clock::ManualClock;
+use crate::config::Config;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+use chrono::Duration;
+
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2020, 1, 1).and_hms(12, 0, 0)
+}
+
+async fn post_tweet(text: &str, token: &str, server: &TestServer) -> String {
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: text.to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    assert_eq!(status, 201);
+    json["data"]["id"].as_str().unwrap().to_string()
+}
+
+async fn edit_tweet(id: &str, text: &str, token: &str, server: &TestServer) -> (Value, StatusCode) {
+    let (json, status, _) = patch(
+        &format!("/tweets/{}", id),
+        Some(EditTweetPayload {
+            text: text.to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    (json, status)
+}
+
+#[async_std::test]
+async fn editing_a_tweet() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = post_tweet("Helo", &token, &server).await;
+
+    clock.advance(Duration::minutes(5));
+    let (json, status) = edit_tweet(&id, "Hello", &token, &server).await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "id": id,
+                "text": "Hello",
+                "created_at": "2020-01-01T12:00:00Z",
+                "edited": true,
+                "edit_count": 1,
+            }
+        })
+    );
+
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": [{ "id": id, "text": "Hello", "edited": true, "edit_count": 1 }]
+        })
+    );
+
+    // Anyone can see the history
+    let (json, status, _) = get(&format!("/tweets/{}/history", id)).send(&server).await;
+    assert_eq!(status, 200);
+    assert_json_eq!(
+        json,
+        json!({
+            "data": [
+                { "text": "Helo", "created_at": "2020-01-01T12:00:00Z" },
+                { "text": "Hello", "created_at": "2020-01-01T12:05:00Z" },
+            ]
+        })
+    );
+}
+
+#[async_std::test]
+async fn tweets_can_only_be_edited_for_a_while() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = post_tweet("Hello", &token, &server).await;
+
+    clock.advance(Duration::minutes(30));
+    let (_, status) = edit_tweet(&id, "Hello!", &token, &server).await;
+    assert_eq!(status, 200);
+
+    clock.advance(Duration::seconds(1));
+    let (json, status) = edit_tweet(&id, "Hello!!", &token, &server).await;
+    assert_eq!(status, 403);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "forbidden",
+                "message": "Tweets can only be edited for 30 minutes after they're posted"
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn edit_window_is_configurable() {
+    let clock = ManualClock::new(start());
+    let mut config = Config::for_tests();
+    config.tweets.edit_window_minutes = 60;
+    let mut server = test_setup_with(config, clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = post_tweet("Hello", &token, &server).await;
+
+    clock.advance(Duration::minutes(45));
+    let (_, status) = edit_tweet(&id, "Hello!", &token, &server).await;
+    assert_eq!(status, 200);
+}
+
+#[async_std::test]
+async fn cant_edit_other_users_tweets() {
+    let mut server = test_setup_in_memory().await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = post_tweet("Hello", &bob_token, &server).await;
+
+    let (json, status) = edit_tweet(&id, "Hijacked", &alice_token, &server).await;
+    assert_eq!(status, 403);
+    assert_eq!(json["error"]["code"], "forbidden");
+
+    let (json, _, _) = get(&format!("/tweets/{}/history", id)).send(&server).await;
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+    assert_eq!(json["data"][0]["text"], "Hello");
+}
+
+#[async_std::test]
+async fn edits_are_validated_like_new_tweets() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = post_tweet("Hello", &token, &server).await;
+
+    let (json, status) = edit_tweet(&id, &"a".repeat(1000), &token, &server).await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["code"], "tweet_too_long");
+}
+
+#[async_std::test]
+async fn editing_a_tweet_that_does_not_exist() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let (json, status) = edit_tweet(&id, "Hello", &token, &server).await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error"]["code"], "not_found");
+
+    let (_, status, _) = get(&format!("/tweets/{}/history", id)).send(&server).await;
+    assert_eq!(status, 404);
+}
@@ -0,0 +1,59 @@
+use crate::config::Config;
+use crate::tests::test_helpers::*;
+
+#[async_std::test]
+async fn signups_can_be_disabled() {
+    let mut config = Config::for_tests();
+    config.features.signups = false;
+    let server = test_setup_with_config(config).await;
+
+    let (_, status, _) = post(
+        "/users",
+        Some(CreateUserPayload {
+            username: "bob".to_string(),
+            password: "correct horse".to_string(),
+        }),
+    )
+    .send(&server)
+    .await;
+    assert_eq!(status, 404);
+
+    let (json, status, _) = get("/openapi.json").send(&server).await;
+    assert_eq!(status, 200);
+    assert!(json["paths"]["/users"]["post"].is_null());
+}
+
+#[async_std::test]
+async fn oauth_can_be_disabled() {
+    let mut config = Config::for_tests();
+    config.features.oauth = false;
+    let server = test_setup_with_config(config).await;
+
+    let (_, status, _) = post_form("/oauth/token", &[("grant_type", "authorization_code")])
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn disabling_two_factor_only_stops_enrolling() {
+    let mut config = Config::for_tests();
+    config.features.two_factor = false;
+    let mut server = test_setup_with_config(config).await;
+
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, _) = empty_post("/me/2fa")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 405);
+
+    // Users who enrolled before it was turned off can still get out of it
+    let (_, status, _) = delete_with_body("/me/2fa", Some(json!({ "code": "000000" })))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_ne!(status, 404);
+    assert_ne!(status, 405);
+}
@@ -2,7 +2,7 @@ use crate::tests::test_helpers::*;
 
 #[async_std::test]
 async fn following_another_user() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
         .await
@@ -77,7 +77,7 @@ async fn following_another_user() {
 
 #[async_std::test]
 async fn follow_same_user_twice() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
         .await
@@ -107,7 +107,7 @@ async fn follow_same_user_twice() {
 
 #[async_std::test]
 async fn cannot_follow_self() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
         .await
@@ -127,3 +127,75 @@ async fn cannot_follow_self() {
         })
     );
 }
+
+#[async_std::test]
+async fn unfollowing_a_user() {
+    let mut server = test_setup_in_memory().await;
+
+    let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    create_user_and_authenticate(&mut server, Some("alice".to_string())).await;
+
+    let (_, status, _) = empty_post("/users/alice/follow")
+        .header("Authorization", format!("Bearer {}", bobs_token))
+        .send(&mut server)
+        .await;
+    assert_eq!(status, 201);
+
+    let (_, status, _) = delete("/users/alice/follow")
+        .header("Authorization", format!("Bearer {}", bobs_token))
+        .send(&mut server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, _, _) = get("/users/bob/following").send(&mut server).await;
+    assert_json_eq!(json, json!({ "data": [] }));
+
+    let (_, status, _) = delete("/users/alice/follow")
+        .header("Authorization", format!("Bearer {}", bobs_token))
+        .send(&mut server)
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn following_unknown_user() {
+    let mut server = test_setup_in_memory().await;
+
+    let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+
+    let (_, status, _) = empty_post("/users/alice/follow")
+        .header("Authorization", format!("Bearer {}", bobs_token))
+        .send(&mut server)
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn following_the_same_user_concurrently() {
+    let mut server = test_setup().await;
+
+    let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    create_user_and_authenticate(&mut server, Some("alice".to_string())).await;
+
+    let requests = (0..5).map(|_| {
+        empty_post("/users/alice/follow")
+            .header("Authorization", format!("Bearer {}", bobs_token))
+            .send(&server)
+    });
+    let mut statuses = futures::future::join_all(requests)
+        .await
+        .into_iter()
+        .map(|(_, status, _)| u16::from(status))
+        .collect::<Vec<_>>();
+    statuses.sort();
+    assert_eq!(statuses, vec![201, 422, 422, 422, 422]);
+
+    let (json, _, _) = get("/users/bob/following").send(&server).await;
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+}
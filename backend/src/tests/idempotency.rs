@@ -0,0 +1,191 @@
+use crate::clock::ManualClock;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use shared::{Scope, MAX_TWEET_LENGTH};
+
+fn post_tweet(token: &str, key: &str, text: &str) -> TestRequest {
+    post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: text.to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .header("Idempotency-Key", key)
+}
+
+async fn timeline_length(server: &TestServer, token: &str) -> usize {
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    json["data"].as_array().unwrap().len()
+}
+
+#[async_std::test]
+async fn retries_get_the_first_response() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (first, status, headers) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+    assert!(!headers.contains_key("idempotent-replayed"));
+
+    let (retry, status, headers) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+    assert_eq!(headers["idempotent-replayed"], "true");
+    assert_eq!(headers["content-type"], "application/json");
+    assert_json_eq!(retry, first);
+
+    assert_eq!(timeline_length(&server, &token).await, 1);
+}
+
+#[async_std::test]
+async fn reusing_a_key_for_a_different_request_is_a_conflict() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, _) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+
+    let (json, status, _) = post_tweet(&token, "key", "Goodbye").send(&server).await;
+    assert_eq!(status, 409);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "status_code": "409",
+                "code": "conflict",
+                "message": "`Idempotency-Key` was already used for a different request"
+            }
+        })
+    );
+
+    assert_eq!(timeline_length(&server, &token).await, 1);
+}
+
+#[async_std::test]
+async fn keys_are_scoped_to_the_client() {
+    let mut server = test_setup_in_memory().await;
+    let bobs_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alices_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let (_, status, _) = post_tweet(&bobs_token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+    let (_, status, _) = post_tweet(&alices_token, "key", "Hi").send(&server).await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn failed_requests_can_be_retried_with_the_same_key() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let text = "a".repeat(MAX_TWEET_LENGTH + 1);
+    let (_, status, _) = post_tweet(&token, "key", &text).send(&server).await;
+    assert_eq!(status, 422);
+    let (_, status, _) = post_tweet(&token, "key", &text).send(&server).await;
+    assert_eq!(status, 422);
+
+    let (_, status, _) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn creating_a_user_and_following() {
+    let mut server = test_setup_in_memory().await;
+
+    let create_user = || {
+        post(
+            "/users",
+            Some(CreateUserPayload {
+                username: "bob".to_string(),
+                password: "correct horse".to_string(),
+            }),
+        )
+        .header("Idempotency-Key", "signup")
+    };
+    let (first, status, _) = create_user().send(&server).await;
+    assert_eq!(status, 201);
+    let token = first["data"]["token"].as_str().unwrap().to_string();
+    // The response has the user's token in it, so it isn't stored for replaying
+    let (_, status, headers) = create_user().send(&server).await;
+    assert_eq!(status, 422);
+    assert!(!headers.contains_key("idempotent-replayed"));
+
+    create_user_and_authenticate(&mut server, Some("alice".to_string())).await;
+    for _ in 0..2 {
+        let (_, status, _) = empty_post("/users/alice/follow")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Idempotency-Key", "follow")
+            .send(&server)
+            .await;
+        assert_eq!(status, 201);
+    }
+}
+
+#[async_std::test]
+async fn credentials_are_never_replayed() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let create_api_token = || {
+        post(
+            "/me/api_tokens",
+            Some(CreateApiTokenPayload {
+                name: "CI".to_string(),
+                scopes: vec![Scope::TimelineRead],
+            }),
+        )
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Idempotency-Key", "key")
+    };
+    let (first, status, _) = create_api_token().send(&server).await;
+    assert_eq!(status, 201);
+    let (retry, status, headers) = create_api_token().send(&server).await;
+    assert_eq!(status, 201);
+    assert!(!headers.contains_key("idempotent-replayed"));
+    assert_ne!(retry["data"]["token"], first["data"]["token"]);
+}
+
+#[async_std::test]
+async fn keys_expire_after_a_day() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, _) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+
+    clock.advance(Duration::hours(23));
+    let (_, status, headers) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+    assert_eq!(headers["idempotent-replayed"], "true");
+
+    clock.advance(Duration::hours(1) + Duration::seconds(1));
+    let (_, status, headers) = post_tweet(&token, "key", "Hello").send(&server).await;
+    assert_eq!(status, 201);
+    assert!(!headers.contains_key("idempotent-replayed"));
+
+    assert_eq!(timeline_length(&server, &token).await, 2);
+}
+
+#[async_std::test]
+async fn keys_cannot_be_too_long() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, _) = post_tweet(&token, &"a".repeat(256), "Hello")
+        .send(&server)
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(timeline_length(&server, &token).await, 0);
+}
@@ -36,25 +36,25 @@ async fn authenticating_with_invalid_auth_header() {
 }
 
 #[async_std::test]
-async fn logging_in_with_unknown_user_gives_404() {
+async fn logging_in_with_unknown_user_looks_like_invalid_password() {
     let mut server = test_setup().await;
 
     let (json, status, _) = post(
         "/users/bob/session",
         Some(LoginPayload {
-            password: "foobar".to_string(),
+            password: "correct horse".to_string(),
         }),
     )
     .send(&mut server)
     .await;
-    assert_eq!(status, 404);
+    assert_eq!(status, 403);
 
     assert_json_include!(
         actual: json,
         expected: json!({
             "error": {
-                "status_code": "404",
-                "message": "User not found",
+                "status_code": "403",
+                "message": "Invalid username or password",
             }
         }),
     );
@@ -82,8 +82,160 @@ async fn logging_in_with_invalid_password() {
         expected: json!({
             "error": {
                 "status_code": "403",
-                "message": "Something went wrong",
+                "message": "Invalid username or password",
+            }
+        }),
+    );
+}
+
+#[async_std::test]
+async fn overlong_passwords_are_rejected_before_hashing() {
+    let mut server = test_setup().await;
+    create_user_and_authenticate(&mut server, Some("bob".to_string())).await;
+
+    let password = "a".repeat(shared::credentials::MAX_PASSWORD_LENGTH + 1);
+    for _ in 0..10 {
+        let (json, status, _) = login(&server, "bob", &password).await;
+        assert_eq!(status, 403);
+        assert_eq!(json["error"]["code"], "invalid_credentials");
+    }
+
+    // They're turned away before the throttle, so they don't count as failed attempts
+    let (_, status, _) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn locked_out_after_too_many_failed_attempts() {
+    use crate::clock::*;
+    use chrono::prelude::*;
+
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+
+    for _ in 0..5 {
+        let (_, status, _) = login(&server, "bob", "wrong").await;
+        assert_eq!(status, 403);
+    }
+
+    // Even the right password is rejected while locked out
+    let (json, status, headers) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 429);
+    assert_eq!(headers["retry-after"], "60");
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "status_code": "429",
+                "message": "Too many failed login attempts. Try again in 60 seconds",
             }
         }),
     );
+
+    clock.advance(chrono::Duration::seconds(61));
+    let (json, status, _) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "token": token
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn lockout_doubles_with_each_further_failure() {
+    use crate::clock::*;
+    use chrono::prelude::*;
+
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    create_user_and_authenticate(&mut server, Some("bob".to_string())).await;
+
+    for _ in 0..5 {
+        login(&server, "bob", "wrong").await;
+    }
+
+    clock.advance(chrono::Duration::seconds(61));
+    login(&server, "bob", "wrong").await;
+    let (_, status, headers) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 429);
+    assert_eq!(headers["retry-after"], "120");
+}
+
+#[async_std::test]
+async fn unknown_usernames_are_locked_out_as_well() {
+    let mut server = test_setup().await;
+
+    for _ in 0..5 {
+        let (_, status, _) = login(&server, "nobody", "wrong").await;
+        assert_eq!(status, 403);
+    }
+
+    let (_, status, _) = login(&server, "nobody", "wrong").await;
+    assert_eq!(status, 429);
+}
+
+#[async_std::test]
+async fn locked_out_by_ip_across_usernames() {
+    let mut server = test_setup().await;
+
+    create_user_and_authenticate(&mut server, Some("bob".to_string())).await;
+
+    for i in 0..20 {
+        let (_, status, _) = post(
+            &format!("/users/user{}/session", i),
+            Some(LoginPayload {
+                password: "wrong".to_string(),
+            }),
+        )
+        .peer_addr("10.0.0.1:1234")
+        .send(&server)
+        .await;
+        assert_eq!(status, 403);
+    }
+
+    let (_, status, _) = post(
+        "/users/bob/session",
+        Some(LoginPayload {
+            password: "correct horse".to_string(),
+        }),
+    )
+    .peer_addr("10.0.0.1:4321")
+    .send(&server)
+    .await;
+    assert_eq!(status, 429);
+
+    let (_, status, _) = post(
+        "/users/bob/session",
+        Some(LoginPayload {
+            password: "correct horse".to_string(),
+        }),
+    )
+    .peer_addr("10.0.0.2:1234")
+    .send(&server)
+    .await;
+    assert_eq!(status, 201);
+}
+
+async fn login(
+    server: &TestServer,
+    username: &str,
+    password: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        &format!("/users/{}/session", username),
+        Some(LoginPayload {
+            password: password.to_string(),
+        }),
+    )
+    .send(server)
+    .await
 }
@@ -2,7 +2,7 @@ use crate::tests::test_helpers::*;
 
 #[async_std::test]
 async fn logging_out() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
         .await
@@ -20,9 +20,18 @@ async fn logging_out() {
         .await;
     assert_eq!(status, 200);
 
-    let (_, status, _) = get("/me")
+    let (json, status, _) = get("/me")
         .header("Authorization", format!("Bearer {}", token))
         .send(&mut server)
         .await;
     assert_eq!(status, 401);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "invalid_token",
+                "message": "Invalid auth token"
+            }
+        })
+    );
 }
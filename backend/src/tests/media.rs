@@ -0,0 +1,294 @@
+use crate::config::Config;
+use crate::rate_limit::RateLimitPolicy;
+use crate::tests::test_helpers::*;
+use image::{DynamicImage, GenericImageView, ImageOutputFormat};
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    DynamicImage::new_rgb8(width, height)
+        .write_to(&mut bytes, ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
+
+async fn upload(file: &[u8], token: &str, server: &TestServer) -> (Value, StatusCode) {
+    let (json, status, _) = post_multipart("/media", "file", file)
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    (json, status)
+}
+
+async fn upload_png(token: &str, server: &TestServer) -> String {
+    let (json, status) = upload(&png(20, 10), token, server).await;
+    assert_eq!(status, 201);
+    json["data"]["id"].as_str().unwrap().to_string()
+}
+
+/// Media responses aren't JSON, so they can't go through `TestRequest::send`.
+async fn fetch(url: &str, if_none_match: Option<&str>, server: &TestServer) -> Response {
+    let mut req = Request::new(
+        Method::Get,
+        Url::parse(&format!("http://example.com{}", url)).unwrap(),
+    );
+    if let Some(etag) = if_none_match {
+        req.insert_header("If-None-Match", etag);
+    }
+    server.simulate(req).await.unwrap()
+}
+
+async fn post_tweet_with_media(
+    media_ids: Vec<&str>,
+    token: &str,
+    server: &TestServer,
+) -> (Value, StatusCode) {
+    let (json, status, _) = post(
+        "/tweets",
+        Some(json!({ "text": "Look at this", "media_ids": media_ids })),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    (json, status)
+}
+
+#[async_std::test]
+async fn uploading_and_serving_an_image() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, status) = upload(&png(800, 600), &token, &server).await;
+    assert_eq!(status, 201);
+    let id = json["data"]["id"].as_str().unwrap().to_string();
+    assert_json_eq!(
+        json,
+        json!({
+            "data": {
+                "id": id,
+                "content_type": "image/png",
+                "width": 800,
+                "height": 600,
+                "url": format!("/media/{}", id),
+                "thumbnail_url": format!("/media/{}/thumbnail", id),
+            }
+        })
+    );
+
+    let mut res = fetch(&format!("/media/{}", id), None, &server).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.content_type().unwrap().essence(), "image/png");
+    assert_eq!(
+        res["Cache-Control"].as_str(),
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(res["X-Content-Type-Options"].as_str(), "nosniff");
+    let etag = res["ETag"].as_str().to_string();
+    let image = image::load_from_memory(&res.body_bytes().await.unwrap()).unwrap();
+    assert_eq!(image.dimensions(), (800, 600));
+
+    let mut res = fetch(&format!("/media/{}/thumbnail", id), None, &server).await;
+    assert_eq!(res.status(), 200);
+    let thumbnail = image::load_from_memory(&res.body_bytes().await.unwrap()).unwrap();
+    assert_eq!(thumbnail.dimensions(), (400, 300));
+
+    let mut res = fetch(&format!("/media/{}", id), Some(&etag), &server).await;
+    assert_eq!(res.status(), 304);
+    assert!(res.body_bytes().await.unwrap().is_empty());
+
+    let res = fetch(&format!("/media/{}", uuid::Uuid::new_v4()), None, &server).await;
+    assert_eq!(res.status(), 404);
+}
+
+#[async_std::test]
+async fn attaching_media_to_a_tweet() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let first = upload_png(&token, &server).await;
+    let second = upload_png(&token, &server).await;
+
+    let (_, status) = post_tweet_with_media(vec![&second, &first], &token, &server).await;
+    assert_eq!(status, 201);
+
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": [{
+                "text": "Look at this",
+                "media": [
+                    { "id": second, "url": format!("/media/{}", second) },
+                    { "id": first, "url": format!("/media/{}", first) },
+                ]
+            }]
+        })
+    );
+}
+
+#[async_std::test]
+async fn media_can_only_be_attached_once_by_its_uploader() {
+    let mut server = test_setup_in_memory().await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = upload_png(&bob_token, &server).await;
+
+    let (json, status) = post_tweet_with_media(vec![&id], &alice_token, &server).await;
+    assert_eq!(status, 422);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "validation_failed",
+                "field_errors": [{
+                    "field": "media_ids",
+                    "message": "must be your own uploads that aren't attached to another tweet"
+                }]
+            }
+        })
+    );
+
+    let (_, status) = post_tweet_with_media(vec![&id], &bob_token, &server).await;
+    assert_eq!(status, 201);
+    let (_, status) = post_tweet_with_media(vec![&id], &bob_token, &server).await;
+    assert_eq!(status, 422);
+
+    // The failed tweets weren't posted
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_json_eq!(json, json!({ "data": [] }));
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", bob_token))
+        .send(&server)
+        .await;
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+}
+
+#[async_std::test]
+async fn at_most_four_media_per_tweet() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let mut ids = Vec::new();
+    for _ in 0..5 {
+        ids.push(upload_png(&token, &server).await);
+    }
+
+    let (json, status) =
+        post_tweet_with_media(ids.iter().map(|id| id.as_str()).collect(), &token, &server).await;
+    assert_eq!(status, 422);
+    assert_eq!(
+        json["error"]["field_errors"][0]["message"],
+        "can have at most 4 items"
+    );
+
+    let (_, status) = post_tweet_with_media(
+        ids[..4].iter().map(|id| id.as_str()).collect(),
+        &token,
+        &server,
+    )
+    .await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn only_images_can_be_uploaded() {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, status) = upload(b"<html><script></script></html>", &token, &server).await;
+    assert_eq!(status, 415);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "unsupported_media_type",
+                "message": "Only PNG and JPEG images are supported",
+                "field_errors": [{ "field": "file", "message": "must be a PNG or JPEG image" }]
+            }
+        })
+    );
+
+    let mut broken = png(10, 10);
+    broken.truncate(40);
+    let (json, status) = upload(&broken, &token, &server).await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["message"], "The image couldn't be read");
+
+    let (json, status, _) = post("/media", Some(json!({ "file": "not multipart" })))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 415);
+    assert_eq!(json["error"]["code"], "unsupported_media_type");
+
+    let (json, status, _) = post_multipart("/media", "other", &png(10, 10))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["field_errors"][0]["field"], "file");
+}
+
+#[async_std::test]
+async fn uploads_are_limited_in_size() {
+    let mut config = Config::for_tests();
+    config.media.max_upload_size = 1000;
+    let mut server = test_setup_with_config(config).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, status) = upload(&vec![0; 1000], &token, &server).await;
+    assert_eq!(status, 413);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "media_too_large",
+                "message": "Uploads can be at most 1000 bytes"
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn upload_rate_limit_can_be_configured() {
+    let mut config = Config::for_tests();
+    config.rate_limits.endpoints.insert(
+        "UploadMedia".to_string(),
+        RateLimitPolicy {
+            limit: 1,
+            period_seconds: 60,
+        },
+    );
+    let mut server = test_setup_with_config(config).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    upload_png(&token, &server).await;
+    let (_, status) = upload(&png(20, 10), &token, &server).await;
+    assert_eq!(status, 429);
+}
+
+#[async_std::test]
+async fn uploading_requires_authentication() {
+    let server = test_setup_in_memory().await;
+
+    let (_, status, _) = post_multipart("/media", "file", &png(10, 10))
+        .send(&server)
+        .await;
+    assert_eq!(status, 400);
+
+    let (_, status, _) = post_multipart("/media", "file", &png(10, 10))
+        .header("Authorization", "Bearer nope")
+        .send(&server)
+        .await;
+    assert_eq!(status, 401);
+}
@@ -1,10 +1,26 @@
 #[allow(unused_imports)]
 pub mod test_helpers;
 
+mod api_tokens;
+mod bookmarks;
+mod cli;
+mod client;
+mod drafts;
+mod editing_tweets;
+mod features;
 mod follows;
+mod idempotency;
 mod login;
 mod logout;
+mod media;
+mod oauth;
+mod openapi;
+mod polls;
 mod posting_tweets;
+mod rate_limiting;
+mod routes;
+mod scheduled_tweets;
 mod timeline;
+mod two_factor;
 mod user_creation;
 mod users;
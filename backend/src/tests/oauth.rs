@@ -0,0 +1,414 @@
+use crate::clock::ManualClock;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+
+// The example from appendix B of RFC 7636
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+const REDIRECT_URI: &str = "https://app.example.com/callback";
+
+#[async_std::test]
+async fn authorization_code_flow() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+
+    let (json, status, _) = authorize(&server, &token, &client, "timeline:read").await;
+    assert_eq!(status, 201);
+    let redirect_to = Url::parse(json["data"]["redirect_to"].as_str().unwrap()).unwrap();
+    assert!(redirect_to.as_str().starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&redirect_to, "state").unwrap(), "xyz");
+    let code = query_param(&redirect_to, "code").unwrap();
+
+    let (json, status, headers) = exchange_code(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(status, 200);
+    assert_eq!(headers["cache-control"], "no-store");
+    assert_json_include!(
+        actual: &json,
+        expected: json!({
+            "token_type": "Bearer",
+            "scope": "timeline:read",
+        })
+    );
+    let access_token = json["access_token"].as_str().unwrap().to_string();
+    assert!(access_token.starts_with("wito_"));
+
+    let (_, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (_, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: "Hello".to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", access_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 403);
+
+    let (_, status, _) = post_form(
+        "/oauth/revoke",
+        &[
+            ("token", access_token.as_str()),
+            ("client_id", client.client_id.as_str()),
+        ],
+    )
+    .send(&server)
+    .await;
+    assert_eq!(status, 200);
+
+    let (_, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 401);
+}
+
+#[async_std::test]
+async fn codes_can_only_be_used_once() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+    let code = authorize_and_get_code(&server, &token, &client).await;
+
+    let (_, status, _) = exchange_code(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = exchange_code(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(status, 400);
+    assert_eq!(json["error"], "invalid_grant");
+}
+
+#[async_std::test]
+async fn wrong_code_verifier() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+    let code = authorize_and_get_code(&server, &token, &client).await;
+
+    let (json, status, _) = exchange_code(
+        &server,
+        &client,
+        &code,
+        "a-completely-different-verifier-that-is-long-enough",
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_json_eq!(
+        json,
+        json!({
+            "error": "invalid_grant",
+            "error_description": "Code verifier doesn't match the code challenge",
+        })
+    );
+}
+
+#[async_std::test]
+async fn codes_expire() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+
+    let code = authorize_and_get_code(&server, &token, &client).await;
+
+    clock.advance(chrono::Duration::minutes(11));
+    let (json, status, _) = exchange_code(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(status, 400);
+    assert_eq!(json["error"], "invalid_grant");
+}
+
+#[async_std::test]
+async fn denying_authorization() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+
+    let (json, status, _) = post(
+        "/oauth/authorizations",
+        Some(AuthorizeOAuthClientPayload {
+            approved: false,
+            client_id: client.client_id.parse().unwrap(),
+            redirect_uri: REDIRECT_URI.to_string(),
+            scope: "timeline:read".to_string(),
+            state: Some("xyz".to_string()),
+            code_challenge: CODE_CHALLENGE.to_string(),
+            code_challenge_method: "S256".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json["data"]["redirect_to"],
+        format!("{}?error=access_denied&state=xyz", REDIRECT_URI)
+    );
+}
+
+#[async_std::test]
+async fn redirect_uri_must_be_registered() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+
+    let (json, status, _) = post(
+        "/oauth/authorizations",
+        Some(AuthorizeOAuthClientPayload {
+            approved: true,
+            client_id: client.client_id.parse().unwrap(),
+            redirect_uri: "https://evil.example.com/callback".to_string(),
+            scope: "timeline:read".to_string(),
+            state: None,
+            code_challenge: CODE_CHALLENGE.to_string(),
+            code_challenge_method: "S256".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "message": "Redirect URI is not registered for this client"
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn redirect_uris_must_be_https_or_loopback() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    for redirect_uri in &[
+        "javascript:alert(1)",
+        "data:text/html,<script>alert(1)</script>",
+        "http://app.example.com/callback",
+        "ftp://app.example.com/callback",
+        "https://app.example.com/callback#fragment",
+    ] {
+        let (json, status, _) = post(
+            "/oauth/clients",
+            Some(RegisterOAuthClientPayload {
+                name: "Witter for Desktop".to_string(),
+                redirect_uris: vec![redirect_uri.to_string()],
+                confidential: false,
+            }),
+        )
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+        assert_eq!(status, 422, "{}", redirect_uri);
+        assert_eq!(
+            json["error"]["message"],
+            format!("Invalid redirect URI `{}`", redirect_uri)
+        );
+    }
+
+    for redirect_uri in &[
+        "http://127.0.0.1:8080/callback",
+        "http://[::1]/callback",
+        "http://localhost:3000/callback",
+    ] {
+        let (_, status, _) = post(
+            "/oauth/clients",
+            Some(RegisterOAuthClientPayload {
+                name: "Witter for Desktop".to_string(),
+                redirect_uris: vec![redirect_uri.to_string()],
+                confidential: false,
+            }),
+        )
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+        assert_eq!(status, 201, "{}", redirect_uri);
+    }
+}
+
+#[async_std::test]
+async fn never_redirects_to_unsafe_schemes() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+
+    // Registered before the schemes were restricted
+    let bob = server
+        .storage()
+        .find_user_by_username("bob")
+        .await
+        .unwrap()
+        .unwrap();
+    let redirect_uris = vec![
+        "javascript:alert(1)".to_string(),
+        "data:text/html,<script>alert(1)</script>".to_string(),
+    ];
+    let client_id = server
+        .storage()
+        .create_oauth_client(bob.id, "Evil", None, &redirect_uris, Utc::now())
+        .await
+        .unwrap();
+
+    for redirect_uri in &redirect_uris {
+        let (json, status, _) = post(
+            "/oauth/authorizations",
+            Some(AuthorizeOAuthClientPayload {
+                approved: true,
+                client_id,
+                redirect_uri: redirect_uri.to_string(),
+                scope: "timeline:read".to_string(),
+                state: None,
+                code_challenge: CODE_CHALLENGE.to_string(),
+                code_challenge_method: "S256".to_string(),
+            }),
+        )
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+        assert_eq!(status, 422, "{}", redirect_uri);
+        assert!(json["data"].get("redirect_to").is_none());
+    }
+}
+
+#[async_std::test]
+async fn confidential_clients_must_authenticate() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, true).await;
+    let client_secret = client.client_secret.clone().unwrap();
+
+    let code = authorize_and_get_code(&server, &token, &client).await;
+    let (json, status, _) = exchange_code(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(status, 401);
+    assert_eq!(json["error"], "invalid_client");
+
+    let code = authorize_and_get_code(&server, &token, &client).await;
+    let credentials = base64::encode(format!("{}:{}", client.client_id, client_secret));
+    let (_, status, _) = post_form(
+        "/oauth/token",
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", CODE_VERIFIER),
+        ],
+    )
+    .header("Authorization", format!("Basic {}", credentials))
+    .send(&server)
+    .await;
+    assert_eq!(status, 200);
+}
+
+#[async_std::test]
+async fn getting_a_client() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+    let client = register_client(&server, &token, false).await;
+
+    let (json, status, _) = get(&format!("/oauth/clients/{}", client.client_id))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_json_eq!(
+        json,
+        json!({
+            "data": {
+                "client_id": client.client_id,
+                "name": "Witter for Desktop",
+            }
+        })
+    );
+}
+
+struct Client {
+    client_id: String,
+    client_secret: Option<String>,
+}
+
+async fn register_client(server: &TestServer, token: &str, confidential: bool) -> Client {
+    let (json, status, _) = post(
+        "/oauth/clients",
+        Some(RegisterOAuthClientPayload {
+            name: "Witter for Desktop".to_string(),
+            redirect_uris: vec![REDIRECT_URI.to_string()],
+            confidential,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    assert_eq!(status, 201);
+
+    Client {
+        client_id: json["data"]["client_id"].as_str().unwrap().to_string(),
+        client_secret: json["data"]["client_secret"]
+            .as_str()
+            .map(|secret| secret.to_string()),
+    }
+}
+
+async fn authorize(
+    server: &TestServer,
+    token: &str,
+    client: &Client,
+    scope: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        "/oauth/authorizations",
+        Some(AuthorizeOAuthClientPayload {
+            approved: true,
+            client_id: client.client_id.parse().unwrap(),
+            redirect_uri: REDIRECT_URI.to_string(),
+            scope: scope.to_string(),
+            state: Some("xyz".to_string()),
+            code_challenge: CODE_CHALLENGE.to_string(),
+            code_challenge_method: "S256".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await
+}
+
+async fn authorize_and_get_code(server: &TestServer, token: &str, client: &Client) -> String {
+    let (json, status, _) = authorize(server, token, client, "timeline:read").await;
+    assert_eq!(status, 201);
+    let redirect_to = Url::parse(json["data"]["redirect_to"].as_str().unwrap()).unwrap();
+    query_param(&redirect_to, "code").unwrap()
+}
+
+async fn exchange_code(
+    server: &TestServer,
+    client: &Client,
+    code: &str,
+    code_verifier: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post_form(
+        "/oauth/token",
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", REDIRECT_URI),
+            ("client_id", client.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ],
+    )
+    .send(server)
+    .await
+}
+
+fn query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.into_owned())
+}
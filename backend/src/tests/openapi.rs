@@ -0,0 +1,38 @@
+use crate::tests::test_helpers::*;
+
+#[async_std::test]
+async fn serves_openapi_document() {
+    let mut server = test_setup().await;
+
+    let (json, status, _) = get("/openapi.json").send(&mut server).await;
+    assert_eq!(status, 200);
+
+    assert_eq!(json["openapi"], "3.0.3");
+    assert!(json["components"]["schemas"]["UserResponse"].is_object());
+
+    let get_user = &json["paths"]["/users/{username}"]["get"];
+    assert_eq!(get_user["operationId"], "GetUser");
+    assert_json_include!(
+        actual: get_user["parameters"].clone(),
+        expected: json!([{ "name": "username", "in": "path", "required": true }])
+    );
+
+    let timeline_params = json["paths"]["/me/timeline"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|param| param["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(timeline_params, vec!["page", "page_size"]);
+
+    assert!(json["paths"]["/tweets"]["post"]["requestBody"].is_object());
+    assert_json_include!(
+        actual: json["paths"]["/tweets"]["post"]["parameters"].clone(),
+        expected: json!([{ "name": "Idempotency-Key", "in": "header", "required": false }])
+    );
+    // Endpoints that issue credentials don't store their responses
+    assert_eq!(
+        json["paths"]["/users/{username}/session"]["post"]["parameters"],
+        json!([{ "name": "username", "in": "path", "required": true, "schema": { "type": "string" } }])
+    );
+}
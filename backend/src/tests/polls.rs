@@ -0,0 +1,234 @@
+use crate::clock::ManualClock;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+use chrono::Duration;
+
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2020, 1, 1).and_hms(12, 0, 0)
+}
+
+async fn post_tweet(poll: Option<Value>, token: &str, server: &TestServer) -> (Value, StatusCode) {
+    let (json, status, _) = post(
+        "/tweets",
+        Some(json!({ "text": "Tabs or spaces?", "poll": poll })),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    (json, status)
+}
+
+async fn post_poll(token: &str, server: &TestServer) -> String {
+    let poll = json!({
+        "options": ["Tabs", "Spaces"],
+        "closes_at": start() + Duration::days(1),
+    });
+    let (json, status) = post_tweet(Some(poll), token, server).await;
+    assert_eq!(status, 201);
+    json["data"]["id"].as_str().unwrap().to_string()
+}
+
+async fn vote(id: &str, option: u32, token: &str, server: &TestServer) -> (Value, StatusCode) {
+    let (json, status, _) = post(
+        &format!("/tweets/{}/poll/votes", id),
+        Some(VotePayload { option }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    (json, status)
+}
+
+async fn timeline_poll(token: &str, server: &TestServer) -> Value {
+    let (json, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    assert_eq!(status, 200);
+    json["data"][0]["poll"].clone()
+}
+
+#[async_std::test]
+async fn voting_in_a_poll() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = post_poll(&bob_token, &server).await;
+
+    // The counts are hidden until you vote
+    assert_json_eq!(
+        timeline_poll(&bob_token, &server).await,
+        json!({
+            "options": [
+                { "text": "Tabs", "votes": null },
+                { "text": "Spaces", "votes": null },
+            ],
+            "closes_at": "2020-01-02T12:00:00Z",
+            "closed": false,
+            "voted_for": null,
+        })
+    );
+
+    clock.advance(Duration::hours(1));
+    let (json, status) = vote(&id, 1, &alice_token, &server).await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "id": id,
+                "poll": {
+                    "options": [
+                        { "text": "Tabs", "votes": 0 },
+                        { "text": "Spaces", "votes": 1 },
+                    ],
+                    "closed": false,
+                    "voted_for": 1,
+                }
+            }
+        })
+    );
+
+    let (json, status) = vote(&id, 0, &alice_token, &server).await;
+    assert_eq!(status, 409);
+    assert_eq!(
+        json["error"]["message"],
+        "You've already voted in this poll"
+    );
+
+    assert_eq!(
+        timeline_poll(&bob_token, &server).await["options"][1]["votes"],
+        Value::Null
+    );
+}
+
+#[async_std::test]
+async fn closed_polls_show_the_results_and_reject_votes() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let id = post_poll(&bob_token, &server).await;
+    let (_, status) = vote(&id, 0, &alice_token, &server).await;
+    assert_eq!(status, 201);
+
+    clock.advance(Duration::days(1));
+    let (json, status) = vote(&id, 1, &bob_token, &server).await;
+    assert_eq!(status, 403);
+    assert_eq!(json["error"]["message"], "This poll has closed");
+
+    assert_json_include!(
+        actual: timeline_poll(&bob_token, &server).await,
+        expected: json!({
+            "options": [
+                { "text": "Tabs", "votes": 1 },
+                { "text": "Spaces", "votes": 0 },
+            ],
+            "closed": true,
+            "voted_for": null,
+        })
+    );
+}
+
+#[async_std::test]
+async fn voting_needs_a_poll_and_one_of_its_options() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let id = post_poll(&token, &server).await;
+    let (json, status) = vote(&id, 2, &token, &server).await;
+    assert_eq!(status, 422);
+    assert_eq!(
+        json["error"]["field_errors"][0]["message"],
+        "must be one of the poll's options"
+    );
+
+    let (json, status) = post_tweet(None, &token, &server).await;
+    assert_eq!(status, 201);
+    let (json, status) = vote(json["data"]["id"].as_str().unwrap(), 0, &token, &server).await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error"]["message"], "This tweet doesn't have a poll");
+
+    let (_, status) = vote(&uuid::Uuid::new_v4().to_string(), 0, &token, &server).await;
+    assert_eq!(status, 404);
+}
+
+#[async_std::test]
+async fn polls_must_be_valid() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let field_errors = |json: &Value| json["error"]["field_errors"].clone();
+
+    let (json, status) = post_tweet(
+        Some(json!({ "options": ["Tabs"], "closes_at": start() })),
+        &token,
+        &server,
+    )
+    .await;
+    assert_eq!(status, 422);
+    assert_json_eq!(
+        field_errors(&json),
+        json!([
+            { "field": "poll.options", "message": "must have between 2 and 4 options" },
+            { "field": "poll.closes_at", "message": "must be in the future" },
+        ])
+    );
+
+    let (json, status) = post_tweet(
+        Some(json!({
+            "options": [" ", "Spaces", " Spaces ", "a".repeat(26)],
+            "closes_at": start() + Duration::days(8),
+        })),
+        &token,
+        &server,
+    )
+    .await;
+    assert_eq!(status, 422);
+    assert_json_eq!(
+        field_errors(&json),
+        json!([
+            { "field": "poll.options", "message": "can't be blank" },
+            { "field": "poll.options", "message": "must be at most 25 characters" },
+            { "field": "poll.options", "message": "must be different from each other" },
+            { "field": "poll.closes_at", "message": "can be at most 7 days away" },
+        ])
+    );
+
+    let (json, status, _) = post(
+        "/tweets",
+        Some(json!({
+            "text": "Later",
+            "publish_at": start() + Duration::hours(1),
+            "poll": { "options": ["Yes", "No"], "closes_at": start() + Duration::days(1) },
+        })),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+    assert_json_eq!(
+        field_errors(&json),
+        json!([{ "field": "poll", "message": "can't be used with scheduled tweets" }])
+    );
+
+    // Nothing was posted
+    let (json, _, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_json_eq!(json, json!({ "data": [] }));
+}
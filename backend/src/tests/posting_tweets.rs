@@ -2,7 +2,7 @@ use crate::tests::test_helpers::*;
 
 #[async_std::test]
 async fn posting_a_valid_tweet() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
@@ -10,6 +10,9 @@ async fn posting_a_valid_tweet() {
         "/tweets",
         Some(CreateTweetPayload {
             text: "Hello, World!".to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
         }),
     )
     .header("Authorization", format!("Bearer {}", token))
@@ -31,21 +34,30 @@ async fn posting_a_valid_tweet() {
 async fn posting_a_tweet_that_is_too_long() {
     use shared::MAX_TWEET_LENGTH;
 
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
     let text = std::iter::repeat('a').take(1000).collect::<String>();
-    let (json, status, _) = post("/tweets", Some(CreateTweetPayload { text }))
-        .header("Authorization", format!("Bearer {}", token))
-        .send(&mut server)
-        .await;
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text,
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&mut server)
+    .await;
     assert_eq!(status, 422);
 
     assert_json_include!(
         actual: json,
         expected: json!({
             "error": {
+                "code": "tweet_too_long",
                 "message": format!("Tweet is too long. Max then is {}", MAX_TWEET_LENGTH)
             }
         })
@@ -56,17 +68,25 @@ async fn posting_a_tweet_that_is_too_long() {
 async fn posting_a_tweet_with_exactly_the_max_length() {
     use shared::MAX_TWEET_LENGTH;
 
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
     let text = std::iter::repeat('a')
         .take(MAX_TWEET_LENGTH)
         .collect::<String>();
-    let (json, status, _) = post("/tweets", Some(CreateTweetPayload { text }))
-        .header("Authorization", format!("Bearer {}", token))
-        .send(&mut server)
-        .await;
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text,
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&mut server)
+    .await;
     assert_eq!(status, 201);
 
     assert_json_include!(
@@ -79,7 +99,7 @@ async fn posting_a_tweet_with_exactly_the_max_length() {
 
 #[async_std::test]
 async fn invalid_data_gets_mapped_to_a_422() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
@@ -94,8 +114,84 @@ async fn invalid_data_gets_mapped_to_a_422() {
         expected: json!({
             "error": {
                 "status_code": "422",
+                "code": "validation_failed",
                 "message": "missing field `text` at line 1 column 13"
             }
         })
     );
 }
+
+async fn post_text(text: &str) -> (Value, StatusCode) {
+    let mut server = test_setup_in_memory().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: text.to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    (json, status)
+}
+
+#[async_std::test]
+async fn length_is_counted_in_characters_not_bytes() {
+    use shared::MAX_TWEET_LENGTH;
+
+    // Each of these is several bytes, and the thumbs up with a skin tone is two code points
+    for character in &["ø", "æ", "👍🏽"] {
+        let (_, status) = post_text(&character.repeat(MAX_TWEET_LENGTH)).await;
+        assert_eq!(status, 201, "{} x {}", character, MAX_TWEET_LENGTH);
+
+        let (json, status) = post_text(&character.repeat(MAX_TWEET_LENGTH + 1)).await;
+        assert_eq!(status, 422);
+        assert_eq!(json["error"]["code"], "tweet_too_long");
+    }
+}
+
+#[async_std::test]
+async fn links_count_as_a_fixed_length() {
+    use shared::tweet_text::URL_LENGTH;
+    use shared::MAX_TWEET_LENGTH;
+
+    let url = format!("https://example.com/{}", "a".repeat(200));
+    let text = format!("{} {}", "a".repeat(MAX_TWEET_LENGTH - URL_LENGTH - 1), url);
+
+    let (_, status) = post_text(&text).await;
+    assert_eq!(status, 201);
+
+    let (_, status) = post_text(&format!("a{}", text)).await;
+    assert_eq!(status, 422);
+}
+
+#[async_std::test]
+async fn posting_a_blank_tweet() {
+    for text in &["", "   ", "\n\t"] {
+        let (json, status) = post_text(text).await;
+        assert_eq!(status, 422);
+        assert_json_include!(
+            actual: json,
+            expected: json!({
+                "error": {
+                    "code": "validation_failed",
+                    "message": "Tweet can't be blank",
+                    "field_errors": [{ "field": "text", "message": "can't be blank" }]
+                }
+            })
+        );
+    }
+}
+
+#[async_std::test]
+async fn text_is_normalized() {
+    // `e` followed by a combining acute accent
+    let (json, status) = post_text("Caf\u{65}\u{301}").await;
+    assert_eq!(status, 201);
+    assert_eq!(json["data"]["text"], "Caf\u{e9}");
+}
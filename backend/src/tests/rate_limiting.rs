@@ -0,0 +1,222 @@
+use crate::clock::ManualClock;
+use crate::config::Config;
+use crate::rate_limit::RateLimitPolicy;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+
+#[async_std::test]
+async fn creating_too_many_users_from_the_same_ip() {
+    let time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+    let clock = ManualClock::new(time);
+    let server = test_setup_with_clock(clock.clone()).await;
+
+    for (idx, remaining) in (0..5).rev().enumerate() {
+        let (_, status, headers) =
+            create_user(&server, &format!("user{}", idx), "10.0.0.1:1").await;
+        assert_eq!(status, 201);
+        assert_eq!(headers["x-ratelimit-limit"], "5");
+        assert_eq!(headers["x-ratelimit-remaining"], remaining.to_string());
+    }
+
+    let (json, status, headers) = create_user(&server, "one_too_many", "10.0.0.1:2").await;
+    assert_eq!(status, 429);
+    assert_eq!(headers["retry-after"], "720");
+    assert_eq!(headers["x-ratelimit-remaining"], "0");
+    assert_eq!(
+        headers["x-ratelimit-reset"],
+        (time + chrono::Duration::hours(1)).timestamp().to_string()
+    );
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "status_code": "429",
+                "message": "Rate limit exceeded. Try again in 720 seconds",
+            }
+        })
+    );
+
+    // Other clients aren't affected
+    let (_, status, _) = create_user(&server, "from_elsewhere", "10.0.0.2:1").await;
+    assert_eq!(status, 201);
+
+    clock.advance(chrono::Duration::minutes(12));
+    let (_, status, headers) = create_user(&server, "a_bit_later", "10.0.0.1:1").await;
+    assert_eq!(status, 201);
+    assert_eq!(headers["x-ratelimit-remaining"], "0");
+}
+
+#[async_std::test]
+async fn posting_tweets_is_limited_per_auth_token() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock).await;
+
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    for _ in 0..300 {
+        let (_, status, _) = post_tweet(&server, &bob_token).await;
+        assert_eq!(status, 201);
+    }
+
+    let (_, status, headers) = post_tweet(&server, &bob_token).await;
+    assert_eq!(status, 429);
+    assert_eq!(headers["x-ratelimit-limit"], "300");
+    assert_eq!(headers["retry-after"], "36");
+
+    let (_, status, _) = post_tweet(&server, &alice_token).await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn publishing_drafts_counts_against_posting_tweets() {
+    let mut server = test_setup().await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    for _ in 0..300 {
+        let (_, status, _) = post_tweet(&server, &token).await;
+        assert_eq!(status, 201);
+    }
+
+    let (json, status, _) = post(
+        "/me/drafts",
+        Some(DraftPayload {
+            text: "Hello, World!".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 201);
+
+    let id = json["data"]["id"].as_str().unwrap();
+    let (_, status, headers) = empty_post(&format!("/me/drafts/{}/publish", id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 429);
+    assert_eq!(headers["x-ratelimit-limit"], "300");
+}
+
+#[async_std::test]
+async fn made_up_tokens_are_limited_by_ip() {
+    let server = test_setup().await;
+
+    for idx in 0..5 {
+        let (_, status, _) = create_user_with_token(
+            &server,
+            &format!("user{}", idx),
+            &format!("made-up-{}", idx),
+        )
+        .await;
+        assert_eq!(status, 201);
+    }
+
+    let (_, status, _) = create_user_with_token(&server, "one_too_many", "made-up-5").await;
+    assert_eq!(status, 429);
+}
+
+#[async_std::test]
+async fn endpoints_without_a_policy_are_not_limited() {
+    let mut server = test_setup().await;
+
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, headers) = get("/me")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert!(!headers.contains_key("x-ratelimit-limit"));
+}
+
+#[async_std::test]
+async fn policies_can_be_configured() {
+    let mut config = Config::for_tests();
+    config.rate_limits.endpoints.insert(
+        "CreateUser".to_string(),
+        RateLimitPolicy {
+            limit: 1,
+            period_seconds: 60,
+        },
+    );
+    let server = test_setup_with_config(config).await;
+
+    let (_, status, headers) = create_user(&server, "bob", "10.0.0.1:1").await;
+    assert_eq!(status, 201);
+    assert_eq!(headers["x-ratelimit-limit"], "1");
+
+    let (_, status, _) = create_user(&server, "alice", "10.0.0.1:1").await;
+    assert_eq!(status, 429);
+}
+
+#[async_std::test]
+async fn rate_limiting_can_be_disabled() {
+    let mut config = Config::for_tests();
+    config.rate_limits.enabled = false;
+    let server = test_setup_with_config(config).await;
+
+    for idx in 0..6 {
+        let (_, status, headers) =
+            create_user(&server, &format!("user{}", idx), "10.0.0.1:1").await;
+        assert_eq!(status, 201);
+        assert!(!headers.contains_key("x-ratelimit-limit"));
+    }
+}
+
+async fn create_user(
+    server: &TestServer,
+    username: &str,
+    peer_addr: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        "/users",
+        Some(CreateUserPayload {
+            username: username.to_string(),
+            password: "correct horse".to_string(),
+        }),
+    )
+    .peer_addr(peer_addr)
+    .send(server)
+    .await
+}
+
+async fn create_user_with_token(
+    server: &TestServer,
+    username: &str,
+    token: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        "/users",
+        Some(CreateUserPayload {
+            username: username.to_string(),
+            password: "correct horse".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .peer_addr("10.0.0.1:1")
+    .send(server)
+    .await
+}
+
+async fn post_tweet(
+    server: &TestServer,
+    token: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: "Hello, World!".to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await
+}
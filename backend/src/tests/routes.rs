@@ -0,0 +1,119 @@
+use crate::config::Features;
+use crate::tests::test_helpers::*;
+use crate::{add_endpoints, BackendApiEndpoint, Endpoints};
+
+/// Routes registered directly on the server instead of through `add_endpoints`.
+const RAW_ROUTES: &[(Method, &str)] = &[
+    (Method::Post, "/oauth/token"),
+    (Method::Post, "/oauth/revoke"),
+    (Method::Post, "/media"),
+    (Method::Get, "/media/:id"),
+    (Method::Get, "/media/:id/thumbnail"),
+    (Method::Get, "/openapi.json"),
+];
+
+#[derive(Default)]
+struct RouteTable(Vec<(Method, &'static str)>);
+
+impl Endpoints for RouteTable {
+    fn add<E>(&mut self)
+    where
+        E: 'static + BackendApiEndpoint,
+        E::Payload: Send,
+        E::Query: Send,
+    {
+        self.0.push((E::METHOD, <E::Url as shared::Url>::URL_SPEC));
+    }
+}
+
+fn route_table() -> Vec<(Method, &'static str)> {
+    let mut table = RouteTable::default();
+    add_endpoints(&mut table, &Features::default());
+    table.0.extend_from_slice(RAW_ROUTES);
+    table.0
+}
+
+/// Whether the request got past the router. Its fallbacks respond with `405 Method Not Allowed`
+/// or an empty `404 Not Found`, which `ErrorReponseToJson` fills in with a generic message.
+async fn is_routed(server: &TestServer, method: Method, url_spec: &str) -> bool {
+    let path = url_spec
+        .split('/')
+        .map(|segment| {
+            if segment.starts_with(':') {
+                "x"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let url = Url::parse(&format!("http://localhost{}", path)).unwrap();
+
+    let mut res = server.simulate(Request::new(method, url)).await.unwrap();
+    let status = res.status();
+    let body = res.body_string().await.unwrap();
+    let json = serde_json::from_str::<Value>(&body).unwrap_or(Value::Null);
+
+    let router_not_found =
+        status == StatusCode::NotFound && json["error"]["message"] == "Something went wrong";
+    status != StatusCode::MethodNotAllowed && !router_not_found
+}
+
+#[test]
+fn routes_are_unique() {
+    let mut routes = route_table();
+    let count = routes.len();
+    routes.sort_by_key(|(method, url_spec)| (method.to_string(), *url_spec));
+    routes.dedup();
+    assert_eq!(routes.len(), count);
+}
+
+#[async_std::test]
+async fn every_route_is_served() {
+    let server = test_setup().await;
+
+    for (method, url_spec) in route_table() {
+        assert!(
+            is_routed(&server, method, url_spec).await,
+            "{} {} isn't routed",
+            method,
+            url_spec
+        );
+    }
+}
+
+#[async_std::test]
+async fn nothing_else_is_served() {
+    let server = test_setup().await;
+    let routes = route_table();
+
+    let mut url_specs = routes
+        .iter()
+        .map(|(_, url_spec)| *url_spec)
+        .collect::<Vec<_>>();
+    url_specs.sort();
+    url_specs.dedup();
+
+    let methods = [
+        Method::Get,
+        Method::Post,
+        Method::Put,
+        Method::Patch,
+        Method::Delete,
+    ];
+    for url_spec in url_specs {
+        for method in methods.iter().copied() {
+            if routes.contains(&(method, url_spec)) {
+                continue;
+            }
+            assert!(
+                !is_routed(&server, method, url_spec).await,
+                "{} {} is routed but isn't an endpoint or listed in RAW_ROUTES",
+                method,
+                url_spec
+            );
+        }
+    }
+
+    assert!(!is_routed(&server, Method::Get, "/not_a_route").await);
+}
@@ -0,0 +1,257 @@
+use crate::clock::ManualClock;
+use crate::tests::test_helpers::*;
+use chrono::prelude::*;
+use chrono::Duration;
+
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2020, 1, 1).and_hms(12, 0, 0)
+}
+
+async fn schedule_tweet(
+    text: &str,
+    publish_at: DateTime<Utc>,
+    token: &str,
+    server: &TestServer,
+) -> (Value, StatusCode) {
+    let (json, status, _) = post(
+        "/tweets",
+        Some(CreateTweetPayload {
+            text: text.to_string(),
+            publish_at: Some(publish_at),
+            media_ids: Vec::new(),
+            poll: None,
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(server)
+    .await;
+    (json, status)
+}
+
+async fn timeline(token: &str, server: &TestServer) -> Value {
+    let (json, status, _) = get("/me/timeline")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    assert_eq!(status, 200);
+    json
+}
+
+#[async_std::test]
+async fn scheduled_tweets_are_published_when_due() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let publish_at = start() + Duration::hours(1);
+    let (json, status) = schedule_tweet("Later", publish_at, &token, &server).await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json.clone(),
+        expected: json!({
+            "data": { "text": "Later", "publish_at": "2020-01-01T13:00:00Z" }
+        })
+    );
+    let id = json["data"]["id"].clone();
+
+    assert!(server.publish_due_tweets().await.is_empty());
+    assert_json_eq!(timeline(&token, &server).await, json!({ "data": [] }));
+
+    // Tweets are posted when they're published, even if the scheduler is a bit late
+    clock.advance(Duration::hours(1) + Duration::minutes(5));
+    let published = server.publish_due_tweets().await;
+    assert_eq!(published.len(), 1);
+
+    assert_json_include!(
+        actual: timeline(&token, &server).await,
+        expected: json!({
+            "data": [
+                { "id": id, "text": "Later", "created_at": "2020-01-01T13:05:00Z" }
+            ]
+        })
+    );
+
+    let (json, status, _) = get("/me/scheduled")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_json_eq!(json, json!({ "data": [] }));
+}
+
+#[async_std::test]
+async fn listing_scheduled_tweets() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    schedule_tweet("Second", start() + Duration::hours(2), &token, &server).await;
+    schedule_tweet("First", start() + Duration::hours(1), &token, &server).await;
+
+    let (json, status, _) = get("/me/scheduled")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": [
+                { "text": "First", "publish_at": "2020-01-01T13:00:00Z" },
+                { "text": "Second", "publish_at": "2020-01-01T14:00:00Z" },
+            ]
+        })
+    );
+}
+
+#[async_std::test]
+async fn editing_a_scheduled_tweet() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, _) = schedule_tweet("Tpyo", start() + Duration::hours(1), &token, &server).await;
+    let id = json["data"]["id"].as_str().unwrap().to_string();
+
+    let (json, status, _) = patch(
+        &format!("/me/scheduled/{}", id),
+        Some(UpdateScheduledTweetPayload {
+            text: Some("Typo".to_string()),
+            ..Default::default()
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": { "id": id, "text": "Typo", "publish_at": "2020-01-01T13:00:00Z" }
+        })
+    );
+
+    let (json, status, _) = patch(
+        &format!("/me/scheduled/{}", id),
+        Some(UpdateScheduledTweetPayload {
+            publish_at: Some(start() + Duration::hours(3)),
+            ..Default::default()
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": { "text": "Typo", "publish_at": "2020-01-01T15:00:00Z" }
+        })
+    );
+
+    clock.advance(Duration::hours(2));
+    assert!(server.publish_due_tweets().await.is_empty());
+    clock.advance(Duration::hours(1));
+    assert_eq!(server.publish_due_tweets().await.len(), 1);
+}
+
+#[async_std::test]
+async fn cancelling_a_scheduled_tweet() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock.clone()).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, _) =
+        schedule_tweet("Nevermind", start() + Duration::hours(1), &token, &server).await;
+    let url = format!("/me/scheduled/{}", json["data"]["id"].as_str().unwrap());
+
+    let (_, status, _) = delete(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = delete(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error"]["code"], "not_found");
+
+    clock.advance(Duration::hours(1));
+    assert!(server.publish_due_tweets().await.is_empty());
+    assert_json_eq!(timeline(&token, &server).await, json!({ "data": [] }));
+}
+
+#[async_std::test]
+async fn scheduling_in_the_past() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock).await;
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, status) = schedule_tweet("Too late", start(), &token, &server).await;
+    assert_eq!(status, 422);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "code": "validation_failed",
+                "field_errors": [
+                    { "field": "publish_at", "message": "must be in the future" }
+                ]
+            }
+        })
+    );
+
+    let (json, _) = schedule_tweet("Soon", start() + Duration::hours(1), &token, &server).await;
+    let (_, status, _) = patch(
+        &format!("/me/scheduled/{}", json["data"]["id"].as_str().unwrap()),
+        Some(UpdateScheduledTweetPayload {
+            publish_at: Some(start() - Duration::hours(1)),
+            ..Default::default()
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+}
+
+#[async_std::test]
+async fn cant_change_other_users_scheduled_tweets() {
+    let clock = ManualClock::new(start());
+    let mut server = test_setup_with_clock(clock).await;
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let (json, _) = schedule_tweet("Mine", start() + Duration::hours(1), &bob_token, &server).await;
+    let url = format!("/me/scheduled/{}", json["data"]["id"].as_str().unwrap());
+
+    let (_, status, _) = patch(
+        &url,
+        Some(UpdateScheduledTweetPayload {
+            text: Some("Yours".to_string()),
+            ..Default::default()
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", alice_token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 404);
+
+    let (_, status, _) = delete(&url)
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 404);
+
+    let (json, _, _) = get("/me/scheduled")
+        .header("Authorization", format!("Bearer {}", alice_token))
+        .send(&server)
+        .await;
+    assert_json_eq!(json, json!({ "data": [] }));
+}
@@ -2,9 +2,14 @@
 
 mod test_db;
 
+use crate::blob_store::{BlobStore, InMemoryBlobStore};
+use crate::clock::{Clock, ManualClock, SystemClock};
+use crate::config::{Config, Secret};
+use crate::scheduler;
+use crate::server;
+use crate::storage::{InMemoryStorage, PgStorage, Storage};
 use crate::Server;
 use crate::State;
-use crate::{make_db_pool, server};
 use futures::{executor::block_on, prelude::*};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -15,7 +20,9 @@ use sqlx::Postgres;
 use std::collections::HashMap;
 use std::env;
 use std::pin::Pin;
-use test_db::TestDb;
+use std::sync::Arc;
+
+pub use test_db::TestDb;
 
 pub use assert_json_diff::{assert_json_eq, assert_json_include};
 pub use serde_json::{json, Value};
@@ -30,31 +37,99 @@ pub use tide::{
 };
 
 pub async fn test_setup() -> TestServer {
-    std::env::set_var("APP_ENV", "test");
-    dotenv::dotenv().ok();
+    test_setup_with(Config::for_tests(), SystemClock).await
+}
 
+pub async fn test_setup_with_config(config: Config) -> TestServer {
+    test_setup_with(config, SystemClock).await
+}
+
+/// Keep a clone of `clock` to move the server's time along.
+pub async fn test_setup_with_clock(clock: ManualClock) -> TestServer {
+    test_setup_with(Config::for_tests(), clock).await
+}
+
+pub async fn test_setup_with(mut config: Config, clock: impl Clock) -> TestServer {
     // pretty_env_logger::try_init().ok();
 
     let test_db = TestDb::new().await;
+    config.database_url = Secret::new(test_db.db_url());
     let db_pool = test_db.db();
+    let storage: Arc<dyn Storage> = Arc::new(PgStorage::new(db_pool));
+    let clock: Arc<dyn Clock> = Arc::new(clock);
 
-    let server = server(db_pool).await;
-    TestServer::new(server, test_db)
+    let blob_store: Arc<dyn BlobStore> = Arc::new(InMemoryBlobStore::default());
+    let server = server(config, storage.clone(), clock.clone(), blob_store).await;
+    TestServer::new(server, storage, clock, Some(test_db))
+}
+
+/// Doesn't need a database.
+pub async fn test_setup_in_memory() -> TestServer {
+    let storage: Arc<dyn Storage> = Arc::new(InMemoryStorage::default());
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+    let blob_store: Arc<dyn BlobStore> = Arc::new(InMemoryBlobStore::default());
+    let server = server(
+        Config::for_tests(),
+        storage.clone(),
+        clock.clone(),
+        blob_store,
+    )
+    .await;
+    TestServer::new(server, storage, clock, None)
 }
 
 pub struct TestServer {
     service: Server<State>,
-    test_db: TestDb,
+    storage: Arc<dyn Storage>,
+    clock: Arc<dyn Clock>,
+    test_db: Option<TestDb>,
 }
 
 impl TestServer {
-    fn new(service: Server<State>, test_db: TestDb) -> Self {
-        Self { service, test_db }
+    fn new(
+        service: Server<State>,
+        storage: Arc<dyn Storage>,
+        clock: Arc<dyn Clock>,
+        test_db: Option<TestDb>,
+    ) -> Self {
+        Self {
+            service,
+            storage,
+            clock,
+            test_db,
+        }
+    }
+
+    /// Does what one tick of the background scheduler would.
+    pub async fn publish_due_tweets(&self) -> Vec<uuid::Uuid> {
+        scheduler::publish_due_tweets(&*self.storage, &*self.clock)
+            .await
+            .unwrap()
+    }
+
+    /// For setting up state the API doesn't allow creating anymore.
+    pub fn storage(&self) -> &dyn Storage {
+        &*self.storage
     }
 
     pub async fn simulate(&self, req: Request) -> tide::Result<Response> {
         self.service.respond(req).await
     }
+
+    /// A typed client that calls this server in-process.
+    pub fn client(&self) -> witter_client::Client<&Self> {
+        witter_client::Client::new(self, "http://localhost")
+            .unwrap()
+            .with_retry_policy(witter_client::RetryPolicy::never())
+    }
+}
+
+#[async_trait::async_trait]
+impl witter_client::HttpBackend for TestServer {
+    async fn send(&self, req: Request) -> tide::http::Result<Response> {
+        self.simulate(req).await
+    }
 }
 
 pub trait BodyJson {
@@ -79,6 +154,7 @@ pub fn get(url: &str) -> TestRequest {
     TestRequest {
         url: url.to_string(),
         headers: HashMap::new(),
+        peer_addr: None,
         kind: TestRequestKind::Get,
     }
 }
@@ -90,15 +166,69 @@ pub fn post<T: Serialize>(url: &str, body: Option<T>) -> TestRequest {
     TestRequest {
         url: url.to_string(),
         headers: HashMap::new(),
+        peer_addr: None,
         kind,
     }
 }
 
+pub fn put<T: Serialize>(url: &str, body: Option<T>) -> TestRequest {
+    let body = body.map(|body| serde_json::to_value(body).unwrap());
+
+    TestRequest {
+        url: url.to_string(),
+        headers: HashMap::new(),
+        peer_addr: None,
+        kind: TestRequestKind::Put(body),
+    }
+}
+
+pub fn patch<T: Serialize>(url: &str, body: Option<T>) -> TestRequest {
+    let body = body.map(|body| serde_json::to_value(body).unwrap());
+
+    TestRequest {
+        url: url.to_string(),
+        headers: HashMap::new(),
+        peer_addr: None,
+        kind: TestRequestKind::Patch(body),
+    }
+}
+
 pub fn delete(url: &str) -> TestRequest {
+    delete_with_body(url, None::<()>)
+}
+
+pub fn delete_with_body<T: Serialize>(url: &str, body: Option<T>) -> TestRequest {
+    let body = body.map(|body| serde_json::to_value(body).unwrap());
+
     TestRequest {
         url: url.to_string(),
         headers: HashMap::new(),
-        kind: TestRequestKind::Delete,
+        peer_addr: None,
+        kind: TestRequestKind::Delete(body),
+    }
+}
+
+pub fn post_form(url: &str, params: &[(&str, &str)]) -> TestRequest {
+    TestRequest {
+        url: url.to_string(),
+        headers: HashMap::new(),
+        peer_addr: None,
+        kind: TestRequestKind::PostForm(
+            params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        ),
+    }
+}
+
+/// Sends `file` as the only field of a `multipart/form-data` body.
+pub fn post_multipart(url: &str, field: &str, file: &[u8]) -> TestRequest {
+    TestRequest {
+        url: url.to_string(),
+        headers: HashMap::new(),
+        peer_addr: None,
+        kind: TestRequestKind::PostMultipart(field.to_string(), file.to_vec()),
     }
 }
 
@@ -110,6 +240,7 @@ pub fn empty_post(url: &str) -> TestRequest {
 pub struct TestRequest {
     url: String,
     headers: HashMap<String, String>,
+    peer_addr: Option<String>,
     kind: TestRequestKind,
 }
 
@@ -117,7 +248,11 @@ pub struct TestRequest {
 pub enum TestRequestKind {
     Get,
     Post(Option<Value>),
-    Delete,
+    Put(Option<Value>),
+    Patch(Option<Value>),
+    Delete(Option<Value>),
+    PostForm(Vec<(String, String)>),
+    PostMultipart(String, Vec<u8>),
 }
 
 impl TestRequest {
@@ -126,20 +261,24 @@ impl TestRequest {
 
         let mut req = match self.kind {
             TestRequestKind::Get => Request::new(Method::Get, url),
-            TestRequestKind::Post(body) => {
-                let mut req = Request::new(Method::Post, url);
-                if let Some(body) = body {
-                    req.set_body(body.to_string());
-                    req.set_content_type("application/json".parse().unwrap());
-                }
-                req
+            TestRequestKind::Post(body) => with_json_body(Request::new(Method::Post, url), body),
+            TestRequestKind::Put(body) => with_json_body(Request::new(Method::Put, url), body),
+            TestRequestKind::Patch(body) => with_json_body(Request::new(Method::Patch, url), body),
+            TestRequestKind::Delete(body) => {
+                with_json_body(Request::new(Method::Delete, url), body)
+            }
+            TestRequestKind::PostForm(params) => {
+                with_form_body(Request::new(Method::Post, url), params)
+            }
+            TestRequestKind::PostMultipart(field, file) => {
+                with_multipart_body(Request::new(Method::Post, url), &field, file)
             }
-            TestRequestKind::Delete => Request::new(Method::Delete, url),
         };
 
         for (key, value) in self.headers {
             req.append_header(key.as_str(), value.as_str());
         }
+        req.set_peer_addr(self.peer_addr);
 
         let res = server.simulate(req).await.unwrap();
         let status = res.status();
@@ -160,6 +299,47 @@ impl TestRequest {
         self.headers.insert(key.to_string(), value.to_string());
         self
     }
+
+    pub fn peer_addr(mut self, peer_addr: &str) -> Self {
+        self.peer_addr = Some(peer_addr.to_string());
+        self
+    }
+}
+
+fn with_json_body(mut req: Request, body: Option<Value>) -> Request {
+    if let Some(body) = body {
+        req.set_body(body.to_string());
+        req.set_content_type("application/json".parse().unwrap());
+    }
+    req
+}
+
+fn with_form_body(mut req: Request, params: Vec<(String, String)>) -> Request {
+    let mut encoded = Url::parse("http://example.com").unwrap();
+    encoded.query_pairs_mut().extend_pairs(params);
+    req.set_body(encoded.query().unwrap_or("").to_string());
+    req.set_content_type("application/x-www-form-urlencoded".parse().unwrap());
+    req
+}
+
+fn with_multipart_body(mut req: Request, field: &str, file: Vec<u8>) -> Request {
+    let boundary = "witter-test-boundary";
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"upload\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n",
+        boundary, field
+    )
+    .into_bytes();
+    body.extend_from_slice(&file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    req.set_body(body);
+    req.set_content_type(
+        format!("multipart/form-data; boundary={}", boundary)
+            .parse()
+            .unwrap(),
+    );
+    req
 }
 
 pub async fn create_user_and_authenticate(
@@ -170,7 +350,7 @@ pub async fn create_user_and_authenticate(
         "/users",
         Some(CreateUserPayload {
             username: username.unwrap_or_else(|| "bob".to_string()),
-            password: "foobar".to_string(),
+            password: "correct horse".to_string(),
         }),
     )
     .send(server)
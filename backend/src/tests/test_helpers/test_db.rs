@@ -13,6 +13,8 @@ pub struct TestDb {
 /// Sets up a new DB for running tests with.
 impl TestDb {
     pub async fn new() -> Self {
+        dotenv::dotenv().ok();
+
         let db_url = db_url();
         create_db(&db_url).await;
         run_migrations(&db_url).await;
@@ -28,6 +30,10 @@ impl TestDb {
     pub fn db(&self) -> PgPool {
         self.db_pool.clone().unwrap()
     }
+
+    pub fn db_url(&self) -> &str {
+        &self.db_url
+    }
 }
 
 impl Drop for TestDb {
@@ -2,7 +2,7 @@ use crate::tests::test_helpers::*;
 
 #[async_std::test]
 async fn sees_own_tweets() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
@@ -30,7 +30,7 @@ async fn sees_own_tweets() {
 
 #[async_std::test]
 async fn sees_tweets_from_users_we_are_following() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
         .await
@@ -69,7 +69,7 @@ async fn sees_tweets_from_users_we_are_following() {
 
 #[async_std::test]
 async fn pagination() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
@@ -135,7 +135,7 @@ async fn pagination() {
 
 #[async_std::test]
 async fn max_page_size() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let token = create_user_and_authenticate(&mut server, None).await.token;
 
@@ -152,11 +152,28 @@ async fn max_page_size() {
     assert_eq!(json["data"].as_array().unwrap().len(), 20);
 }
 
+#[async_std::test]
+async fn invalid_pagination() {
+    let mut server = test_setup_in_memory().await;
+
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (_, status, _) = get("/me/timeline?page=first")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&mut server)
+        .await;
+
+    assert_eq!(status, 400);
+}
+
 async fn post_tweet(text: &str, token: &str, server: &TestServer) {
     post(
         "/tweets",
         Some(CreateTweetPayload {
             text: text.to_string(),
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
         }),
     )
     .header("Authorization", format!("Bearer {}", token))
@@ -166,20 +183,18 @@ async fn post_tweet(text: &str, token: &str, server: &TestServer) {
 
 #[async_std::test]
 async fn response_includes_user_who_posted_tweet() {
-    use crate::clock::*;
+    use crate::clock::FixedClock;
+    use crate::config::Config;
     use chrono::prelude::*;
 
-    let mut server = test_setup().await;
+    let time = Utc.ymd(1970, 1, 1).and_hms(0, 1, 1);
+    let mut server = test_setup_with(Config::for_tests(), FixedClock(time)).await;
 
     let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
         .await
         .token;
 
-    let time = Utc.ymd(1970, 1, 1).and_hms(0, 1, 1);
-    freeze_time::<(), _, _>(time, || async {
-        post_tweet("foo", &token, &server).await;
-    })
-    .await;
+    post_tweet("foo", &token, &server).await;
 
     let (json, status, _) = get("/me/timeline")
         .header("Authorization", format!("Bearer {}", token))
@@ -0,0 +1,365 @@
+use crate::clock::{Clock, ManualClock};
+use crate::tests::test_helpers::*;
+use crate::totp;
+use chrono::prelude::*;
+
+#[async_std::test]
+async fn logging_in_with_two_factor_enabled() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let (secret, _) = enable_two_factor(&server, &token, &clock).await;
+
+    let (json, status, _) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 200);
+    assert_json_include!(
+        actual: &json,
+        expected: json!({
+            "data": {
+                "type": "second_factor_required"
+            }
+        })
+    );
+    assert!(json["data"].get("token").is_none());
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    clock.advance(chrono::Duration::minutes(1));
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (json, status, _) = complete_login(&server, "bob", &challenge_token, &code).await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "token": token
+            }
+        })
+    );
+
+    // challenges can only be used once
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, &code).await;
+    assert_eq!(status, 401);
+}
+
+#[async_std::test]
+async fn logging_in_with_an_invalid_code() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    enable_two_factor(&server, &token, &clock).await;
+
+    let (json, _, _) = login(&server, "bob", "correct horse").await;
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (json, status, _) = complete_login(&server, "bob", &challenge_token, "000000").await;
+    assert_eq!(status, 403);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "message": "Invalid two-factor code"
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn challenges_expire() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let (secret, _) = enable_two_factor(&server, &token, &clock).await;
+
+    let (json, _, _) = login(&server, "bob", "correct horse").await;
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    clock.advance(chrono::Duration::minutes(10));
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, &code).await;
+    assert_eq!(status, 401);
+}
+
+#[async_std::test]
+async fn recovery_codes_can_only_be_used_once() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let (_, recovery_codes) = enable_two_factor(&server, &token, &clock).await;
+    assert_eq!(recovery_codes.len(), 10);
+    let recovery_code = &recovery_codes[0];
+
+    let (json, _, _) = login(&server, "bob", "correct horse").await;
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, recovery_code).await;
+    assert_eq!(status, 201);
+
+    let (json, _, _) = login(&server, "bob", "correct horse").await;
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, recovery_code).await;
+    assert_eq!(status, 403);
+}
+
+#[async_std::test]
+async fn codes_can_only_be_used_once() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let (secret, _) = enable_two_factor(&server, &token, &clock).await;
+
+    // The code that confirmed enrollment has been used already
+    let (json, _, _) = login(&server, "bob", "correct horse").await;
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, &code).await;
+    assert_eq!(status, 403);
+
+    clock.advance(chrono::Duration::seconds(30));
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, &code).await;
+    assert_eq!(status, 201);
+
+    // Still within the allowed drift, but not accepted again
+    let (json, _, _) = login(&server, "bob", "correct horse").await;
+    let challenge_token = json["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, &code).await;
+    assert_eq!(status, 403);
+
+    // Neither are codes from before it
+    let earlier = totp::code_at(&secret, clock.now() - chrono::Duration::seconds(30)).unwrap();
+    let (_, status, _) = complete_login(&server, "bob", &challenge_token, &earlier).await;
+    assert_eq!(status, 403);
+}
+
+#[async_std::test]
+async fn confirming_with_an_invalid_code() {
+    let mut server = test_setup().await;
+
+    let token = create_user_and_authenticate(&mut server, None).await.token;
+
+    let (json, status, _) = empty_post("/me/2fa")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 201);
+    assert!(json["data"]["provisioning_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/Witter:bob?secret="));
+
+    let (json, status, _) = post(
+        "/me/2fa/confirmation",
+        Some(TwoFactorCodePayload {
+            code: "000000".to_string(),
+        }),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send(&server)
+    .await;
+    assert_eq!(status, 422);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "error": {
+                "message": "Invalid two-factor code"
+            }
+        })
+    );
+
+    // Two-factor isn't enabled until it has been confirmed
+    let (json, status, _) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "type": "token",
+                "token": token
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn disabling_two_factor() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let (secret, _) = enable_two_factor(&server, &token, &clock).await;
+
+    clock.advance(chrono::Duration::seconds(30));
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (_, status, _) = delete_with_body("/me/2fa", Some(TwoFactorCodePayload { code }))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 200);
+
+    let (json, status, _) = login(&server, "bob", "correct horse").await;
+    assert_eq!(status, 201);
+    assert_json_include!(
+        actual: json,
+        expected: json!({
+            "data": {
+                "token": token
+            }
+        })
+    );
+}
+
+#[async_std::test]
+async fn guessing_codes_with_a_session_is_throttled() {
+    let clock = ManualClock::new(Utc.ymd(2020, 1, 1).and_hms(12, 0, 0));
+    let mut server = test_setup_with_clock(clock.clone()).await;
+
+    let bob_token = create_user_and_authenticate(&mut server, Some("bob".to_string()))
+        .await
+        .token;
+    let alice_token = create_user_and_authenticate(&mut server, Some("alice".to_string()))
+        .await
+        .token;
+
+    let confirm = |token: &str, code: &str| {
+        post(
+            "/me/2fa/confirmation",
+            Some(TwoFactorCodePayload {
+                code: code.to_string(),
+            }),
+        )
+        .header("Authorization", format!("Bearer {}", token))
+    };
+    let (json, status, _) = empty_post("/me/2fa")
+        .header("Authorization", format!("Bearer {}", bob_token))
+        .send(&server)
+        .await;
+    assert_eq!(status, 201);
+    let secret = json["data"]["secret"].as_str().unwrap().to_string();
+
+    for _ in 0..5 {
+        let (_, status, _) = confirm(&bob_token, "000000").send(&server).await;
+        assert_eq!(status, 422);
+    }
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (_, status, _) = confirm(&bob_token, &code).send(&server).await;
+    assert_eq!(status, 429);
+
+    clock.advance(chrono::Duration::seconds(61));
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (_, status, _) = confirm(&bob_token, &code).send(&server).await;
+    assert_eq!(status, 201);
+
+    // Disabling is throttled too
+    enable_two_factor(&server, &alice_token, &clock).await;
+    let disable = |code: &str| {
+        delete_with_body(
+            "/me/2fa",
+            Some(TwoFactorCodePayload {
+                code: code.to_string(),
+            }),
+        )
+        .header("Authorization", format!("Bearer {}", alice_token))
+    };
+    for _ in 0..5 {
+        let (_, status, _) = disable("000000").send(&server).await;
+        assert_eq!(status, 403);
+    }
+    let (_, status, _) = disable("000000").send(&server).await;
+    assert_eq!(status, 429);
+}
+
+async fn enable_two_factor(
+    server: &TestServer,
+    token: &str,
+    clock: &ManualClock,
+) -> (String, Vec<String>) {
+    let (json, status, _) = empty_post("/me/2fa")
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    assert_eq!(status, 201);
+    let secret = json["data"]["secret"].as_str().unwrap().to_string();
+
+    let code = totp::code_at(&secret, clock.now()).unwrap();
+    let (json, status, _) = post("/me/2fa/confirmation", Some(TwoFactorCodePayload { code }))
+        .header("Authorization", format!("Bearer {}", token))
+        .send(server)
+        .await;
+    assert_eq!(status, 201);
+
+    let recovery_codes = serde_json::from_value::<ApiResponse<RecoveryCodesResponse>>(json)
+        .unwrap()
+        .data
+        .recovery_codes;
+
+    (secret, recovery_codes)
+}
+
+async fn login(
+    server: &TestServer,
+    username: &str,
+    password: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        &format!("/users/{}/session", username),
+        Some(LoginPayload {
+            password: password.to_string(),
+        }),
+    )
+    .send(server)
+    .await
+}
+
+async fn complete_login(
+    server: &TestServer,
+    username: &str,
+    challenge_token: &str,
+    code: &str,
+) -> (Value, StatusCode, std::collections::HashMap<String, String>) {
+    post(
+        &format!("/users/{}/session/2fa", username),
+        Some(SecondFactorPayload {
+            challenge_token: challenge_token.to_string(),
+            code: code.to_string(),
+        }),
+    )
+    .send(server)
+    .await
+}
@@ -1,3 +1,4 @@
+use crate::config::Config;
 use crate::tests::test_helpers::*;
 
 #[async_std::test]
@@ -26,7 +27,7 @@ async fn creating_a_user_and_logging_in() {
     let (json, status, _) = post(
         "/users/bob/session",
         Some(LoginPayload {
-            password: "foobar".to_string(),
+            password: "correct horse".to_string(),
         }),
     )
     .send(&mut server)
@@ -54,7 +55,7 @@ async fn claiming_username_already_claimed_gives_client_error() {
         "/users",
         Some(CreateUserPayload {
             username,
-            password: "bar".to_string(),
+            password: "battery staple".to_string(),
         }),
     )
     .send(&mut server)
@@ -65,8 +66,187 @@ async fn claiming_username_already_claimed_gives_client_error() {
         actual: json,
         expected: json!({
             "error": {
-                "message": "Username is already claimed"
+                "status_code": "422",
+                "code": "username_taken",
+                "message": "Username is already claimed",
+                "field_errors": [
+                    { "field": "username", "message": "is already claimed" }
+                ]
             }
         })
     );
 }
+
+async fn claim_username_concurrently(server: &TestServer) {
+    let requests = (0..5).map(|_| {
+        post(
+            "/users",
+            Some(CreateUserPayload {
+                username: "bob".to_string(),
+                password: "correct horse".to_string(),
+            }),
+        )
+        .send(server)
+    });
+    let mut statuses = futures::future::join_all(requests)
+        .await
+        .into_iter()
+        .map(|(json, status, _)| {
+            if status == 422 {
+                assert_eq!(json["error"]["code"], "username_taken");
+            }
+            u16::from(status)
+        })
+        .collect::<Vec<_>>();
+    statuses.sort();
+
+    assert_eq!(statuses, vec![201, 422, 422, 422, 422]);
+}
+
+#[async_std::test]
+async fn claiming_the_same_username_concurrently() {
+    let server = test_setup().await;
+    claim_username_concurrently(&server).await;
+
+    let (_, status, _) = post(
+        "/users/bob/session",
+        Some(LoginPayload {
+            password: "correct horse".to_string(),
+        }),
+    )
+    .send(&server)
+    .await;
+    assert_eq!(status, 201);
+}
+
+#[async_std::test]
+async fn claiming_the_same_username_concurrently_in_memory() {
+    let server = test_setup_in_memory().await;
+    claim_username_concurrently(&server).await;
+}
+
+/// These tests make more failed attempts than `CreateUser` allows per hour.
+async fn test_setup_without_rate_limits() -> TestServer {
+    let mut config = Config::for_tests();
+    config.rate_limits.enabled = false;
+    test_setup_with_config(config).await
+}
+
+async fn create_user(username: &str, password: &str, server: &TestServer) -> (Value, StatusCode) {
+    let (json, status, _) = post(
+        "/users",
+        Some(CreateUserPayload {
+            username: username.to_string(),
+            password: password.to_string(),
+        }),
+    )
+    .send(server)
+    .await;
+    (json, status)
+}
+
+#[async_std::test]
+async fn usernames_must_follow_the_rules() {
+    let server = test_setup_without_rate_limits().await;
+
+    let cases = vec![
+        ("", "must be between 3 and 20 characters"),
+        ("bo", "must be between 3 and 20 characters"),
+        (
+            "a_very_long_username_1",
+            "must be between 3 and 20 characters",
+        ),
+        (
+            "bob/alice",
+            "can only contain letters, numbers, and underscores",
+        ),
+        (
+            "bob smith",
+            "can only contain letters, numbers, and underscores",
+        ),
+        ("bøb", "can only contain letters, numbers, and underscores"),
+        ("me", "is reserved"),
+        ("Admin", "is reserved"),
+        ("login", "is reserved"),
+    ];
+    for (username, message) in cases {
+        let (json, status) = create_user(username, "correct horse", &server).await;
+        assert_eq!(status, 422, "{:?}", username);
+        assert_eq!(json["error"]["code"], "validation_failed");
+        let field_errors = json["error"]["field_errors"].as_array().unwrap();
+        assert!(
+            field_errors.contains(&json!({ "field": "username", "message": message })),
+            "{:?} gave {:?}",
+            username,
+            field_errors
+        );
+    }
+}
+
+#[async_std::test]
+async fn passwords_must_be_strong_enough() {
+    let server = test_setup_without_rate_limits().await;
+
+    let too_long = "a".repeat(129);
+    let cases = vec![
+        ("", "must be at least 8 characters"),
+        ("hunter2", "must be at least 8 characters"),
+        ("Password123", "is too common"),
+        ("qwertyuiop", "is too common"),
+        ("Alice_1984", "can't be the same as your username"),
+        (too_long.as_str(), "can't be more than 128 characters"),
+    ];
+    for (password, message) in cases {
+        let (json, status) = create_user("alice_1984", password, &server).await;
+        assert_eq!(status, 422, "{:?}", password);
+        assert_json_include!(
+            actual: json,
+            expected: json!({
+                "error": {
+                    "code": "validation_failed",
+                    "field_errors": [{ "field": "password", "message": message }]
+                }
+            })
+        );
+    }
+}
+
+#[async_std::test]
+async fn all_problems_are_reported_at_once() {
+    let server = test_setup_in_memory().await;
+
+    let (json, status) = create_user("me/", "1234", &server).await;
+    assert_eq!(status, 422);
+    assert_json_eq!(
+        json["error"]["field_errors"].clone(),
+        json!([
+            { "field": "username", "message": "can only contain letters, numbers, and underscores" },
+            { "field": "password", "message": "must be at least 8 characters" },
+        ])
+    );
+}
+
+#[async_std::test]
+async fn usernames_are_unique_regardless_of_case() {
+    let mut server = test_setup().await;
+    create_user_and_authenticate(&mut server, Some("Bob".to_string())).await;
+
+    let (json, status) = create_user("bOB", "battery staple", &server).await;
+    assert_eq!(status, 422);
+    assert_eq!(json["error"]["code"], "username_taken");
+
+    // Lookups ignore case too, but the username is shown the way it was chosen
+    let (json, status, _) = get("/users/bob").send(&server).await;
+    assert_eq!(status, 200);
+    assert_eq!(json["data"]["username"], "Bob");
+
+    let (_, status, _) = post(
+        "/users/BOB/session",
+        Some(LoginPayload {
+            password: "correct horse".to_string(),
+        }),
+    )
+    .send(&server)
+    .await;
+    assert_eq!(status, 201);
+}
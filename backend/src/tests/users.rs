@@ -2,7 +2,7 @@ use crate::tests::test_helpers::*;
 
 #[async_std::test]
 async fn get_profile_of_other_user() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let username = "bob";
     create_user_and_authenticate(&mut server, Some(username.to_string())).await;
@@ -22,7 +22,7 @@ async fn get_profile_of_other_user() {
 
 #[async_std::test]
 async fn get_profile_of_non_unknown_user() {
-    let mut server = test_setup().await;
+    let mut server = test_setup_in_memory().await;
 
     let (json, status, _) = get("/users/foo").send(&mut server).await;
     assert_eq!(status, 404);
@@ -32,6 +32,7 @@ async fn get_profile_of_non_unknown_user() {
         expected: json!({
             "error": {
                 "message": "User not found",
+                "code": "not_found",
                 "status_code": "404"
             }
         })
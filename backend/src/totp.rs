@@ -0,0 +1,148 @@
+//! Time-based one-time passwords as described in [RFC 6238].
+//!
+//! Codes are six digits, use HMAC-SHA1 and a 30 second time step, which is what authenticator
+//! apps expect by default.
+//!
+//! [RFC 6238]: https://tools.ietf.org/html/rfc6238
+
+use chrono::prelude::*;
+use hmac::{Hmac, Mac, NewMac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha1::Sha1;
+
+const ISSUER: &str = "Witter";
+const DIGITS: u32 = 6;
+const TIME_STEP_SECONDS: i64 = 30;
+const SECRET_LENGTH: usize = 20;
+
+/// How many time steps before and after the current one we accept codes for. This allows for
+/// a bit of clock drift between the server and the user's device.
+const ALLOWED_DRIFT: i64 = 1;
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// Everything but unreserved characters, so `:` and `?` in usernames can't end the label early.
+const LABEL: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+pub fn generate_secret() -> String {
+    let mut secret = [0; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    base32::encode(BASE32, &secret)
+}
+
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = utf8_percent_encode(ISSUER, LABEL),
+        username = utf8_percent_encode(username, LABEL),
+        secret = secret,
+        digits = DIGITS,
+        period = TIME_STEP_SECONDS,
+    )
+}
+
+pub fn code_at(secret: &str, time: DateTime<Utc>) -> Option<String> {
+    let key = base32::decode(BASE32, secret)?;
+    Some(hotp(&key, time_step(time), DIGITS))
+}
+
+/// Returns the time step `code` belongs to, if it's valid. Callers should only accept each step
+/// once, otherwise a code can be replayed for as long as it's valid.
+pub fn verify(secret: &str, code: &str, time: DateTime<Utc>) -> Option<i64> {
+    let key = base32::decode(BASE32, secret)?;
+    let code = code.trim();
+    let step = time_step(time);
+
+    (-ALLOWED_DRIFT..=ALLOWED_DRIFT)
+        .map(|drift| step + drift)
+        .find(|step| hotp(&key, *step, DIGITS) == code)
+}
+
+fn time_step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(TIME_STEP_SECONDS)
+}
+
+/// HOTP as described in [RFC 4226](https://tools.ietf.org/html/rfc4226).
+fn hotp(key: &[u8], counter: i64, digits: u32) -> String {
+    let mut mac = Hmac::<Sha1>::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+
+    format!(
+        "{:0width$}",
+        binary % 10_u32.pow(digits),
+        width = digits as usize
+    )
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    // The SHA1 seed from the test vectors in appendix B of RFC 6238
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn rfc_6238_test_vectors() {
+        let vectors = [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ];
+
+        for (timestamp, expected) in vectors.iter() {
+            let time = Utc.timestamp(*timestamp, 0);
+            assert_eq!(hotp(RFC_SECRET, time_step(time), 8), *expected);
+        }
+    }
+
+    #[test]
+    fn verifying_codes() {
+        let secret = base32::encode(BASE32, RFC_SECRET);
+        let time = Utc.timestamp(1111111109, 0);
+
+        assert_eq!(code_at(&secret, time).unwrap(), "081804");
+        assert_eq!(verify(&secret, "081804", time), Some(time_step(time)));
+        assert_eq!(
+            verify(&secret, "081804", time + chrono::Duration::seconds(30)),
+            Some(time_step(time))
+        );
+        assert_eq!(
+            verify(&secret, "081804", time + chrono::Duration::seconds(90)),
+            None
+        );
+        assert_eq!(verify(&secret, "000000", time), None);
+        assert_eq!(verify("not base32!", "081804", time), None);
+    }
+
+    #[test]
+    fn provisioning_uri_includes_secret_and_issuer() {
+        let uri = provisioning_uri("JBSWY3DPEHPK3PXP", "bob");
+        assert_eq!(
+            uri,
+            "otpauth://totp/Witter:bob?secret=JBSWY3DPEHPK3PXP&issuer=Witter&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn provisioning_uri_encodes_the_label() {
+        let uri = provisioning_uri("JBSWY3DPEHPK3PXP", "bob smith?&x=1");
+        assert!(uri.starts_with("otpauth://totp/Witter:bob%20smith%3F%26x%3D1?secret="));
+    }
+}
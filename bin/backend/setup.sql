@@ -6,7 +6,7 @@ create table users (
     updated_at timestamp with time zone not null
 );
 
-create unique index users_username on users(username);
+create unique index users_username on users(lower(username));
 
 create table auth_tokens (
     id uuid primary key,
@@ -18,14 +18,27 @@ create table auth_tokens (
 
 create unique index auth_tokens_token on auth_tokens(token);
 
+-- Everything that belongs to a tweet references it with `on delete cascade`, so it goes with the
+-- tweet. Media files are in the blob store and have to be deleted separately.
 create table tweets (
     id uuid primary key,
     user_id uuid not null references users (id),
     text text not null,
+    edit_count integer not null default 0,
     created_at timestamp with time zone not null,
     updated_at timestamp with time zone not null
 );
 
+-- Earlier versions of edited tweets. `created_at` is when that version was posted.
+create table tweet_revisions (
+    id uuid primary key,
+    tweet_id uuid not null references tweets (id) on delete cascade,
+    text text not null,
+    created_at timestamp with time zone not null
+);
+
+create index tweet_revisions_tweet_id on tweet_revisions(tweet_id);
+
 create table follows (
     id uuid primary key,
     follower_id uuid not null references users (id),
@@ -35,3 +48,249 @@ create table follows (
 );
 
 create unique index follows_follower_followee on follows(follower_id, followee_id);
+
+create table totp_secrets (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    secret varchar not null,
+    confirmed_at timestamp with time zone,
+    -- The time step of the last code that was accepted, so codes can't be used twice
+    last_used_step bigint,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index totp_secrets_user_id on totp_secrets(user_id);
+
+create table recovery_codes (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    hashed_code varchar not null,
+    used_at timestamp with time zone,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create index recovery_codes_user_id on recovery_codes(user_id);
+
+create table second_factor_challenges (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    token varchar not null,
+    expires_at timestamp with time zone not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index second_factor_challenges_token on second_factor_challenges(token);
+
+create table api_tokens (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    name varchar not null,
+    hashed_token varchar not null,
+    scopes varchar not null,
+    last_used_at timestamp with time zone,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index api_tokens_hashed_token on api_tokens(hashed_token);
+create index api_tokens_user_id on api_tokens(user_id);
+
+create table oauth_clients (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    name varchar not null,
+    hashed_client_secret varchar,
+    redirect_uris varchar not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create table oauth_authorization_codes (
+    id uuid primary key,
+    client_id uuid not null references oauth_clients (id),
+    user_id uuid not null references users (id),
+    hashed_code varchar not null,
+    redirect_uri varchar not null,
+    scopes varchar not null,
+    code_challenge varchar not null,
+    expires_at timestamp with time zone not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index oauth_authorization_codes_hashed_code on oauth_authorization_codes(hashed_code);
+
+create table oauth_access_tokens (
+    id uuid primary key,
+    client_id uuid not null references oauth_clients (id),
+    user_id uuid not null references users (id),
+    hashed_token varchar not null,
+    scopes varchar not null,
+    expires_at timestamp with time zone not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index oauth_access_tokens_hashed_token on oauth_access_tokens(hashed_token);
+
+create table idempotency_keys (
+    id uuid primary key,
+    scope varchar not null,
+    key varchar not null,
+    request_hash varchar not null,
+    response_status integer,
+    response_body varchar,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index idempotency_keys_scope_key on idempotency_keys(scope, key);
+create index idempotency_keys_created_at on idempotency_keys(created_at);
+
+create table scheduled_tweets (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    text varchar not null,
+    publish_at timestamp with time zone not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create index scheduled_tweets_user_id on scheduled_tweets(user_id);
+create index scheduled_tweets_publish_at on scheduled_tweets(publish_at);
+
+create table drafts (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    text varchar not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create index drafts_user_id on drafts(user_id);
+
+-- Uploaded images. The files themselves are in the blob store, keyed by id. `tweet_id` is set
+-- once they're attached to a tweet.
+create table media (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    content_type varchar not null,
+    width integer not null,
+    height integer not null,
+    tweet_id uuid references tweets (id) on delete cascade,
+    position integer,
+    created_at timestamp with time zone not null
+);
+
+create index media_tweet_id on media(tweet_id);
+
+create table polls (
+    id uuid primary key,
+    tweet_id uuid not null references tweets (id) on delete cascade,
+    closes_at timestamp with time zone not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null
+);
+
+create unique index polls_tweet_id on polls(tweet_id);
+
+create table poll_options (
+    id uuid primary key,
+    poll_id uuid not null references polls (id) on delete cascade,
+    position integer not null,
+    text varchar not null
+);
+
+create unique index poll_options_poll_id_position on poll_options(poll_id, position);
+
+create table poll_votes (
+    id uuid primary key,
+    poll_id uuid not null references polls (id) on delete cascade,
+    poll_option_id uuid not null references poll_options (id) on delete cascade,
+    user_id uuid not null references users (id),
+    created_at timestamp with time zone not null
+);
+
+create unique index poll_votes_poll_id_user_id on poll_votes(poll_id, user_id);
+create index poll_votes_poll_option_id on poll_votes(poll_option_id);
+
+-- Private to the user who made them.
+create table bookmarks (
+    id uuid primary key,
+    user_id uuid not null references users (id),
+    tweet_id uuid not null references tweets (id) on delete cascade,
+    created_at timestamp with time zone not null
+);
+
+create unique index bookmarks_user_id_tweet_id on bookmarks(user_id, tweet_id);
+create index bookmarks_user_id_created_at on bookmarks(user_id, created_at, id);
+
+-- Tweets with everything shown alongside them, as `viewer_id` sees them. Media and the poll are
+-- JSON, and the bookmark columns are null unless the viewer bookmarked the tweet. Postgres inlines
+-- this into the query calling it, so filters on it still use the indexes.
+create function viewed_tweets(viewer_id uuid)
+returns table (
+    id uuid,
+    text text,
+    edit_count integer,
+    created_at timestamp with time zone,
+    user_id uuid,
+    username varchar,
+    media json,
+    poll json,
+    bookmark_id uuid,
+    bookmarked_at timestamp with time zone
+)
+as $$
+    select
+        tweets.id
+        , tweets.text
+        , tweets.edit_count
+        , tweets.created_at
+        , users.id
+        , users.username
+        , (
+            select coalesce(json_agg(json_build_object(
+                'id', media.id
+                , 'content_type', media.content_type
+                , 'width', media.width
+                , 'height', media.height
+            ) order by media.position), '[]')
+            from media
+            where media.tweet_id = tweets.id
+        )
+        , (
+            select json_build_object(
+                'closes_at', polls.closes_at
+                , 'options', (
+                    select json_agg(json_build_object(
+                        'text', poll_options.text
+                        , 'votes', (
+                            select count(*) from poll_votes
+                            where poll_votes.poll_option_id = poll_options.id
+                        )
+                    ) order by poll_options.position)
+                    from poll_options
+                    where poll_options.poll_id = polls.id
+                )
+                , 'voted_for', (
+                    select poll_options.position
+                    from poll_votes
+                    inner join poll_options
+                        on poll_options.id = poll_votes.poll_option_id
+                    where poll_votes.poll_id = polls.id
+                        and poll_votes.user_id = viewer_id
+                )
+            )
+            from polls
+            where polls.tweet_id = tweets.id
+        )
+        , bookmarks.id
+        , bookmarks.created_at
+    from tweets
+    inner join users on users.id = tweets.user_id
+    left join bookmarks on bookmarks.tweet_id = tweets.id and bookmarks.user_id = viewer_id
+$$ language sql stable;
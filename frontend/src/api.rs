@@ -1,17 +1,33 @@
 use crate::{Error, Model, Msg};
-use payloads::{CreateTweetPayload, LoginPayload};
+use payloads::{
+    AuthorizeOAuthClientPayload, CreateTweetPayload, DraftPayload, LoginPayload,
+    SecondFactorPayload, VotePayload,
+};
 use seed::{prelude::*, *};
+use serde::ser::Error as _;
 use shared::payloads::CreateUserPayload;
-use shared::responses::{ApiResponse, TokenResponse, UserResponse};
-use shared::Url as _;
+use shared::queries::Pagination;
+use shared::responses::{
+    ApiError, ApiErrorResponse, ApiResponse, LoginResponse, TokenResponse, UserResponse,
+};
 use shared::*;
 
-const API_URL: &'static str = "http://localhost:8080";
+/// Set `API_URL` when building to point the frontend at another backend.
+const API_URL: &str = match option_env!("API_URL") {
+    Some(url) => url,
+    None => "http://localhost:8080",
+};
+
+/// For URLs the API returns, like those of images, which are relative to it.
+pub fn api_url(path: &str) -> String {
+    format!("{}{}", API_URL, path)
+}
 
 pub async fn create_user(username: String, password: String) -> Msg {
     fetch::<CreateUser>(
         None,
         CreateUserUrl,
+        NoQuery {},
         CreateUserPayload { username, password },
         |resp| Msg::CreateUserEndpointResponded(resp.token),
     )
@@ -21,15 +37,59 @@ pub async fn create_user(username: String, password: String) -> Msg {
 pub async fn login(username: String, password: String) -> Msg {
     fetch::<Login>(
         None,
-        LoginUrl { username },
+        LoginUrl {
+            username: username.clone(),
+        },
+        NoQuery {},
         LoginPayload { password },
+        |resp| match resp {
+            LoginResponse::Token(resp) => Msg::LoginEndpointResponded(resp.token),
+            LoginResponse::SecondFactorRequired(resp) => {
+                Msg::SecondFactorRequired(resp.challenge_token)
+            }
+        },
+    )
+    .await
+}
+
+pub async fn complete_two_factor_login(
+    username: String,
+    challenge_token: String,
+    code: String,
+) -> Msg {
+    fetch::<CompleteTwoFactorLogin>(
+        None,
+        CompleteTwoFactorLoginUrl { username },
+        NoQuery {},
+        SecondFactorPayload {
+            challenge_token,
+            code,
+        },
         |resp| Msg::LoginEndpointResponded(resp.token),
     )
     .await
 }
 
+pub async fn logout(auth_token: String, username: String) -> Msg {
+    fetch::<Logout>(
+        Some(auth_token),
+        LogoutUrl { username },
+        NoQuery {},
+        NoPayload,
+        |()| Msg::Noop,
+    )
+    .await
+}
+
 pub async fn reload_current_user(auth_token: String) -> Msg {
-    fetch::<Me>(Some(auth_token), MeUrl, NoPayload, Msg::MeLoaded).await
+    fetch::<Me>(
+        Some(auth_token),
+        MeUrl,
+        NoQuery {},
+        NoPayload,
+        Msg::MeLoaded,
+    )
+    .await
 }
 
 pub async fn load_user(username: String, auth_token: Option<String>) -> Msg {
@@ -38,6 +98,7 @@ pub async fn load_user(username: String, auth_token: Option<String>) -> Msg {
         GetUserUrl {
             username: username.to_string(),
         },
+        NoQuery {},
         NoPayload,
         Msg::GetUserLoaded,
     )
@@ -48,6 +109,7 @@ pub async fn load_timeline(auth_token: Option<String>) -> Msg {
     fetch::<Timeline>(
         auth_token,
         TimelineUrl,
+        Pagination::default(),
         NoPayload,
         Msg::LoadTimelineEndpointResponded,
     )
@@ -58,39 +120,161 @@ pub async fn post_tweet(auth_token: Option<String>, text: String) -> Msg {
     fetch::<PostTweet>(
         auth_token,
         PostTweetUrl,
-        CreateTweetPayload { text },
+        NoQuery {},
+        CreateTweetPayload {
+            text,
+            publish_at: None,
+            media_ids: Vec::new(),
+            poll: None,
+        },
         Msg::PostTweetEndpointResponded,
     )
     .await
 }
 
+pub async fn vote_in_poll(auth_token: Option<String>, id: uuid::Uuid, option: u32) -> Msg {
+    fetch::<VoteInPoll>(
+        auth_token,
+        VoteInPollUrl { id },
+        NoQuery {},
+        VotePayload { option },
+        Msg::PollVoted,
+    )
+    .await
+}
+
+pub async fn bookmark_tweet(auth_token: Option<String>, id: uuid::Uuid) -> Msg {
+    fetch::<BookmarkTweet>(
+        auth_token,
+        BookmarkTweetUrl { id },
+        NoQuery {},
+        NoPayload,
+        |()| Msg::Noop,
+    )
+    .await
+}
+
+pub async fn remove_bookmark(auth_token: Option<String>, id: uuid::Uuid) -> Msg {
+    fetch::<RemoveBookmark>(
+        auth_token,
+        RemoveBookmarkUrl { id },
+        NoQuery {},
+        NoPayload,
+        |()| Msg::Noop,
+    )
+    .await
+}
+
+pub async fn load_drafts(auth_token: Option<String>) -> Msg {
+    fetch::<ListDrafts>(
+        auth_token,
+        ListDraftsUrl,
+        NoQuery {},
+        NoPayload,
+        Msg::DraftsLoaded,
+    )
+    .await
+}
+
+pub async fn create_draft(auth_token: Option<String>, text: String) -> Msg {
+    let msg = fetch::<CreateDraft>(
+        auth_token,
+        CreateDraftUrl,
+        NoQuery {},
+        DraftPayload { text },
+        Msg::DraftSaved,
+    )
+    .await;
+
+    match msg {
+        Msg::Error(err) => Msg::DraftNotCreated(err),
+        msg => msg,
+    }
+}
+
+pub async fn update_draft(auth_token: Option<String>, id: uuid::Uuid, text: String) -> Msg {
+    fetch::<UpdateDraft>(
+        auth_token,
+        UpdateDraftUrl { id },
+        NoQuery {},
+        DraftPayload { text },
+        Msg::DraftSaved,
+    )
+    .await
+}
+
+pub async fn delete_draft(auth_token: Option<String>, id: uuid::Uuid) -> Msg {
+    fetch::<DeleteDraft>(
+        auth_token,
+        DeleteDraftUrl { id },
+        NoQuery {},
+        NoPayload,
+        |()| Msg::Noop,
+    )
+    .await
+}
+
+pub async fn load_oauth_client(client_id: uuid::Uuid) -> Msg {
+    fetch::<GetOAuthClient>(
+        None,
+        GetOAuthClientUrl { client_id },
+        NoQuery {},
+        NoPayload,
+        Msg::OAuthClientLoaded,
+    )
+    .await
+}
+
+pub async fn authorize_oauth_client(
+    auth_token: Option<String>,
+    payload: AuthorizeOAuthClientPayload,
+) -> Msg {
+    fetch::<AuthorizeOAuthClient>(
+        auth_token,
+        AuthorizeOAuthClientUrl,
+        NoQuery {},
+        payload,
+        |resp| Msg::OAuthAuthorizationResponded(resp.redirect_to),
+    )
+    .await
+}
+
 pub async fn fetch<E>(
     auth_token: Option<String>,
     url: E::Url,
+    query: E::Query,
     payload: E::Payload,
     make_msg: fn(E::Response) -> Msg,
 ) -> Msg
 where
     E: ApiEndpoint,
     E::Response: 'static,
-    E::Payload: SetRequestPayload,
+    E::Error: Into<ApiError>,
 {
     let result = (|| async {
-        let mut req =
-            Request::new(format!("{}{}", API_URL, url.url())).method(convert_method(E::METHOD));
+        let path = shared::request_path::<E>(&url, &query)
+            .map_err(|err| FetchError::SerdeError(serde_json::Error::custom(err)))?;
+        let mut req = Request::new(api_url(&path)).method(convert_method(E::METHOD));
         if let Some(auth_token) = auth_token {
             req = req.header(Header::bearer(auth_token));
         }
 
-        req = payload.set_request_payload(req)?;
+        if E::PAYLOAD_ENCODING == PayloadEncoding::Json {
+            req = req.json(&payload)?;
+        }
 
         let resp = seed::browser::fetch::fetch(req).await?;
 
-        let value = resp
-            .check_status()?
-            .json::<ApiResponse<E::Response>>()
-            .await?
-            .data;
+        let status = resp.status();
+        if !status.is_ok() {
+            // Responses that didn't come from our backend won't have an error body
+            return match resp.json::<ApiErrorResponse<E::Error>>().await {
+                Ok(body) => Ok(Msg::Error(Error::Api(body.error.into()))),
+                Err(_) => Err(FetchError::StatusError(status)),
+            };
+        }
+
+        let value = resp.json::<ApiResponse<E::Response>>().await?.data;
 
         seed::browser::fetch::Result::Ok(make_msg(value))
     })()
@@ -115,36 +299,3 @@ fn convert_method(method: http_types::Method) -> seed::browser::fetch::Method {
         http_types::Method::Patch => seed::browser::fetch::Method::Patch,
     }
 }
-
-pub trait SetRequestPayload {
-    fn set_request_payload<'a>(
-        &self,
-        req: Request<'a>,
-    ) -> seed::browser::fetch::Result<Request<'a>>;
-}
-
-impl SetRequestPayload for NoPayload {
-    fn set_request_payload<'a>(
-        &self,
-        req: Request<'a>,
-    ) -> seed::browser::fetch::Result<Request<'a>> {
-        Ok(req)
-    }
-}
-
-macro_rules! impl_set_request_payload {
-    ($name:ident) => {
-        impl SetRequestPayload for $name {
-            fn set_request_payload<'a>(
-                &self,
-                req: Request<'a>,
-            ) -> seed::browser::fetch::Result<Request<'a>> {
-                req.json(self)
-            }
-        }
-    };
-}
-
-impl_set_request_payload!(CreateTweetPayload);
-impl_set_request_payload!(LoginPayload);
-impl_set_request_payload!(CreateUserPayload);
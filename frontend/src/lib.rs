@@ -1,6 +1,10 @@
 use flash::Flash;
 use seed::{prelude::*, *};
-use shared::responses::{PostTweetResponse, TweetResponse, UserResponse};
+use shared::payloads::AuthorizeOAuthClientPayload;
+use shared::responses::{
+    ApiError, DraftResponse, OAuthClientResponse, PostTweetResponse, TweetResponse, UserResponse,
+};
+use shared::{credentials, tweet_text};
 use std::fmt;
 use web_sys::HtmlInputElement;
 
@@ -14,6 +18,7 @@ pub struct Model {
     login_form: LoginForm,
     sign_up_form: SignUpForm,
     post_tweet_form: PostTweetForm,
+    two_factor_form: TwoFactorForm,
     auth_token: Option<String>,
     current_user: Option<UserResponse>,
     page: Page,
@@ -49,9 +54,41 @@ struct SignUpForm {
     password_input: ElRef<HtmlInputElement>,
 }
 
+#[derive(Debug, Default)]
+struct TwoFactorForm {
+    code_input: ElRef<HtmlInputElement>,
+    username: Option<String>,
+    challenge_token: Option<String>,
+}
+
+/// How long to wait after the last keystroke before saving the draft.
+const AUTOSAVE_DELAY_MS: u32 = 1000;
+
+/// What to do once a draft that's being created has been saved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AfterCreate {
+    Nothing,
+    /// The text changed in the meantime.
+    Save,
+    /// The tweet was posted in the meantime.
+    Delete,
+}
+
 #[derive(Debug, Default)]
 struct PostTweetForm {
     text_input: ElRef<HtmlInputElement>,
+    /// The draft we're saving to. `None` until the first autosave. Kept when leaving the page so
+    /// coming back carries on with the same draft.
+    draft_id: Option<uuid::Uuid>,
+    /// Set while the first autosave is creating the draft. Later autosaves wait for it rather than
+    /// creating a second one.
+    creating_draft: Option<AfterCreate>,
+    /// The latest draft from an earlier visit, until the user restores or discards it.
+    restorable_draft: Option<DraftResponse>,
+    /// Bumped on every change so only the last one in a burst of typing gets saved.
+    autosave_generation: u32,
+    /// As counted by `tweet_text::length`, for the character counter.
+    length: usize,
 }
 
 #[derive(Debug)]
@@ -65,10 +102,17 @@ pub enum Page {
     RootLoggedOut,
     Timeline(PageData<Vec<TweetResponse>>),
     Login,
+    TwoFactorLogin,
     SignUp,
     UserProfile(String),
     SignedIn,
     PostTweet,
+    /// Asks the user whether a third party app may access their account. `None` if the app sent
+    /// us an invalid authorization request.
+    OAuthAuthorize(
+        Option<AuthorizeOAuthClientPayload>,
+        PageData<OAuthClientResponse>,
+    ),
 }
 
 impl Page {
@@ -88,8 +132,18 @@ impl Page {
             Page::Timeline(_) => {
                 orders.send_msg(Msg::LoadTimeline);
             }
-            Page::RootLoggedOut | Page::Login | Page::SignUp | Page::SignedIn | Page::PostTweet => {
+            Page::PostTweet => {
+                orders.send_msg(Msg::LoadDrafts);
+            }
+            Page::OAuthAuthorize(Some(request), _) => {
+                orders.send_msg(Msg::LoadOAuthClient(request.client_id));
             }
+            Page::OAuthAuthorize(None, _)
+            | Page::RootLoggedOut
+            | Page::Login
+            | Page::TwoFactorLogin
+            | Page::SignUp
+            | Page::SignedIn => {}
         }
     }
 
@@ -99,6 +153,7 @@ impl Page {
         match path.as_slice() {
             ["sign_up"] => Page::SignUp,
             ["login"] => Page::Login,
+            ["login", "2fa"] => Page::TwoFactorLogin,
             ["users", username] => Page::UserProfile(username.to_string()),
             [] => {
                 if model.logged_in() {
@@ -109,6 +164,9 @@ impl Page {
             }
             ["signed_in"] => Page::SignedIn,
             ["tweets", "new"] => Page::PostTweet,
+            ["oauth", "authorize"] => {
+                Page::OAuthAuthorize(oauth_authorization_request(&url), PageData::NotLoaded)
+            }
             _ => todo!("Unknown URL: {}", url),
         }
     }
@@ -120,19 +178,41 @@ impl fmt::Display for Page {
             Page::RootLoggedOut => write!(f, "/"),
             Page::Timeline(_) => write!(f, "/"),
             Page::Login => write!(f, "/login"),
+            Page::TwoFactorLogin => write!(f, "/login/2fa"),
             Page::SignUp => write!(f, "/sign_up"),
             Page::UserProfile(username) => write!(f, "/users/{}", username.clone()),
             Page::SignedIn => write!(f, "/signed_in"),
             Page::PostTweet => write!(f, "/tweets/new"),
+            Page::OAuthAuthorize(_, _) => write!(f, "/oauth/authorize"),
         }
     }
 }
 
+fn oauth_authorization_request(url: &Url) -> Option<AuthorizeOAuthClientPayload> {
+    let param = |key: &str| url.search().get(key)?.first().cloned();
+
+    if param("response_type")? != "code" {
+        return None;
+    }
+
+    Some(AuthorizeOAuthClientPayload {
+        approved: false,
+        client_id: param("client_id")?.parse().ok()?,
+        redirect_uri: param("redirect_uri")?,
+        scope: param("scope")?,
+        state: param("state"),
+        code_challenge: param("code_challenge")?,
+        code_challenge_method: param("code_challenge_method")?,
+    })
+}
+
 #[derive(Debug)]
 pub enum Msg {
     LoginFormSubmitted,
     SignUpFormSubmitted,
     LoginEndpointResponded(String),
+    SecondFactorRequired(String),
+    TwoFactorFormSubmitted,
     CreateUserEndpointResponded(String),
     MeLoaded(UserResponse),
     UrlChanged(subs::UrlChanged),
@@ -146,13 +226,29 @@ pub enum Msg {
     LoadTimeline,
     PostTweetFormSubmitted,
     PostTweetEndpointResponded(PostTweetResponse),
-    #[allow(dead_code)]
+    PostTweetTextChanged,
+    VoteInPoll(uuid::Uuid, u32),
+    PollVoted(TweetResponse),
+    ToggleBookmark(uuid::Uuid, bool),
+    AutosaveDraft(u32),
+    LoadDrafts,
+    DraftsLoaded(Vec<DraftResponse>),
+    DraftSaved(DraftResponse),
+    DraftNotCreated(Error),
+    RestoreDraft,
+    DiscardDraft,
+    LoadOAuthClient(uuid::Uuid),
+    OAuthClientLoaded(OAuthClientResponse),
+    OAuthAuthorizationAnswered(bool),
+    OAuthAuthorizationResponded(String),
     Noop,
 }
 
 #[derive(Debug)]
 pub enum Error {
     RequestFailed(FetchError),
+    /// The backend responded with an error.
+    Api(ApiError),
 }
 
 fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
@@ -174,9 +270,29 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
             let form = &model.login_form;
             let username = form.username_input.get().unwrap().value();
             let password = form.password_input.get().unwrap().value();
+            model.two_factor_form.username = Some(username.clone());
             orders.perform_cmd(api::login(username, password));
         }
+        Msg::SecondFactorRequired(challenge_token) => {
+            model.two_factor_form.challenge_token = Some(challenge_token);
+            Page::TwoFactorLogin.go(model, orders);
+        }
+        Msg::TwoFactorFormSubmitted => {
+            let form = &model.two_factor_form;
+            let code = form.code_input.get().unwrap().value();
+            match (form.username.clone(), form.challenge_token.clone()) {
+                (Some(username), Some(challenge_token)) => {
+                    orders.perform_cmd(api::complete_two_factor_login(
+                        username,
+                        challenge_token,
+                        code,
+                    ));
+                }
+                _ => Page::Login.go(model, orders),
+            }
+        }
         Msg::LoginEndpointResponded(token) => {
+            model.two_factor_form = Default::default();
             model.set_auth_token(&token);
             orders.perform_cmd(api::reload_current_user(token.to_string()));
             Page::SignedIn.go(model, orders);
@@ -186,6 +302,22 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
             let form = &model.sign_up_form;
             let username = form.username_input.get().unwrap().value();
             let password = form.password_input.get().unwrap().value();
+
+            // Same rules as the backend, so most mistakes don't need a round trip
+            let problems = credentials::username_errors(&username)
+                .into_iter()
+                .map(|message| format!("Username {}", message))
+                .chain(
+                    credentials::password_errors(&password, &username)
+                        .into_iter()
+                        .map(|message| format!("Password {}", message)),
+                )
+                .collect::<Vec<_>>();
+            if !problems.is_empty() {
+                model.flash.set_error(&problems.join(". "), orders);
+                return;
+            }
+
             orders.perform_cmd(api::create_user(username, password));
         }
         Msg::CreateUserEndpointResponded(token) => {
@@ -204,6 +336,10 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
                 log!("request failed", err);
                 model.flash.set_error("Request failed", orders);
             }
+            Error::Api(err) => {
+                log!("api error", err);
+                model.flash.set_error(&err.message, orders);
+            }
         },
 
         Msg::ClearFlash => {
@@ -211,6 +347,10 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
         }
 
         Msg::Logout => {
+            // Forget the token right away rather than waiting for the backend to revoke it
+            if let (Some(token), Some(user)) = (&model.auth_token, &model.current_user) {
+                orders.perform_cmd(api::logout(token.clone(), user.username.clone()));
+            }
             Page::RootLoggedOut.go(model, orders);
             model.remove_auth_token();
         }
@@ -223,15 +363,170 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
         Msg::LoadTimeline => {
             orders.perform_cmd(api::load_timeline(model.auth_token.clone()));
         }
+        Msg::VoteInPoll(id, option) => {
+            orders.perform_cmd(api::vote_in_poll(model.auth_token.clone(), id, option));
+        }
+        Msg::ToggleBookmark(id, bookmarked) => {
+            // Shown straight away. A failed request shows an error instead.
+            if let Page::Timeline(PageData::Loaded(tweets)) = &mut model.page {
+                for tweet in tweets.iter_mut().filter(|tweet| tweet.id == id) {
+                    tweet.bookmarked_by_me = bookmarked;
+                }
+            }
+            let auth_token = model.auth_token.clone();
+            if bookmarked {
+                orders.perform_cmd(api::bookmark_tweet(auth_token, id));
+            } else {
+                orders.perform_cmd(api::remove_bookmark(auth_token, id));
+            }
+        }
+        Msg::PollVoted(voted) => {
+            if let Page::Timeline(PageData::Loaded(tweets)) = &mut model.page {
+                for tweet in tweets.iter_mut().filter(|tweet| tweet.id == voted.id) {
+                    *tweet = voted.clone();
+                }
+            }
+        }
 
         Msg::PostTweetFormSubmitted => {
-            let text = model.post_tweet_form.text_input.get().unwrap().value();
+            let form = &mut model.post_tweet_form;
+            // Don't save the draft again once it's been posted
+            form.autosave_generation += 1;
+            let text = form.text_input.get().unwrap().value();
             orders.perform_cmd(api::post_tweet(model.auth_token.clone(), text));
         }
         Msg::PostTweetEndpointResponded(_) => {
+            let form = &mut model.post_tweet_form;
+            if let Some(id) = form.draft_id.take() {
+                orders.perform_cmd(api::delete_draft(model.auth_token.clone(), id));
+            }
+            if form.creating_draft.is_some() {
+                form.creating_draft = Some(AfterCreate::Delete);
+            }
             model.flash.set_notice("Tweet posted", orders);
             Page::Timeline(PageData::NotLoaded).go(model, orders);
         }
+
+        Msg::PostTweetTextChanged => {
+            let form = &mut model.post_tweet_form;
+            if let Some(input) = form.text_input.get() {
+                form.length = tweet_text::length(&input.value());
+            }
+            form.autosave_generation += 1;
+            let generation = form.autosave_generation;
+            orders.perform_cmd(cmds::timeout(AUTOSAVE_DELAY_MS, move || {
+                Msg::AutosaveDraft(generation)
+            }));
+        }
+        Msg::AutosaveDraft(generation) => {
+            let form = &mut model.post_tweet_form;
+            if generation != form.autosave_generation {
+                return;
+            }
+            if form.creating_draft.is_some() {
+                form.creating_draft = Some(AfterCreate::Save);
+                return;
+            }
+            let text = match form.text_input.get() {
+                Some(input) => input.value(),
+                None => return,
+            };
+            let auth_token = model.auth_token.clone();
+            match form.draft_id {
+                Some(id) => {
+                    orders.perform_cmd(api::update_draft(auth_token, id, text));
+                }
+                None => {
+                    form.creating_draft = Some(AfterCreate::Nothing);
+                    orders.perform_cmd(api::create_draft(auth_token, text));
+                }
+            };
+        }
+        Msg::LoadDrafts => {
+            model.post_tweet_form.length = 0;
+            orders.perform_cmd(api::load_drafts(model.auth_token.clone()));
+        }
+        Msg::DraftsLoaded(drafts) => {
+            let form = &mut model.post_tweet_form;
+            // Carry on with the draft from earlier in this session if it's still around, otherwise
+            // start a new one but offer to pick up the last one
+            let current = form
+                .draft_id
+                .and_then(|id| drafts.iter().find(|draft| draft.id == id));
+            match current {
+                Some(draft) => {
+                    if let Some(input) = form.text_input.get() {
+                        input.set_value(&draft.text);
+                    }
+                    form.length = tweet_text::length(&draft.text);
+                }
+                None => {
+                    form.draft_id = None;
+                    form.restorable_draft = drafts.into_iter().next();
+                }
+            }
+        }
+        Msg::DraftSaved(draft) => {
+            let form = &mut model.post_tweet_form;
+            match form.creating_draft.take() {
+                Some(AfterCreate::Delete) => {
+                    orders.perform_cmd(api::delete_draft(model.auth_token.clone(), draft.id));
+                }
+                Some(AfterCreate::Save) => {
+                    form.draft_id = Some(draft.id);
+                    orders.send_msg(Msg::AutosaveDraft(form.autosave_generation));
+                }
+                Some(AfterCreate::Nothing) | None => {
+                    form.draft_id = Some(draft.id);
+                }
+            }
+        }
+        Msg::DraftNotCreated(err) => {
+            model.post_tweet_form.creating_draft = None;
+            orders.send_msg(Msg::Error(err));
+        }
+        Msg::RestoreDraft => {
+            let form = &mut model.post_tweet_form;
+            if let Some(draft) = form.restorable_draft.take() {
+                if let Some(input) = form.text_input.get() {
+                    input.set_value(&draft.text);
+                }
+                form.length = tweet_text::length(&draft.text);
+                form.draft_id = Some(draft.id);
+            }
+        }
+        Msg::DiscardDraft => {
+            if let Some(draft) = model.post_tweet_form.restorable_draft.take() {
+                orders.perform_cmd(api::delete_draft(model.auth_token.clone(), draft.id));
+            }
+        }
+
+        Msg::LoadOAuthClient(client_id) => {
+            orders.perform_cmd(api::load_oauth_client(client_id));
+        }
+        Msg::OAuthClientLoaded(client) => {
+            if let Page::OAuthAuthorize(_, data) = &mut model.page {
+                *data = PageData::Loaded(client);
+            }
+        }
+        Msg::OAuthAuthorizationAnswered(approved) => {
+            if let Page::OAuthAuthorize(Some(request), _) = &model.page {
+                let payload = AuthorizeOAuthClientPayload {
+                    approved,
+                    ..request.clone()
+                };
+                orders.perform_cmd(api::authorize_oauth_client(
+                    model.auth_token.clone(),
+                    payload,
+                ));
+            }
+        }
+        Msg::OAuthAuthorizationResponded(redirect_to) => {
+            // Leaves our app and sends the user back to the third party app
+            if let Err(err) = window().location().set_href(&redirect_to) {
+                log!("redirect failed", err);
+            }
+        }
     }
 }
 
@@ -246,6 +541,7 @@ fn init(url: Url, orders: &mut impl Orders<Msg>) -> Model {
         login_form: Default::default(),
         sign_up_form: Default::default(),
         post_tweet_form: Default::default(),
+        two_factor_form: Default::default(),
         flash: Default::default(),
     };
 
@@ -1,6 +1,8 @@
-use crate::{flash::FlashMsg, Model, Msg, Page, PageData};
+use crate::{api, flash::FlashMsg, Model, Msg, Page, PageData};
 use seed::{prelude::*, *};
-use shared::responses::TweetResponse;
+use shared::payloads::AuthorizeOAuthClientPayload;
+use shared::responses::{OAuthClientResponse, PollResponse, TweetResponse};
+use shared::MAX_TWEET_LENGTH;
 
 pub fn view(model: &Model) -> Vec<Node<Msg>> {
     nodes![flash(model), nav(model), view_page(model),]
@@ -10,11 +12,13 @@ fn view_page(model: &Model) -> Node<Msg> {
     match &model.page {
         Page::RootLoggedOut => p!["Welcome"],
         Page::Login => login(model),
+        Page::TwoFactorLogin => two_factor_login(model),
         Page::SignUp => sign_up(model),
         Page::UserProfile(username) => user_profile(username),
         Page::SignedIn => signed_in(),
         Page::PostTweet => post_tweet(model),
         Page::Timeline(tweets) => timeline(model, tweets),
+        Page::OAuthAuthorize(request, client) => oauth_authorize(model, request, client),
     }
 }
 
@@ -40,20 +44,80 @@ fn tweet(tweet: &TweetResponse) -> Node<Msg> {
         br![],
         &tweet.text,
         br![],
+        tweet.media.iter().map(|media| {
+            a![
+                img![attrs! {
+                    At::Src => api::api_url(&media.thumbnail_url),
+                    At::Alt => "",
+                }],
+                attrs! { At::Href => api::api_url(&media.url) },
+            ]
+        }),
+        tweet.poll.as_ref().map(|poll| view_poll(tweet.id, poll)),
         format!("{:?}", &tweet.created_at),
+        if tweet.edited { " (edited)" } else { "" },
+        bookmark_button(tweet),
         hr![],
     ]
 }
 
+fn bookmark_button(tweet: &TweetResponse) -> Node<Msg> {
+    let (id, bookmarked) = (tweet.id, tweet.bookmarked_by_me);
+    button![
+        if bookmarked {
+            "Remove bookmark"
+        } else {
+            "Bookmark"
+        },
+        ev(Ev::Click, move |_| Msg::ToggleBookmark(id, !bookmarked))
+    ]
+}
+
+/// Options are buttons until the counts can be shown, i.e. once you've voted or the poll has
+/// closed.
+fn view_poll(tweet_id: uuid::Uuid, poll: &PollResponse) -> Node<Msg> {
+    div![
+        poll.options
+            .iter()
+            .enumerate()
+            .map(|(position, option)| match option.votes {
+                Some(votes) => {
+                    let mine = poll.voted_for == Some(position as u32);
+                    div![
+                        &option.text,
+                        format!(": {}", votes),
+                        if mine { " (your vote)" } else { "" },
+                    ]
+                }
+                None if poll.closed => div![&option.text],
+                None => div![button![
+                    &option.text,
+                    ev(Ev::Click, move |_| Msg::VoteInPoll(
+                        tweet_id,
+                        position as u32
+                    ))
+                ]],
+            }),
+        if poll.closed {
+            "Final results".to_string()
+        } else {
+            format!("Closes {:?}", poll.closes_at)
+        },
+    ]
+}
+
 fn post_tweet(model: &Model) -> Node<Msg> {
     div![
+        restorable_draft(model),
         div![input![
             el_ref(&model.post_tweet_form.text_input),
             attrs! {
                 At::Type => "text",
                 At::Placeholder => "Whats up?",
             },
+            ev(Ev::Input, |_| Msg::PostTweetTextChanged),
         ]],
+        character_counter(model.post_tweet_form.length),
         div![button![
             "Post",
             ev(Ev::Click, |_| Msg::PostTweetFormSubmitted)
@@ -61,6 +125,28 @@ fn post_tweet(model: &Model) -> Node<Msg> {
     ]
 }
 
+fn character_counter(length: usize) -> Node<Msg> {
+    let counter = format!("{}/{}", length, MAX_TWEET_LENGTH);
+    if length > MAX_TWEET_LENGTH {
+        div![counter, " (too long)"]
+    } else {
+        div![counter]
+    }
+}
+
+fn restorable_draft(model: &Model) -> Node<Msg> {
+    match &model.post_tweet_form.restorable_draft {
+        None => empty![],
+        Some(draft) => div![
+            "You have an unfinished tweet: ",
+            &draft.text,
+            " ",
+            button!["Restore", ev(Ev::Click, |_| Msg::RestoreDraft)],
+            button!["Discard", ev(Ev::Click, |_| Msg::DiscardDraft)],
+        ],
+    }
+}
+
 fn flash(model: &Model) -> Node<Msg> {
     match model.flash.get() {
         None => div![],
@@ -125,6 +211,23 @@ fn login(model: &Model) -> Node<Msg> {
     ]
 }
 
+fn two_factor_login(model: &Model) -> Node<Msg> {
+    div![
+        p!["Enter the code from your authenticator app or one of your recovery codes"],
+        div![input![
+            el_ref(&model.two_factor_form.code_input),
+            attrs! {
+                At::Type => "text",
+                At::Placeholder => "Code",
+            },
+        ]],
+        div![button![
+            "Verify",
+            ev(Ev::Click, |_| Msg::TwoFactorFormSubmitted),
+        ]]
+    ]
+}
+
 fn sign_up(model: &Model) -> Node<Msg> {
     div![
         div![input![
@@ -151,3 +254,45 @@ fn sign_up(model: &Model) -> Node<Msg> {
 fn user_profile(username: &str) -> Node<Msg> {
     p!["Profile of ", username]
 }
+
+fn oauth_authorize(
+    model: &Model,
+    request: &Option<AuthorizeOAuthClientPayload>,
+    client: &PageData<OAuthClientResponse>,
+) -> Node<Msg> {
+    let request = match request {
+        Some(request) => request,
+        None => return p!["Invalid authorization request"],
+    };
+    let client = match client {
+        PageData::Loaded(client) => client,
+        PageData::NotLoaded => return p!["Loading..."],
+    };
+
+    if !model.logged_in() {
+        return div![
+            p![&client.name, " wants to access your account."],
+            a!["Login to continue", attrs! { At::Href => Page::Login }],
+        ];
+    }
+
+    let scopes = request.scope.split(' ').map(|scope| li![scope]);
+
+    div![
+        p![
+            &client.name,
+            " wants to access your account with these permissions:"
+        ],
+        ul![scopes],
+        div![
+            button![
+                "Allow",
+                ev(Ev::Click, |_| Msg::OAuthAuthorizationAnswered(true)),
+            ],
+            button![
+                "Deny",
+                ev(Ev::Click, |_| Msg::OAuthAuthorizationAnswered(false)),
+            ],
+        ]
+    ]
+}
@@ -0,0 +1,296 @@
+//! Generates the boilerplate for endpoints in the `shared` crate.
+//!
+//! ```ignore
+//! #[endpoint(GET, "/users/:username", response = responses::UserResponse)]
+//! pub struct GetUser {
+//!     pub username: String,
+//! }
+//! ```
+//!
+//! expands to a `GetUser` marker struct implementing `ApiEndpoint` and a `GetUserUrl` struct
+//! with the fields from the annotated struct implementing `Url`. Endpoints that take a JSON body
+//! specify `payload = ...`, otherwise there is no body and the payload is `NoPayload`. Endpoints
+//! that take query parameters also specify `query = ...`, otherwise it defaults to `NoQuery`.
+//! Likewise `error` defaults to `responses::ApiError`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Fields, Ident, ItemStruct, LitStr, Token, Type};
+
+#[proc_macro_attribute]
+pub fn endpoint(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as EndpointArgs);
+    let item = parse_macro_input!(item as ItemStruct);
+
+    match expand(args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct EndpointArgs {
+    method: Ident,
+    url_spec: LitStr,
+    payload: Option<Type>,
+    query: Option<Type>,
+    response: Type,
+    error: Option<Type>,
+}
+
+impl Parse for EndpointArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let method = input.parse::<Ident>()?;
+        input.parse::<Token![,]>()?;
+        let url_spec = input.parse::<LitStr>()?;
+
+        let mut payload = None;
+        let mut query = None;
+        let mut response = None;
+        let mut error = None;
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let key = input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+            let value = input.parse::<Type>()?;
+
+            let slot = match key.to_string().as_str() {
+                "payload" => &mut payload,
+                "query" => &mut query,
+                "response" => &mut response,
+                "error" => &mut error,
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "expected `payload`, `query`, `response`, or `error`",
+                    ))
+                }
+            };
+            if slot.is_some() {
+                return Err(syn::Error::new(key.span(), format!("duplicate `{}`", key)));
+            }
+            *slot = Some(value);
+        }
+
+        Ok(EndpointArgs {
+            method,
+            url_spec,
+            payload,
+            query,
+            response: response.ok_or_else(|| input.error("missing `response = ...`"))?,
+            error,
+        })
+    }
+}
+
+fn expand(args: EndpointArgs, item: ItemStruct) -> syn::Result<TokenStream2> {
+    let method = method_variant(&args.method)?;
+    let segments = parse_url_spec(&args.url_spec.value())
+        .map_err(|msg| syn::Error::new(args.url_spec.span(), msg))?;
+    let field_names = field_names(&item.fields)?;
+
+    for segment in &segments {
+        if let Segment::Param(param) = segment {
+            if !field_names.contains(param) {
+                return Err(syn::Error::new(
+                    args.url_spec.span(),
+                    format!("`:{}` doesn't have a matching field", param),
+                ));
+            }
+        }
+    }
+    for field in &field_names {
+        if !segments.contains(&Segment::Param(field.clone())) {
+            return Err(syn::Error::new(
+                item.ident.span(),
+                format!("field `{}` isn't used in the URL", field),
+            ));
+        }
+    }
+
+    let ItemStruct {
+        attrs,
+        vis,
+        ident,
+        fields,
+        ..
+    } = item;
+    let url_ident = format_ident!("{}Url", ident);
+    let url_spec = &args.url_spec;
+    let response = &args.response;
+    let query = match &args.query {
+        Some(query) => quote! { #query },
+        None => quote! { ::shared::NoQuery },
+    };
+    let error = match &args.error {
+        Some(error) => quote! { #error },
+        None => quote! { ::shared::responses::ApiError },
+    };
+    let (payload, payload_encoding) = match &args.payload {
+        Some(payload) => (
+            quote! { #payload },
+            quote! { ::shared::PayloadEncoding::Json },
+        ),
+        None => (
+            quote! { ::shared::NoPayload },
+            quote! { ::shared::PayloadEncoding::None },
+        ),
+    };
+
+    let url_struct = match &fields {
+        Fields::Unit => quote! { #vis struct #url_ident; },
+        _ => quote! { #vis struct #url_ident #fields },
+    };
+    let url_struct = quote! {
+        #[derive(::shared::__private::JsonSchema)]
+        #url_struct
+    };
+
+    let (format_string, format_args) = url_format(&segments);
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis struct #ident;
+
+        impl ::shared::ApiEndpoint for #ident {
+            type Url = #url_ident;
+            const METHOD: ::shared::__private::Method = ::shared::__private::Method::#method;
+            const PAYLOAD_ENCODING: ::shared::PayloadEncoding = #payload_encoding;
+            type Payload = #payload;
+            type Query = #query;
+            type Response = #response;
+            type Error = #error;
+        }
+
+        #url_struct
+
+        impl ::shared::Url for #url_ident {
+            const URL_SPEC: &'static str = #url_spec;
+
+            fn url(&self) -> String {
+                format!(#format_string, #(#format_args),*)
+            }
+        }
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+fn parse_url_spec(spec: &str) -> Result<Vec<Segment>, String> {
+    if !spec.starts_with('/') {
+        return Err("URLs must start with `/`".to_string());
+    }
+
+    spec[1..]
+        .split('/')
+        .map(|segment| {
+            if let Some(param) = segment.strip_prefix(':') {
+                if param.is_empty() {
+                    return Err("URL params must have a name".to_string());
+                }
+                Ok(Segment::Param(param.to_string()))
+            } else if segment.contains([':', '*', '{', '}']) {
+                Err(format!("unsupported URL segment `{}`", segment))
+            } else {
+                Ok(Segment::Literal(segment.to_string()))
+            }
+        })
+        .collect()
+}
+
+fn url_format(segments: &[Segment]) -> (String, Vec<TokenStream2>) {
+    let mut format_string = String::new();
+    let mut format_args = Vec::new();
+
+    for segment in segments {
+        format_string.push('/');
+        match segment {
+            Segment::Literal(literal) => format_string.push_str(literal),
+            Segment::Param(param) => {
+                format_string.push_str("{}");
+                let field = Ident::new(param, Span::call_site());
+                format_args.push(quote! {
+                    ::shared::__private::encode_path_segment(&self.#field.to_string())
+                });
+            }
+        }
+    }
+
+    (format_string, format_args)
+}
+
+fn field_names(fields: &Fields) -> syn::Result<Vec<String>> {
+    match fields {
+        Fields::Named(fields) => Ok(fields
+            .named
+            .iter()
+            .filter_map(|field| field.ident.as_ref())
+            .map(|ident| ident.to_string())
+            .collect()),
+        Fields::Unit => Ok(Vec::new()),
+        Fields::Unnamed(fields) => Err(syn::Error::new_spanned(
+            fields,
+            "endpoints must have named fields",
+        )),
+    }
+}
+
+fn method_variant(method: &Ident) -> syn::Result<Ident> {
+    let variant = match method.to_string().as_str() {
+        "GET" => "Get",
+        "POST" => "Post",
+        "PUT" => "Put",
+        "PATCH" => "Patch",
+        "DELETE" => "Delete",
+        "HEAD" => "Head",
+        "OPTIONS" => "Options",
+        _ => {
+            return Err(syn::Error::new(
+                method.span(),
+                "expected one of GET, POST, PUT, PATCH, DELETE, HEAD, or OPTIONS",
+            ))
+        }
+    };
+    Ok(Ident::new(variant, method.span()))
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn parsing_url_specs() {
+        assert_eq!(
+            parse_url_spec("/users/:username/session").unwrap(),
+            vec![
+                Segment::Literal("users".to_string()),
+                Segment::Param("username".to_string()),
+                Segment::Literal("session".to_string()),
+            ]
+        );
+        assert!(parse_url_spec("users").is_err());
+        assert!(parse_url_spec("/users/:").is_err());
+        assert!(parse_url_spec("/files/*path").is_err());
+    }
+
+    #[test]
+    fn url_format_strings() {
+        let segments = parse_url_spec("/users/:username/session").unwrap();
+        let (format_string, format_args) = url_format(&segments);
+        assert_eq!(format_string, "/users/{}/session");
+        assert_eq!(format_args.len(), 1);
+    }
+}
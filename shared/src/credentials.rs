@@ -0,0 +1,190 @@
+//! Rules for usernames and passwords, checked the same way in the frontend and the backend.
+//!
+//! The checks return every problem as a message meant to follow the field name, e.g.
+//! "username is reserved".
+
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 20;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Hashing is slow on purpose, so don't let anyone make us hash megabytes.
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Names that would be confused with pages or the API, or with us.
+const RESERVED_USERNAMES: &[&str] = &[
+    "about",
+    "admin",
+    "administrator",
+    "api",
+    "help",
+    "login",
+    "logout",
+    "me",
+    "oauth",
+    "root",
+    "settings",
+    "sign_up",
+    "signed_in",
+    "signup",
+    "support",
+    "system",
+    "tweets",
+    "users",
+    "witter",
+];
+
+/// Lowercase, one per line. Passwords shorter than `MIN_PASSWORD_LENGTH` are left out since
+/// they're rejected anyway.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+pub fn username_errors(username: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let length = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+        errors.push(format!(
+            "must be between {} and {} characters",
+            MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        ));
+    }
+
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        errors.push("can only contain letters, numbers, and underscores".to_string());
+    }
+
+    if is_reserved(username) {
+        errors.push("is reserved".to_string());
+    }
+
+    errors
+}
+
+pub fn password_errors(password: &str, username: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        errors.push(format!(
+            "must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    } else if length > MAX_PASSWORD_LENGTH {
+        errors.push(format!(
+            "can't be more than {} characters",
+            MAX_PASSWORD_LENGTH
+        ));
+    }
+
+    if password.to_lowercase() == username.to_lowercase() {
+        errors.push("can't be the same as your username".to_string());
+    } else if is_common(password) {
+        errors.push("is too common".to_string());
+    }
+
+    errors
+}
+
+/// Usernames are unique regardless of case, so this is what they're compared by.
+pub fn canonical_username(username: &str) -> String {
+    username.to_lowercase()
+}
+
+fn is_reserved(username: &str) -> bool {
+    RESERVED_USERNAMES.contains(&canonical_username(username).as_str())
+}
+
+fn is_common(password: &str) -> bool {
+    let password = password.to_lowercase();
+    COMMON_PASSWORDS.lines().any(|common| common == password)
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn username_length_limits_are_inclusive() {
+        assert!(username_errors(&"a".repeat(MIN_USERNAME_LENGTH)).is_empty());
+        assert!(username_errors(&"a".repeat(MAX_USERNAME_LENGTH)).is_empty());
+        assert_eq!(
+            username_errors(&"a".repeat(MIN_USERNAME_LENGTH - 1)),
+            vec!["must be between 3 and 20 characters"]
+        );
+        assert_eq!(
+            username_errors(&"a".repeat(MAX_USERNAME_LENGTH + 1)),
+            vec!["must be between 3 and 20 characters"]
+        );
+    }
+
+    #[test]
+    fn usernames_are_ascii_letters_numbers_and_underscores() {
+        assert!(username_errors("bob_42").is_empty());
+        for username in &["bob smith", "bøb", "bob-42", "bob\u{301}"] {
+            assert_eq!(
+                username_errors(username),
+                vec!["can only contain letters, numbers, and underscores"],
+                "{}",
+                username
+            );
+        }
+    }
+
+    #[test]
+    fn reserved_usernames_are_reserved_in_any_case() {
+        for username in &["admin", "Admin", "ADMIN", "Sign_Up"] {
+            assert_eq!(
+                username_errors(username),
+                vec!["is reserved"],
+                "{}",
+                username
+            );
+        }
+        assert!(username_errors("admins").is_empty());
+    }
+
+    #[test]
+    fn password_length_limits_are_inclusive() {
+        assert!(password_errors(&"x".repeat(MIN_PASSWORD_LENGTH), "bob").is_empty());
+        assert!(password_errors(&"x".repeat(MAX_PASSWORD_LENGTH), "bob").is_empty());
+        assert_eq!(
+            password_errors(&"x".repeat(MIN_PASSWORD_LENGTH - 1), "bob"),
+            vec!["must be at least 8 characters"]
+        );
+        assert_eq!(
+            password_errors(&"x".repeat(MAX_PASSWORD_LENGTH + 1), "bob"),
+            vec!["can't be more than 128 characters"]
+        );
+
+        // Counted in characters, not bytes
+        assert!(password_errors(&"ø".repeat(MIN_PASSWORD_LENGTH), "bob").is_empty());
+    }
+
+    #[test]
+    fn common_passwords_are_rejected_in_any_case() {
+        for password in &["password", "Password", "PASSWORD", "iLoveYou"] {
+            assert_eq!(
+                password_errors(password, "bob"),
+                vec!["is too common"],
+                "{}",
+                password
+            );
+        }
+    }
+
+    #[test]
+    fn passwords_cant_be_the_username_in_any_case() {
+        assert_eq!(
+            password_errors("BobSmith", "bobsmith"),
+            vec!["can't be the same as your username"]
+        );
+    }
+
+    #[test]
+    fn usernames_are_compared_lowercase() {
+        assert_eq!(canonical_username("BoB_42"), "bob_42");
+    }
+}
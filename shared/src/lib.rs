@@ -1,140 +1,332 @@
+// Lets the code generated by `#[endpoint]` refer to `::shared` from inside this crate as well
+extern crate self as shared;
+
 use http_types::Method;
-use serde::{de::DeserializeOwned, Serialize};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use shared_derive::endpoint;
 
+pub mod credentials;
 pub mod payloads;
+pub mod queries;
 pub mod responses;
+mod scopes;
+pub mod tweet_text;
+
+pub use scopes::{Scope, UnknownScope};
 
+/// In characters as counted by `tweet_text::length`.
 pub const MAX_TWEET_LENGTH: usize = 140;
 
+/// In bytes. Drafts can go over `MAX_TWEET_LENGTH` while they're being written, but not by much.
+pub const MAX_DRAFT_SIZE: usize = 4096;
+pub const MAX_DRAFTS_PER_USER: usize = 100;
+
+pub const MAX_MEDIA_PER_TWEET: usize = 4;
+
+pub const MIN_POLL_OPTIONS: usize = 2;
+pub const MAX_POLL_OPTIONS: usize = 4;
+/// In characters as counted by `tweet_text::length`.
+pub const MAX_POLL_OPTION_LENGTH: usize = 25;
+pub const MAX_POLL_DURATION_DAYS: i64 = 7;
+
 pub trait Url {
     const URL_SPEC: &'static str;
 
     fn url(&self) -> String;
 }
 
+/// The schemas are used to generate API documentation.
 pub trait ApiEndpoint {
-    type Url: Url;
+    type Url: Url + JsonSchema;
     const METHOD: Method;
-    type Payload;
-    type Response: Serialize + DeserializeOwned;
+    const PAYLOAD_ENCODING: PayloadEncoding;
+    type Payload: Serialize + DeserializeOwned + JsonSchema;
+    /// Parameters sent in the query string.
+    type Query: Serialize + DeserializeOwned + JsonSchema;
+    type Response: Serialize + DeserializeOwned + JsonSchema;
+    /// Sent as `{"error": ...}` when the request fails.
+    type Error: Serialize + DeserializeOwned + JsonSchema;
 }
 
-pub struct NoPayload;
-
-pub struct GetUser;
-
-impl ApiEndpoint for GetUser {
-    type Url = GetUserUrl;
-    const METHOD: Method = Method::Get;
-    type Payload = NoPayload;
-    type Response = responses::UserResponse;
+/// The path and query string for calling an endpoint.
+pub fn request_path<E: ApiEndpoint>(
+    url: &E::Url,
+    query: &E::Query,
+) -> Result<String, serde_urlencoded::ser::Error> {
+    let query = serde_urlencoded::to_string(query)?;
+    if query.is_empty() {
+        Ok(url.url())
+    } else {
+        Ok(format!("{}?{}", url.url(), query))
+    }
 }
 
-pub struct GetUserUrl {
-    pub username: String,
+/// How the payload is sent in the request body.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PayloadEncoding {
+    /// There is no request body.
+    None,
+    Json,
 }
 
-impl Url for GetUserUrl {
-    const URL_SPEC: &'static str = "/users/:username";
+/// The payload of endpoints that leave out `payload` in `#[endpoint]`, since they have no body.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NoPayload;
 
-    fn url(&self) -> String {
-        format!("/users/{}", self.username)
+/// Braces rather than a unit struct so it can be deserialized from an empty query string.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct NoQuery {}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use http_types::Method;
+    pub use schemars::JsonSchema;
+
+    /// Percent-encodes everything except the unreserved characters from RFC 3986 so values
+    /// can't escape their path segment.
+    pub fn encode_path_segment(value: &str) -> String {
+        let mut encoded = String::with_capacity(value.len());
+        for byte in value.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(byte as char)
+                }
+                _ => encoded.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        encoded
     }
 }
 
+#[endpoint(GET, "/users/:username", response = responses::UserResponse)]
+pub struct GetUser {
+    pub username: String,
+}
+
+#[endpoint(
+    POST,
+    "/tweets",
+    payload = payloads::CreateTweetPayload,
+    response = responses::PostTweetResponse
+)]
 pub struct PostTweet;
 
-impl ApiEndpoint for PostTweet {
-    type Url = PostTweetUrl;
-    const METHOD: Method = Method::Post;
-    type Payload = payloads::CreateTweetPayload;
-    type Response = responses::PostTweetResponse;
-}
+#[endpoint(GET, "/me", response = responses::UserResponse)]
+pub struct Me;
 
-pub struct PostTweetUrl;
+#[endpoint(
+    POST,
+    "/users/:username/session",
+    payload = payloads::LoginPayload,
+    response = responses::LoginResponse
+)]
+pub struct Login {
+    pub username: String,
+}
 
-impl Url for PostTweetUrl {
-    const URL_SPEC: &'static str = "/tweets";
+#[endpoint(DELETE, "/users/:username/session", response = ())]
+pub struct Logout {
+    pub username: String,
+}
 
-    fn url(&self) -> String {
-        format!("/tweets")
-    }
+#[endpoint(
+    POST,
+    "/users/:username/session/2fa",
+    payload = payloads::SecondFactorPayload,
+    response = responses::TokenResponse
+)]
+pub struct CompleteTwoFactorLogin {
+    pub username: String,
 }
 
-pub struct Me;
+#[endpoint(POST, "/me/2fa", response = responses::TwoFactorEnrollmentResponse)]
+pub struct EnrollTwoFactor;
+
+#[endpoint(
+    POST,
+    "/me/2fa/confirmation",
+    payload = payloads::TwoFactorCodePayload,
+    response = responses::RecoveryCodesResponse
+)]
+pub struct ConfirmTwoFactor;
+
+#[endpoint(DELETE, "/me/2fa", payload = payloads::TwoFactorCodePayload, response = ())]
+pub struct DisableTwoFactor;
+
+#[endpoint(
+    POST,
+    "/users",
+    payload = payloads::CreateUserPayload,
+    response = responses::TokenResponse
+)]
+pub struct CreateUser;
+
+/// Only the author can edit a tweet, and only for a while after posting it.
+#[endpoint(
+    PATCH,
+    "/tweets/:id",
+    payload = payloads::EditTweetPayload,
+    response = responses::TweetResponse
+)]
+pub struct EditTweet {
+    pub id: uuid::Uuid,
+}
 
-impl ApiEndpoint for Me {
-    type Url = MeUrl;
-    const METHOD: Method = Method::Get;
-    type Payload = NoPayload;
-    type Response = responses::UserResponse;
+/// Every version of the tweet, oldest first. The last one is the current text.
+#[endpoint(GET, "/tweets/:id/history", response = Vec<responses::TweetRevisionResponse>)]
+pub struct TweetHistory {
+    pub id: uuid::Uuid,
 }
 
-pub struct MeUrl;
+/// Each user can vote once, while the poll is open. Responds with the tweet, which now shows the
+/// vote counts.
+#[endpoint(
+    POST,
+    "/tweets/:id/poll/votes",
+    payload = payloads::VotePayload,
+    response = responses::TweetResponse
+)]
+pub struct VoteInPoll {
+    pub id: uuid::Uuid,
+}
 
-impl Url for MeUrl {
-    const URL_SPEC: &'static str = "/me";
+#[endpoint(POST, "/tweets/:id/bookmark", response = ())]
+pub struct BookmarkTweet {
+    pub id: uuid::Uuid,
+}
 
-    fn url(&self) -> String {
-        format!("/me")
-    }
+#[endpoint(DELETE, "/tweets/:id/bookmark", response = ())]
+pub struct RemoveBookmark {
+    pub id: uuid::Uuid,
 }
 
-pub struct Login;
+/// Only ever your own bookmarks, most recently bookmarked first.
+#[endpoint(
+    GET,
+    "/me/bookmarks",
+    query = queries::CursorPagination,
+    response = responses::CursorPage<responses::TweetResponse>
+)]
+pub struct ListBookmarks;
+
+#[endpoint(
+    GET,
+    "/me/timeline",
+    query = queries::Pagination,
+    response = Vec<responses::TweetResponse>
+)]
+pub struct Timeline;
 
-impl ApiEndpoint for Login {
-    type Url = LoginUrl;
-    const METHOD: Method = Method::Post;
-    type Payload = payloads::LoginPayload;
-    type Response = responses::TokenResponse;
+#[endpoint(GET, "/me/scheduled", response = Vec<responses::ScheduledTweetResponse>)]
+pub struct ListScheduledTweets;
+
+#[endpoint(
+    PATCH,
+    "/me/scheduled/:id",
+    payload = payloads::UpdateScheduledTweetPayload,
+    response = responses::ScheduledTweetResponse
+)]
+pub struct UpdateScheduledTweet {
+    pub id: uuid::Uuid,
 }
 
-pub struct LoginUrl {
-    pub username: String,
+#[endpoint(DELETE, "/me/scheduled/:id", response = ())]
+pub struct CancelScheduledTweet {
+    pub id: uuid::Uuid,
 }
 
-impl Url for LoginUrl {
-    const URL_SPEC: &'static str = "/users/:username/session";
+#[endpoint(
+    POST,
+    "/me/drafts",
+    payload = payloads::DraftPayload,
+    response = responses::DraftResponse
+)]
+pub struct CreateDraft;
+
+/// Most recently updated first.
+#[endpoint(GET, "/me/drafts", response = Vec<responses::DraftResponse>)]
+pub struct ListDrafts;
+
+#[endpoint(GET, "/me/drafts/:id", response = responses::DraftResponse)]
+pub struct GetDraft {
+    pub id: uuid::Uuid,
+}
 
-    fn url(&self) -> String {
-        format!("/users/{}/session", self.username)
-    }
+#[endpoint(
+    PUT,
+    "/me/drafts/:id",
+    payload = payloads::DraftPayload,
+    response = responses::DraftResponse
+)]
+pub struct UpdateDraft {
+    pub id: uuid::Uuid,
 }
 
-pub struct CreateUser;
+#[endpoint(DELETE, "/me/drafts/:id", response = ())]
+pub struct DeleteDraft {
+    pub id: uuid::Uuid,
+}
 
-impl ApiEndpoint for CreateUser {
-    type Url = CreateUserUrl;
-    const METHOD: Method = Method::Post;
-    type Payload = payloads::CreateUserPayload;
-    type Response = responses::TokenResponse;
+/// Posts the draft as a tweet and deletes it.
+#[endpoint(POST, "/me/drafts/:id/publish", response = responses::PostTweetResponse)]
+pub struct PublishDraft {
+    pub id: uuid::Uuid,
 }
 
-pub struct CreateUserUrl;
+#[endpoint(
+    POST,
+    "/me/api_tokens",
+    payload = payloads::CreateApiTokenPayload,
+    response = responses::CreatedApiTokenResponse
+)]
+pub struct CreateApiToken;
 
-impl Url for CreateUserUrl {
-    const URL_SPEC: &'static str = "/users";
+#[endpoint(GET, "/me/api_tokens", response = Vec<responses::ApiTokenResponse>)]
+pub struct ListApiTokens;
 
-    fn url(&self) -> String {
-        format!("/users")
-    }
+#[endpoint(DELETE, "/me/api_tokens/:id", response = ())]
+pub struct RevokeApiToken {
+    pub id: uuid::Uuid,
 }
 
-pub struct Timeline;
+#[endpoint(
+    POST,
+    "/oauth/clients",
+    payload = payloads::RegisterOAuthClientPayload,
+    response = responses::RegisteredOAuthClientResponse
+)]
+pub struct RegisterOAuthClient;
+
+#[endpoint(GET, "/oauth/clients/:client_id", response = responses::OAuthClientResponse)]
+pub struct GetOAuthClient {
+    pub client_id: uuid::Uuid,
+}
 
-impl ApiEndpoint for Timeline {
-    type Url = TimelineUrl;
-    const METHOD: Method = Method::Get;
-    type Payload = NoPayload;
-    type Response = Vec<responses::TweetResponse>;
+#[endpoint(
+    POST,
+    "/oauth/authorizations",
+    payload = payloads::AuthorizeOAuthClientPayload,
+    response = responses::OAuthAuthorizationResponse
+)]
+pub struct AuthorizeOAuthClient;
+
+#[endpoint(POST, "/users/:username/follow", response = ())]
+pub struct Follow {
+    pub username: String,
 }
 
-pub struct TimelineUrl;
+#[endpoint(DELETE, "/users/:username/follow", response = ())]
+pub struct Unfollow {
+    pub username: String,
+}
 
-impl Url for TimelineUrl {
-    const URL_SPEC: &'static str = "/me/timeline";
+#[endpoint(GET, "/users/:username/following", response = Vec<responses::UserResponse>)]
+pub struct Following {
+    pub username: String,
+}
 
-    fn url(&self) -> String {
-        format!("/me/timeline")
-    }
+#[endpoint(GET, "/users/:username/followers", response = Vec<responses::UserResponse>)]
+pub struct Followers {
+    pub username: String,
 }
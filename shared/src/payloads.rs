@@ -1,17 +1,104 @@
+use crate::Scope;
+use chrono::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct CreateUserPayload {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct LoginPayload {
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct CreateTweetPayload {
     pub text: String,
+    /// Schedules the tweet instead of publishing it straight away. Must be in the future.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime<Utc>>,
+    /// Ids from uploading to `POST /media`, at most `MAX_MEDIA_PER_TWEET`. Each can only be
+    /// attached to one tweet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media_ids: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<CreatePollPayload>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CreatePollPayload {
+    /// Between `MIN_POLL_OPTIONS` and `MAX_POLL_OPTIONS` of them, in the order they're shown.
+    pub options: Vec<String>,
+    /// Must be in the future, and at most `MAX_POLL_DURATION_DAYS` away.
+    pub closes_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct VotePayload {
+    /// The option's position in `PollResponse::options`.
+    pub option: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct EditTweetPayload {
+    pub text: String,
+}
+
+/// Fields that are left out aren't changed.
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct UpdateScheduledTweetPayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+/// Drafts aren't checked until they're published, so they can be saved while they're too long.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DraftPayload {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TwoFactorCodePayload {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SecondFactorPayload {
+    pub challenge_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CreateApiTokenPayload {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RegisterOAuthClientPayload {
+    pub name: String,
+    /// Must use `https`, or `http` on `127.0.0.1`, `[::1]` or `localhost`.
+    pub redirect_uris: Vec<String>,
+    /// Confidential clients get a client secret which they must send when exchanging codes for
+    /// tokens. Apps that run in the browser or on users' devices can't keep a secret.
+    pub confidential: bool,
+}
+
+/// The parameters from an OAuth authorization request, sent when the user approves or denies it.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct AuthorizeOAuthClientPayload {
+    pub approved: bool,
+    pub client_id: Uuid,
+    pub redirect_uri: String,
+    /// Space separated list of scopes.
+    pub scope: String,
+    pub state: Option<String>,
+    pub code_challenge: String,
+    pub code_challenge_method: String,
 }
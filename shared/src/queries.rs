@@ -0,0 +1,16 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct Pagination {
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+}
+
+/// For lists that change while they're being read, so pages don't skip or repeat items.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct CursorPagination {
+    /// The `next_cursor` of the previous page. Leave it out for the first page.
+    pub cursor: Option<String>,
+    pub page_size: Option<usize>,
+}
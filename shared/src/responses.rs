@@ -1,8 +1,11 @@
+use crate::Scope;
 use chrono::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ApiResponse<T> {
     pub data: T,
 }
@@ -13,7 +16,141 @@ impl<T> ApiResponse<T> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A page of a list paginated with `queries::CursorPagination`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    /// `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// The body of all error responses.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ApiErrorResponse<E = ApiError> {
+    pub error: E,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ApiError {
+    pub status_code: String,
+    pub code: ErrorCode,
+    pub message: String,
+    /// Which fields of the payload were invalid, if any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub field_errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            status_code: code.status().to_string(),
+            code,
+            message: message.into(),
+            field_errors: Vec::new(),
+        }
+    }
+
+    /// For errors that don't have a more specific code.
+    pub fn from_status(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status_code: status.to_string(),
+            code: ErrorCode::from_status(status),
+            message: message.into(),
+            field_errors: Vec::new(),
+        }
+    }
+
+    pub fn with_field_error(mut self, field: &str, message: impl Into<String>) -> Self {
+        self.field_errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status_code
+            .parse()
+            .unwrap_or_else(|_| self.code.status())
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    InvalidToken,
+    InvalidCredentials,
+    InvalidTwoFactorCode,
+    Forbidden,
+    MissingScope,
+    NotFound,
+    Conflict,
+    ValidationFailed,
+    UsernameTaken,
+    TweetTooLong,
+    MediaTooLarge,
+    UnsupportedMediaType,
+    TooManyLoginAttempts,
+    RateLimited,
+    InternalError,
+    /// A code this version of the client doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> u16 {
+        match self {
+            ErrorCode::BadRequest => 400,
+            ErrorCode::Unauthorized | ErrorCode::InvalidToken => 401,
+            ErrorCode::InvalidCredentials
+            | ErrorCode::InvalidTwoFactorCode
+            | ErrorCode::Forbidden
+            | ErrorCode::MissingScope => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::Conflict => 409,
+            ErrorCode::MediaTooLarge => 413,
+            ErrorCode::UnsupportedMediaType => 415,
+            ErrorCode::ValidationFailed | ErrorCode::UsernameTaken | ErrorCode::TweetTooLong => 422,
+            ErrorCode::TooManyLoginAttempts | ErrorCode::RateLimited => 429,
+            ErrorCode::InternalError | ErrorCode::Unknown => 500,
+        }
+    }
+
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            400 => ErrorCode::BadRequest,
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            404 => ErrorCode::NotFound,
+            409 => ErrorCode::Conflict,
+            413 => ErrorCode::MediaTooLarge,
+            415 => ErrorCode::UnsupportedMediaType,
+            422 => ErrorCode::ValidationFailed,
+            429 => ErrorCode::RateLimited,
+            500..=599 => ErrorCode::InternalError,
+            _ => ErrorCode::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct TokenResponse {
     pub token: String,
 }
@@ -26,22 +163,157 @@ impl TokenResponse {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LoginResponse {
+    Token(TokenResponse),
+    SecondFactorRequired(SecondFactorChallengeResponse),
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SecondFactorChallengeResponse {
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TwoFactorEnrollmentResponse {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct UserResponse {
     pub id: Uuid,
     pub username: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct TweetResponse {
     pub id: Uuid,
     pub text: String,
     pub created_at: DateTime<Utc>,
     pub user: UserResponse,
+    pub edited: bool,
+    pub edit_count: u32,
+    /// In the order they were attached.
+    pub media: Vec<MediaResponse>,
+    pub poll: Option<PollResponse>,
+    /// Bookmarks are private, so this is only ever about the viewer's own.
+    pub bookmarked_by_me: bool,
+}
+
+/// Vote counts are left out until the viewer has voted or the poll has closed, so they can't
+/// sway the vote.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PollResponse {
+    pub options: Vec<PollOptionResponse>,
+    pub closes_at: DateTime<Utc>,
+    pub closed: bool,
+    /// The position of the option the viewer voted for.
+    pub voted_for: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PollOptionResponse {
+    pub text: String,
+    pub votes: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// An uploaded image. The URLs are relative to the API.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MediaResponse {
+    pub id: Uuid,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub url: String,
+    pub thumbnail_url: String,
+}
+
+impl MediaResponse {
+    pub fn new(id: Uuid, content_type: &str, width: u32, height: u32) -> Self {
+        Self {
+            id,
+            content_type: content_type.to_string(),
+            width,
+            height,
+            url: format!("/media/{}", id),
+            thumbnail_url: format!("/media/{}/thumbnail", id),
+        }
+    }
+}
+
+/// A version of a tweet, from before or after an edit.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TweetRevisionResponse {
+    pub text: String,
+    /// When this version was posted.
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct PostTweetResponse {
     pub id: Uuid,
     pub text: String,
+    /// Set if the tweet was scheduled. It keeps its id once it's published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ScheduledTweetResponse {
+    pub id: Uuid,
+    pub text: String,
+    pub publish_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct DraftResponse {
+    pub id: Uuid,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ApiTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Only returned when the token is created. The token itself can't be retrieved later.
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct CreatedApiTokenResponse {
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiTokenResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct OAuthClientResponse {
+    pub client_id: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct RegisteredOAuthClientResponse {
+    pub client_id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct OAuthAuthorizationResponse {
+    /// Where to send the user back to, including the authorization code.
+    pub redirect_to: String,
 }
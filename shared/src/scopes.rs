@@ -0,0 +1,86 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What an API token is allowed to do. Tokens from logging in with a password aren't limited to
+/// any scopes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum Scope {
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+    #[serde(rename = "timeline:read")]
+    TimelineRead,
+    #[serde(rename = "tweets:write")]
+    TweetsWrite,
+    #[serde(rename = "follows:write")]
+    FollowsWrite,
+    #[serde(rename = "bookmarks:read")]
+    BookmarksRead,
+    #[serde(rename = "bookmarks:write")]
+    BookmarksWrite,
+}
+
+impl Scope {
+    pub const ALL: &'static [Scope] = &[
+        Scope::ProfileRead,
+        Scope::TimelineRead,
+        Scope::TweetsWrite,
+        Scope::FollowsWrite,
+        Scope::BookmarksRead,
+        Scope::BookmarksWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "profile:read",
+            Scope::TimelineRead => "timeline:read",
+            Scope::TweetsWrite => "tweets:write",
+            Scope::FollowsWrite => "follows:write",
+            Scope::BookmarksRead => "bookmarks:read",
+            Scope::BookmarksWrite => "bookmarks:write",
+        }
+    }
+
+    /// Parses a space separated list of scopes, as used in the database and by OAuth.
+    pub fn parse_list(scopes: &str) -> Result<Vec<Scope>, UnknownScope> {
+        scopes.split_whitespace().map(str::parse).collect()
+    }
+
+    pub fn format_list(scopes: &[Scope]) -> String {
+        scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownScope(pub String);
+
+impl fmt::Display for UnknownScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown scope `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownScope {}
+
+impl FromStr for Scope {
+    type Err = UnknownScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .iter()
+            .find(|scope| scope.as_str() == s)
+            .copied()
+            .ok_or_else(|| UnknownScope(s.to_string()))
+    }
+}
@@ -0,0 +1,148 @@
+//! Checks tweet text the same way in the frontend and the backend.
+
+use crate::MAX_TWEET_LENGTH;
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// What a link counts as, however long it is.
+pub const URL_LENGTH: usize = 23;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InvalidTweetText {
+    /// Empty or only whitespace.
+    Blank,
+    TooLong {
+        length: usize,
+    },
+}
+
+impl fmt::Display for InvalidTweetText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidTweetText::Blank => write!(f, "Tweet can't be blank"),
+            InvalidTweetText::TooLong { length } => write!(
+                f,
+                "Tweet is {} characters long. Max is {}",
+                length, MAX_TWEET_LENGTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidTweetText {}
+
+/// Returns the text in NFC, which is how it should be stored.
+pub fn validate(text: &str) -> Result<String, InvalidTweetText> {
+    let text = normalize(text);
+    if text.trim().is_empty() {
+        return Err(InvalidTweetText::Blank);
+    }
+
+    let length = length(&text);
+    if length > MAX_TWEET_LENGTH {
+        return Err(InvalidTweetText::TooLong { length });
+    }
+
+    Ok(text)
+}
+
+/// Composes characters where possible, so `e` followed by a combining accent becomes `é`.
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Counts what users see as characters, so an emoji or `ø` is one no matter how many bytes or
+/// code points it takes. Links count as `URL_LENGTH`.
+pub fn length(text: &str) -> usize {
+    let graphemes = text.graphemes(true).collect::<Vec<_>>();
+    let spaces = graphemes
+        .iter()
+        .filter(|grapheme| is_space(grapheme))
+        .count();
+    let words = graphemes
+        .split(|grapheme| is_space(grapheme))
+        .map(|word| {
+            if is_url(&word.concat()) {
+                URL_LENGTH
+            } else {
+                word.len()
+            }
+        })
+        .sum::<usize>();
+    spaces + words
+}
+
+fn is_space(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+fn is_url(word: &str) -> bool {
+    ["http://", "https://"]
+        .iter()
+        .any(|scheme| word.len() > scheme.len() && word.starts_with(scheme))
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn combining_marks_are_part_of_their_character() {
+        assert_eq!(length("e\u{301}"), 1);
+        assert_eq!(length("a\u{300}\u{301}\u{302}"), 1);
+        assert_eq!(length("blåbærgrød"), 10);
+    }
+
+    #[test]
+    fn emoji_sequences_are_one_character() {
+        assert_eq!(length("👍🏽"), 1);
+        assert_eq!(length("👨\u{200d}👩\u{200d}👧\u{200d}👦"), 1);
+        assert_eq!(length("🇩🇰"), 1);
+        assert_eq!(length("🇩🇰🇸🇪"), 2);
+    }
+
+    #[test]
+    fn line_breaks_are_one_character() {
+        assert_eq!(length("a\r\nb"), 3);
+        assert_eq!(length("a\nb"), 3);
+    }
+
+    #[test]
+    fn links_count_as_url_length() {
+        let link = format!("https://example.com/{}", "a".repeat(100));
+        assert_eq!(length(&link), URL_LENGTH);
+        assert_eq!(length(&format!("see {} now", link)), 4 + URL_LENGTH + 4);
+        assert_eq!(length("https://"), 8);
+        assert_eq!(length("ftp://example.com"), 17);
+    }
+
+    #[test]
+    fn max_length_is_inclusive() {
+        assert!(validate(&"a".repeat(MAX_TWEET_LENGTH)).is_ok());
+        assert_eq!(
+            validate(&"a".repeat(MAX_TWEET_LENGTH + 1)),
+            Err(InvalidTweetText::TooLong {
+                length: MAX_TWEET_LENGTH + 1
+            })
+        );
+
+        // Counted in characters, not bytes
+        assert!(validate(&"👨\u{200d}👩\u{200d}👧".repeat(MAX_TWEET_LENGTH)).is_ok());
+        assert!(validate(&"e\u{301}".repeat(MAX_TWEET_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn blank_text_is_invalid() {
+        assert_eq!(validate(""), Err(InvalidTweetText::Blank));
+        assert_eq!(validate(" \t\r\n"), Err(InvalidTweetText::Blank));
+        assert_eq!(validate("\u{3000}"), Err(InvalidTweetText::Blank));
+    }
+
+    #[test]
+    fn text_is_returned_in_nfc() {
+        assert_eq!(validate("cafe\u{301}"), Ok("café".to_string()));
+        assert_eq!(normalize("café"), "café");
+    }
+}
@@ -0,0 +1,272 @@
+//! The `witter` command line client.
+//!
+//! Everything except building the HTTP backend lives here so the commands can be run against a
+//! server in-process.
+
+use serde::Serialize;
+use shared::payloads::{CreateTweetPayload, LoginPayload, SecondFactorPayload};
+use shared::queries::Pagination;
+use shared::responses::{LoginResponse, TweetResponse};
+use shared::*;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+use thiserror::Error;
+use witter_client::{Client, HttpBackend};
+
+mod config;
+
+pub use config::Config;
+
+pub const DEFAULT_SERVER: &str = "http://localhost:8080";
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "witter")]
+pub struct Cli {
+    /// Print JSON instead of text, one value per line
+    #[structopt(long, global = true)]
+    pub json: bool,
+
+    /// URL of the server. Defaults to the one you last logged in to
+    #[structopt(long, global = true)]
+    pub server: Option<String>,
+
+    /// Where to keep credentials. Defaults to `witter/config.json` in your config directory
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Log in and save the token. The password is read from stdin
+    Login { username: String },
+    /// Post a tweet
+    Post { text: String },
+    /// Show your timeline
+    Timeline {
+        /// Keep checking for new tweets
+        #[structopt(long)]
+        follow: bool,
+        /// Seconds between checks with `--follow`
+        #[structopt(long, default_value = "10")]
+        interval: u64,
+    },
+    /// Follow a user
+    Follow { username: String },
+    /// Unfollow a user
+    Unfollow { username: String },
+    /// Show a user's profile
+    Whois { username: String },
+    /// Search tweets
+    Search { query: String },
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("{0}")]
+    Client(#[from] witter_client::Error),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("invalid config file: {0}")]
+    Config(#[from] serde_json::Error),
+    #[error("couldn't find a config directory, use --config")]
+    NoConfigDir,
+    #[error("not logged in, run `witter login <username>` first")]
+    NotLoggedIn,
+    #[error("the server doesn't support search yet")]
+    SearchUnsupported,
+}
+
+pub async fn run<B: HttpBackend>(
+    cli: &Cli,
+    backend: B,
+    config: &mut Config,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<(), CliError> {
+    let server = cli
+        .server
+        .clone()
+        .or_else(|| config.server.clone())
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+    let mut client = Client::new(backend, &server)?;
+    client.set_auth_token(config.token.clone());
+
+    let mut out = Output {
+        json: cli.json,
+        writer: output,
+    };
+
+    match &cli.command {
+        Command::Login { username } => {
+            let token = login(&client, username, input).await?;
+            config.server = Some(server);
+            config.username = Some(username.clone());
+            config.token = Some(token);
+
+            out.print(&serde_json::json!({ "username": username }), |w| {
+                writeln!(w, "Logged in as @{}", username)
+            })?;
+        }
+
+        Command::Post { text } => {
+            require_login(config)?;
+            let payload = CreateTweetPayload {
+                text: text.clone(),
+                publish_at: None,
+                media_ids: Vec::new(),
+                poll: None,
+            };
+            let tweet = client
+                .call::<PostTweet>(&PostTweetUrl, &NoQuery {}, &payload)
+                .await?;
+            out.print(&tweet, |w| writeln!(w, "Posted {}", tweet.id))?;
+        }
+
+        Command::Timeline { follow, interval } => {
+            require_login(config)?;
+            let mut seen = HashSet::new();
+            loop {
+                let tweets = client
+                    .call::<Timeline>(&TimelineUrl, &Pagination::default(), &NoPayload)
+                    .await?;
+
+                // The newest tweets come first but it reads better the other way around
+                for tweet in tweets.iter().rev() {
+                    if seen.insert(tweet.id) {
+                        out.print(tweet, |w| print_tweet(w, tweet))?;
+                    }
+                }
+
+                if !follow {
+                    break;
+                }
+                out.writer.flush()?;
+                async_std::task::sleep(Duration::from_secs(*interval)).await;
+            }
+        }
+
+        Command::Follow { username } => {
+            require_login(config)?;
+            let url = FollowUrl {
+                username: username.clone(),
+            };
+            client.call::<Follow>(&url, &NoQuery {}, &NoPayload).await?;
+            out.print(&serde_json::json!({ "following": username }), |w| {
+                writeln!(w, "Following @{}", username)
+            })?;
+        }
+
+        Command::Unfollow { username } => {
+            require_login(config)?;
+            let url = UnfollowUrl {
+                username: username.clone(),
+            };
+            client
+                .call::<Unfollow>(&url, &NoQuery {}, &NoPayload)
+                .await?;
+            out.print(&serde_json::json!({ "unfollowed": username }), |w| {
+                writeln!(w, "Unfollowed @{}", username)
+            })?;
+        }
+
+        Command::Whois { username } => {
+            let url = GetUserUrl {
+                username: username.clone(),
+            };
+            let user = client
+                .call::<GetUser>(&url, &NoQuery {}, &NoPayload)
+                .await?;
+            out.print(&user, |w| writeln!(w, "@{} ({})", user.username, user.id))?;
+        }
+
+        Command::Search { .. } => return Err(CliError::SearchUnsupported),
+    }
+
+    Ok(())
+}
+
+async fn login<B: HttpBackend>(
+    client: &Client<B>,
+    username: &str,
+    input: &mut dyn BufRead,
+) -> Result<String, CliError> {
+    let password = prompt("Password: ", input)?;
+    let url = LoginUrl {
+        username: username.to_string(),
+    };
+    let resp = client
+        .call::<Login>(&url, &NoQuery {}, &LoginPayload { password })
+        .await?;
+
+    match resp {
+        LoginResponse::Token(resp) => Ok(resp.token),
+        LoginResponse::SecondFactorRequired(challenge) => {
+            let code = prompt("Two-factor code: ", input)?;
+            let url = CompleteTwoFactorLoginUrl {
+                username: username.to_string(),
+            };
+            let payload = SecondFactorPayload {
+                challenge_token: challenge.challenge_token,
+                code,
+            };
+            let resp = client
+                .call::<CompleteTwoFactorLogin>(&url, &NoQuery {}, &payload)
+                .await?;
+            Ok(resp.token)
+        }
+    }
+}
+
+fn require_login(config: &Config) -> Result<(), CliError> {
+    if config.token.is_some() {
+        Ok(())
+    } else {
+        Err(CliError::NotLoggedIn)
+    }
+}
+
+/// Prompts go to stderr so they don't end up in piped output.
+fn prompt(message: &str, input: &mut dyn BufRead) -> io::Result<String> {
+    eprint!("{}", message);
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+fn print_tweet(w: &mut dyn Write, tweet: &TweetResponse) -> io::Result<()> {
+    writeln!(
+        w,
+        "{} @{}: {}{}",
+        tweet.created_at.format("%Y-%m-%d %H:%M"),
+        tweet.user.username,
+        tweet.text,
+        if tweet.edited { " (edited)" } else { "" }
+    )
+}
+
+struct Output<'a> {
+    json: bool,
+    writer: &'a mut dyn Write,
+}
+
+impl Output<'_> {
+    fn print<T: Serialize>(
+        &mut self,
+        value: &T,
+        text: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> Result<(), CliError> {
+        if self.json {
+            serde_json::to_writer(&mut *self.writer, value)?;
+            writeln!(self.writer)?;
+        } else {
+            text(self.writer)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::io;
+use structopt::StructOpt;
+use witter_cli::{run, Cli, CliError, Config};
+
+#[async_std::main]
+async fn main() {
+    let cli = Cli::from_args();
+
+    if let Err(err) = try_main(cli).await {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn try_main(cli: Cli) -> Result<(), CliError> {
+    let path = cli
+        .config
+        .clone()
+        .or_else(Config::default_path)
+        .ok_or(CliError::NoConfigDir)?;
+    let mut config = Config::load(&path)?;
+    let loaded = config.clone();
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    run(
+        &cli,
+        surf::Client::new(),
+        &mut config,
+        &mut stdin.lock(),
+        &mut stdout.lock(),
+    )
+    .await?;
+
+    if config != loaded {
+        config.save(&path)?;
+    }
+    Ok(())
+}
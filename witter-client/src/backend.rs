@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use http_types::{Request, Response};
+
+/// Sends requests on behalf of a `Client`. Implemented for `surf::Client` with the `surf`
+/// feature. Tests can implement it to call a server in-process.
+#[async_trait]
+pub trait HttpBackend: Send + Sync {
+    async fn send(&self, req: Request) -> http_types::Result<Response>;
+}
+
+#[async_trait]
+impl<B: HttpBackend + ?Sized> HttpBackend for &B {
+    async fn send(&self, req: Request) -> http_types::Result<Response> {
+        (**self).send(req).await
+    }
+}
+
+#[async_trait]
+impl<B: HttpBackend + ?Sized> HttpBackend for std::sync::Arc<B> {
+    async fn send(&self, req: Request) -> http_types::Result<Response> {
+        (**self).send(req).await
+    }
+}
+
+#[cfg(feature = "surf")]
+#[async_trait]
+impl HttpBackend for surf::Client {
+    async fn send(&self, req: Request) -> http_types::Result<Response> {
+        let resp = surf::Client::send(self, req).await?;
+        Ok(resp.into())
+    }
+}
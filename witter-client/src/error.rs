@@ -0,0 +1,38 @@
+use http_types::StatusCode;
+use shared::responses::ApiError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The API responded with an error.
+    #[error("{0}")]
+    Api(ApiError),
+    /// An error status without an API error body.
+    #[error("request failed with status {0}")]
+    Status(StatusCode),
+    #[error("HTTP error: {0}")]
+    Http(http_types::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid URL: {0}")]
+    Url(#[from] http_types::url::ParseError),
+    #[error("invalid query string: {0}")]
+    Query(#[from] serde_urlencoded::ser::Error),
+}
+
+impl Error {
+    /// The error the API responded with, if any.
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// `http_types::Error` doesn't implement `std::error::Error` so `#[from]` can't be used
+impl From<http_types::Error> for Error {
+    fn from(err: http_types::Error) -> Self {
+        Error::Http(err)
+    }
+}
@@ -0,0 +1,156 @@
+//! Typed client for the Witter API built on the endpoints in `shared`.
+//!
+//! ```ignore
+//! let client = Client::new(surf::Client::new(), "http://localhost:8080")?
+//!     .with_auth_token(token);
+//!
+//! let me = client.call::<Me>(&MeUrl, &NoQuery {}, &NoPayload).await?;
+//!
+//! let mut pages = client.pages::<Timeline, _>(TimelineUrl);
+//! while let Some(tweets) = pages.next_page().await {
+//!     for tweet in tweets? {
+//!         println!("{}: {}", tweet.user.username, tweet.text);
+//!     }
+//! }
+//! ```
+
+use http_types::headers::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use http_types::{Request, Response};
+use shared::queries::Pagination;
+use shared::responses::{ApiError, ApiErrorResponse, ApiResponse};
+use shared::{ApiEndpoint, NoPayload, PayloadEncoding};
+use std::time::Duration;
+
+mod backend;
+mod error;
+mod pages;
+mod retry;
+
+pub use backend::HttpBackend;
+pub use error::Error;
+pub use pages::Pages;
+pub use retry::RetryPolicy;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone)]
+pub struct Client<B> {
+    backend: B,
+    base_url: http_types::Url,
+    auth_token: Option<String>,
+    retry_policy: RetryPolicy,
+}
+
+impl<B: HttpBackend> Client<B> {
+    pub fn new(backend: B, base_url: &str) -> Result<Self> {
+        Ok(Client {
+            backend,
+            base_url: http_types::Url::parse(base_url)?,
+            auth_token: None,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// Sent as a bearer token with every request. Both session and API tokens work.
+    pub fn with_auth_token(mut self, auth_token: impl Into<String>) -> Self {
+        self.auth_token = Some(auth_token.into());
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn set_auth_token(&mut self, auth_token: Option<String>) {
+        self.auth_token = auth_token;
+    }
+
+    pub fn auth_token(&self) -> Option<&str> {
+        self.auth_token.as_deref()
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub async fn call<E>(
+        &self,
+        url: &E::Url,
+        query: &E::Query,
+        payload: &E::Payload,
+    ) -> Result<E::Response>
+    where
+        E: ApiEndpoint,
+        E::Error: Into<ApiError>,
+    {
+        let path = shared::request_path::<E>(url, query)?;
+        let url = self.base_url.join(&path)?;
+        let body = match E::PAYLOAD_ENCODING {
+            PayloadEncoding::None => None,
+            PayloadEncoding::Json => Some(serde_json::to_vec(payload)?),
+        };
+
+        let mut attempt = 0;
+        loop {
+            let mut req = Request::new(E::METHOD, url.clone());
+            if let Some(auth_token) = &self.auth_token {
+                req.insert_header(AUTHORIZATION, format!("Bearer {}", auth_token));
+            }
+            if let Some(body) = &body {
+                req.insert_header(CONTENT_TYPE, "application/json");
+                req.set_body(body.clone());
+            }
+
+            let result = self.backend.send(req).await;
+
+            let retry_after = match &result {
+                Ok(resp) => retry::should_retry(resp.status()).then(|| retry_after(resp)),
+                Err(_) => Some(None),
+            };
+            if let Some(retry_after) = retry_after {
+                if let Some(delay) = self.retry_policy.delay(E::METHOD, attempt, retry_after) {
+                    async_std::task::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+            }
+
+            return parse_response::<E>(result?).await;
+        }
+    }
+
+    /// Iterates over the pages of an endpoint that takes `Pagination`.
+    pub fn pages<E, T>(&self, url: E::Url) -> Pages<'_, B, E>
+    where
+        E: ApiEndpoint<Payload = NoPayload, Query = Pagination, Response = Vec<T>>,
+        E::Error: Into<ApiError>,
+    {
+        Pages::new(self, url)
+    }
+}
+
+async fn parse_response<E>(mut resp: Response) -> Result<E::Response>
+where
+    E: ApiEndpoint,
+    E::Error: Into<ApiError>,
+{
+    let status = resp.status();
+    let body = resp.body_string().await?;
+
+    if status.is_success() {
+        let body = serde_json::from_str::<ApiResponse<E::Response>>(&body)?;
+        Ok(body.data)
+    } else {
+        // Errors that didn't come from our backend, e.g. from a proxy, won't have an error body
+        match serde_json::from_str::<ApiErrorResponse<E::Error>>(&body) {
+            Ok(body) => Err(Error::Api(body.error.into())),
+            Err(_) => Err(Error::Status(status)),
+        }
+    }
+}
+
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let seconds = resp.header(RETRY_AFTER)?.last().as_str().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
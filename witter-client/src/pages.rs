@@ -0,0 +1,73 @@
+use crate::{Client, HttpBackend, Result};
+use shared::queries::Pagination;
+use shared::responses::ApiError;
+use shared::{ApiEndpoint, NoPayload};
+
+/// Fetches one page at a time until the API returns an empty page.
+pub struct Pages<'a, B, E: ApiEndpoint> {
+    client: &'a Client<B>,
+    url: E::Url,
+    next_page: usize,
+    page_size: Option<usize>,
+    done: bool,
+}
+
+impl<'a, B, E, T> Pages<'a, B, E>
+where
+    B: HttpBackend,
+    E: ApiEndpoint<Payload = NoPayload, Query = Pagination, Response = Vec<T>>,
+    E::Error: Into<ApiError>,
+{
+    pub(crate) fn new(client: &'a Client<B>, url: E::Url) -> Self {
+        Pages {
+            client,
+            url,
+            next_page: 1,
+            page_size: None,
+            done: false,
+        }
+    }
+
+    /// Defaults to the server's page size.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Returns `None` once there are no more pages. Errors end the iteration.
+    pub async fn next_page(&mut self) -> Option<Result<Vec<T>>> {
+        if self.done {
+            return None;
+        }
+
+        let query = Pagination {
+            page: Some(self.next_page),
+            page_size: self.page_size,
+        };
+        let result = self.client.call::<E>(&self.url, &query, &NoPayload).await;
+
+        match result {
+            Ok(items) if items.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(items) => {
+                self.next_page += 1;
+                Some(Ok(items))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+
+    /// Fetches all the remaining pages.
+    pub async fn collect(mut self) -> Result<Vec<T>> {
+        let mut all = Vec::new();
+        while let Some(items) = self.next_page().await {
+            all.extend(items?);
+        }
+        Ok(all)
+    }
+}
@@ -0,0 +1,111 @@
+use http_types::{Method, StatusCode};
+use std::time::Duration;
+
+/// How failed requests are retried.
+///
+/// Only requests with idempotent methods are retried, since a `POST` that timed out might still
+/// have gone through. Connection errors, `429 Too Many Requests`, and `502`-`504` are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Doubled after every attempt unless the server sends `Retry-After`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// How long to wait before retrying, or `None` if the request shouldn't be retried.
+    pub(crate) fn delay(
+        &self,
+        method: Method,
+        attempt: u32,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries || !is_idempotent(method) {
+            return None;
+        }
+
+        let backoff = retry_after
+            .unwrap_or_else(|| self.initial_backoff * 2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        Some(backoff)
+    }
+}
+
+pub(crate) fn should_retry(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TooManyRequests
+            | StatusCode::BadGateway
+            | StatusCode::ServiceUnavailable
+            | StatusCode::GatewayTimeout
+    )
+}
+
+fn is_idempotent(method: Method) -> bool {
+    matches!(
+        method,
+        Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options
+    )
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+
+        assert_eq!(
+            policy.delay(Method::Get, 0, None),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.delay(Method::Get, 1, None),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.delay(Method::Get, 2, None),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(policy.delay(Method::Get, 3, None), None);
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(Method::Get, 0, Some(Duration::from_secs(1))),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn doesnt_retry_posts() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(Method::Post, 0, None), None);
+    }
+}